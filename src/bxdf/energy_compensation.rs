@@ -0,0 +1,361 @@
+use crate::core::{color::Color, coord::Coordinate, rng::Rng};
+
+use super::{
+    util, Bxdf, BxdfDirType, BxdfInputs, BxdfT, DielectricFresnel, GgxMicrofacet,
+    MicrofacetConductor, MicrofacetDielectric, SchlickFresnel,
+};
+
+/// resolution of `cos(theta_o)` and alpha in albedo tables
+const TABLE_RES: usize = 32;
+/// resolution of ior in albedo tables of dielectric, covering [1, 3]
+const IOR_RES: usize = 16;
+const IOR_MAX: f32 = 3.0;
+const CONDUCTOR_SAMPLES: usize = 1024;
+const DIELECTRIC_SAMPLES: usize = 128;
+
+/// directional albedo E(mu, alpha) and its cosine weighted average over mu,
+/// estimated by sampling the single scattering bsdf
+struct AlbedoTable {
+    albedo: Vec<f32>,
+    average: Vec<f32>,
+}
+
+impl AlbedoTable {
+    fn new<F: Fn(f32) -> Bxdf>(
+        bxdf_of_alpha: F,
+        leaving: bool,
+        samples: usize,
+        rng: &mut Rng,
+    ) -> Self {
+        let mut albedo = Vec::with_capacity(TABLE_RES * TABLE_RES);
+        let mut average = Vec::with_capacity(TABLE_RES);
+        for alpha_ind in 0..TABLE_RES {
+            let bxdf = bxdf_of_alpha(table_value(alpha_ind));
+            let mut sum = 0.0;
+            for mu_ind in 0..TABLE_RES {
+                let mu = table_value(mu_ind);
+                let wo = glam::Vec3A::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let wo = if leaving { -wo } else { wo };
+                let value = directional_albedo(&bxdf, wo, samples, rng);
+                albedo.push(value);
+                sum += value * mu;
+            }
+            average.push(2.0 * sum / TABLE_RES as f32);
+        }
+        Self { albedo, average }
+    }
+
+    fn albedo(&self, alpha: f32, mu: f32) -> f32 {
+        let (a0, a1, at) = table_coord(alpha);
+        let (m0, m1, mt) = table_coord(mu);
+        let value = |a: usize, m: usize| self.albedo[a * TABLE_RES + m];
+        let v0 = value(a0, m0) * (1.0 - mt) + value(a0, m1) * mt;
+        let v1 = value(a1, m0) * (1.0 - mt) + value(a1, m1) * mt;
+        v0 * (1.0 - at) + v1 * at
+    }
+
+    fn average(&self, alpha: f32) -> f32 {
+        let (a0, a1, at) = table_coord(alpha);
+        self.average[a0] * (1.0 - at) + self.average[a1] * at
+    }
+}
+
+lazy_static! {
+    static ref CONDUCTOR_TABLE: AlbedoTable = AlbedoTable::new(
+        |alpha| {
+            MicrofacetConductor::new(
+                GgxMicrofacet::new(alpha, alpha).into(),
+                SchlickFresnel::new(Color::WHITE).into(),
+            )
+            .into()
+        },
+        false,
+        CONDUCTOR_SAMPLES,
+        &mut Rng::with_seed(0),
+    );
+    /// tables of entering and leaving the surface for each ior
+    static ref DIELECTRIC_TABLES: Vec<[AlbedoTable; 2]> = crossbeam::scope(|scope| {
+        let handles: Vec<_> = (0..IOR_RES)
+            .map(|ior_ind| {
+                scope.spawn(move |_| {
+                    let ior = 1.0 + (ior_ind as f32 + 0.5) / IOR_RES as f32 * (IOR_MAX - 1.0);
+                    let bxdf_of_alpha = |alpha| {
+                        MicrofacetDielectric::new(
+                            GgxMicrofacet::new(alpha, alpha).into(),
+                            DielectricFresnel::new(ior).into(),
+                        )
+                        .into()
+                    };
+                    let mut rng = Rng::with_seed(ior_ind as u64);
+                    [
+                        AlbedoTable::new(bxdf_of_alpha, false, DIELECTRIC_SAMPLES, &mut rng),
+                        AlbedoTable::new(bxdf_of_alpha, true, DIELECTRIC_SAMPLES, &mut rng),
+                    ]
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+    .unwrap();
+}
+
+fn table_value(ind: usize) -> f32 {
+    (ind as f32 + 0.5) / TABLE_RES as f32
+}
+
+fn table_coord(value: f32) -> (usize, usize, f32) {
+    let x = (value * TABLE_RES as f32 - 0.5).clamp(0.0, (TABLE_RES - 1) as f32);
+    let ind = (x as usize).min(TABLE_RES - 2);
+    (ind, ind + 1, x - ind as f32)
+}
+
+fn directional_albedo(bxdf: &Bxdf, wo: glam::Vec3A, samples: usize, rng: &mut Rng) -> f32 {
+    let inputs = BxdfInputs {
+        po: glam::Vec3A::ZERO,
+        po_error: glam::Vec3A::ZERO,
+        geo_normal_po: glam::Vec3A::Z,
+        coord_po: Coordinate::from_z(glam::Vec3A::Z, glam::Vec3A::Z),
+        wo,
+        scene: None,
+        instance: None,
+    };
+    let mut sum = 0.0;
+    for _ in 0..samples {
+        let samp = bxdf.sample(&inputs, rng);
+        let reflect = wo.z * samp.wi.z > 0.0;
+        if samp.wi.z != 0.0 && samp.pdf > 0.0 && reflect == (samp.ty.dir == BxdfDirType::Reflect) {
+            sum += (samp.bxdf * samp.wi.z.abs() / samp.pdf).luminance();
+        }
+    }
+    (sum / samples as f32).clamp(0.0, 1.0)
+}
+
+fn sample_cosine(same_side: bool, wo: glam::Vec3A, rng: &mut Rng) -> glam::Vec3A {
+    let mut wi = rng.cosine_weighted_on_hemisphere();
+    if (wo.z < 0.0) == same_side {
+        wi.z = -wi.z;
+    }
+    wi
+}
+
+/// Kulla-Conty multiple scattering lobe for ggx reflection
+#[derive(Clone, Copy)]
+pub struct ConductorCompensation {
+    alpha: f32,
+    albedo_avg: f32,
+    fresnel_avg: Color,
+    fresnel_ms: Color,
+}
+
+impl ConductorCompensation {
+    /// `fresnel_avg` is the cosine weighted average of fresnel reflectance
+    pub fn new(alpha: f32, fresnel_avg: Color) -> Self {
+        let albedo_avg = CONDUCTOR_TABLE.average(alpha);
+        let fresnel_ms = fresnel_avg * fresnel_avg * albedo_avg
+            / (Color::WHITE - fresnel_avg * (1.0 - albedo_avg));
+        Self {
+            alpha,
+            albedo_avg,
+            fresnel_avg,
+            fresnel_ms,
+        }
+    }
+
+    /// probability of sampling this lobe instead of the single scattering one
+    pub fn sample_prob(&self, wo: glam::Vec3A) -> f32 {
+        let albedo = CONDUCTOR_TABLE.albedo(self.alpha, wo.z.abs());
+        let ms = self.fresnel_ms.luminance() * (1.0 - albedo);
+        let ss = self.fresnel_avg.luminance() * albedo;
+        if ms + ss > 0.0 {
+            ms / (ms + ss)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn sample_wi(&self, wo: glam::Vec3A, rng: &mut Rng) -> glam::Vec3A {
+        sample_cosine(true, wo, rng)
+    }
+
+    /// directional albedo of this lobe
+    pub fn albedo(&self, wo: glam::Vec3A) -> Color {
+        self.fresnel_ms * (1.0 - CONDUCTOR_TABLE.albedo(self.alpha, wo.z.abs()))
+    }
+
+    pub fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        if wo.z * wi.z >= 0.0 {
+            wi.z.abs() * std::f32::consts::FRAC_1_PI
+        } else {
+            0.0
+        }
+    }
+
+    pub fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        if wo.z * wi.z >= 0.0 {
+            let albedo_o = CONDUCTOR_TABLE.albedo(self.alpha, wo.z.abs());
+            let albedo_i = CONDUCTOR_TABLE.albedo(self.alpha, wi.z.abs());
+            self.fresnel_ms * (1.0 - albedo_o) * (1.0 - albedo_i)
+                / (std::f32::consts::PI * (1.0 - self.albedo_avg).max(0.0001))
+        } else {
+            Color::BLACK
+        }
+    }
+}
+
+/// Kulla-Conty multiple scattering lobes for ggx dielectric,
+/// missing energy is split into reflection and transmission by average fresnel
+pub struct DielectricCompensation {
+    alpha: f32,
+    /// not less than 1
+    ior: f32,
+    /// `true` if the ior is inverted, so that entering and leaving are swapped
+    inverted: bool,
+}
+
+impl DielectricCompensation {
+    pub fn new(alpha: f32, ior: f32) -> Self {
+        if ior >= 1.0 {
+            Self {
+                alpha,
+                ior,
+                inverted: false,
+            }
+        } else {
+            Self {
+                alpha,
+                ior: 1.0 / ior,
+                inverted: true,
+            }
+        }
+    }
+
+    fn leaving(&self, w: glam::Vec3A) -> bool {
+        (w.z < 0.0) != self.inverted
+    }
+
+    fn lookup<F: Fn(&AlbedoTable) -> f32>(&self, leaving: bool, f: F) -> f32 {
+        let x = ((self.ior - 1.0) / (IOR_MAX - 1.0) * IOR_RES as f32 - 0.5)
+            .clamp(0.0, (IOR_RES - 1) as f32);
+        let ind = (x as usize).min(IOR_RES - 2);
+        let t = x - ind as f32;
+        let side = leaving as usize;
+        f(&DIELECTRIC_TABLES[ind][side]) * (1.0 - t) + f(&DIELECTRIC_TABLES[ind + 1][side]) * t
+    }
+
+    fn albedo(&self, w: glam::Vec3A) -> f32 {
+        self.lookup(self.leaving(w), |table| table.albedo(self.alpha, w.z.abs()))
+    }
+
+    fn average(&self, leaving: bool) -> f32 {
+        self.lookup(leaving, |table| table.average(self.alpha))
+    }
+
+    fn reflect_ratio(&self, wo: glam::Vec3A) -> f32 {
+        let eta = if self.leaving(wo) {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+        (2.0 * util::fresnel_moment1(eta)).clamp(0.0, 1.0)
+    }
+
+    /// probability of sampling these lobes instead of the single scattering ones
+    pub fn sample_prob(&self, wo: glam::Vec3A) -> f32 {
+        1.0 - self.albedo(wo)
+    }
+
+    pub fn sample_wi(&self, wo: glam::Vec3A, rng: &mut Rng) -> glam::Vec3A {
+        let reflect = rng.uniform_1d() < self.reflect_ratio(wo);
+        sample_cosine(reflect, wo, rng)
+    }
+
+    pub fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let reflect_ratio = self.reflect_ratio(wo);
+        let ratio = if wo.z * wi.z >= 0.0 {
+            reflect_ratio
+        } else {
+            1.0 - reflect_ratio
+        };
+        ratio * wi.z.abs() * std::f32::consts::FRAC_1_PI
+    }
+
+    pub fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let reflect_ratio = self.reflect_ratio(wo);
+        let (ratio, leaving_i) = if wo.z * wi.z >= 0.0 {
+            (reflect_ratio, self.leaving(wo))
+        } else {
+            (1.0 - reflect_ratio, !self.leaving(wo))
+        };
+        let value = ratio * (1.0 - self.albedo(wo)) * (1.0 - self.albedo(wi))
+            / (std::f32::consts::PI * (1.0 - self.average(leaving_i)).max(0.0001));
+        Color::gray(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bxdf::ConductorFresnel;
+
+    /// cosine weighted average of directional albedo of a white ggx conductor
+    fn furnace_albedo(alpha: f32, compensation: bool) -> f32 {
+        let microfacet = MicrofacetConductor::new(
+            GgxMicrofacet::new(alpha, alpha).into(),
+            ConductorFresnel::new(Color::WHITE, Color::gray(1000.0)).into(),
+        );
+        let bxdf: Bxdf = if compensation {
+            microfacet.with_energy_compensation().into()
+        } else {
+            microfacet.into()
+        };
+
+        const MU_STEPS: usize = 16;
+        const SAMPLES: usize = 20000;
+        let mut rng = Rng::with_seed(1);
+        let mut average = 0.0;
+        for mu_ind in 0..MU_STEPS {
+            let mu = (mu_ind as f32 + 0.5) / MU_STEPS as f32;
+            let inputs = BxdfInputs {
+                po: glam::Vec3A::ZERO,
+                po_error: glam::Vec3A::ZERO,
+                geo_normal_po: glam::Vec3A::Z,
+                coord_po: Coordinate::from_z(glam::Vec3A::Z, glam::Vec3A::Z),
+                wo: glam::Vec3A::new((1.0 - mu * mu).sqrt(), 0.0, mu),
+                scene: None,
+                instance: None,
+            };
+            let mut sum = 0.0;
+            for _ in 0..SAMPLES {
+                let samp = bxdf.sample(&inputs, &mut rng);
+                if samp.wi.z > 0.0 && samp.pdf > 0.0 {
+                    sum += (samp.bxdf * samp.wi.z.abs() / samp.pdf).luminance();
+                }
+            }
+            average += 2.0 * mu * sum / SAMPLES as f32;
+        }
+        average / MU_STEPS as f32
+    }
+
+    #[test]
+    fn conductor_furnace() {
+        for &alpha in &[0.5, 1.0] {
+            let compensated = furnace_albedo(alpha, true);
+            let single = furnace_albedo(alpha, false);
+            assert!(
+                (compensated - 1.0).abs() < 0.03,
+                "compensated albedo {} at alpha {}",
+                compensated,
+                alpha
+            );
+            assert!(
+                single < 1.0 && single < compensated,
+                "single scattering albedo {} at alpha {}",
+                single,
+                alpha
+            );
+        }
+    }
+}
@@ -7,6 +7,9 @@ pub trait FresnelT {
     fn fresnel(&self, i: glam::Vec3A, n: glam::Vec3A) -> Color;
 
     fn ior(&self) -> f32;
+
+    /// cosine weighted average over the hemisphere, `2 * integral(F(mu) * mu)`
+    fn average(&self) -> Color;
 }
 
 #[enum_dispatch::enum_dispatch]
@@ -14,6 +17,7 @@ pub enum Fresnel {
     DielectricFresnel,
     ConductorFresnel,
     SchlickFresnel,
+    ThinFilmFresnel,
 }
 
 pub struct DielectricFresnel {
@@ -34,6 +38,10 @@ impl FresnelT for DielectricFresnel {
     fn ior(&self) -> f32 {
         self.ior
     }
+
+    fn average(&self) -> Color {
+        Color::gray(2.0 * util::fresnel_moment1(1.0 / self.ior))
+    }
 }
 
 pub struct ConductorFresnel {
@@ -56,6 +64,10 @@ impl FresnelT for ConductorFresnel {
         // unreachable
         1.0
     }
+
+    fn average(&self) -> Color {
+        numerical_average(self)
+    }
 }
 
 pub struct SchlickFresnel {
@@ -77,4 +89,98 @@ impl FresnelT for SchlickFresnel {
         let sqrt_r0 = self.r0.luminance().sqrt();
         (1.0 - sqrt_r0) / (1.0 + sqrt_r0)
     }
+
+    fn average(&self) -> Color {
+        self.r0 + (Color::WHITE - self.r0) / 21.0
+    }
+}
+
+/// a thin film over a dielectric or conductor base, which gives iridescence by interference,
+/// evaluated by Belcour and Barla 2017
+pub struct ThinFilmFresnel {
+    eta: Color,
+    k: Color,
+    /// ior of the film
+    film_ior: f32,
+    /// thickness of the film in nm
+    thickness: f32,
+    /// blend weight between the base without film and with film
+    weight: f32,
+}
+
+impl ThinFilmFresnel {
+    pub fn dielectric(ior: f32, film_ior: f32, thickness: f32) -> Self {
+        Self {
+            eta: Color::gray(ior),
+            k: Color::BLACK,
+            film_ior,
+            thickness,
+            weight: 1.0,
+        }
+    }
+
+    pub fn conductor(eta: Color, k: Color, film_ior: f32, thickness: f32) -> Self {
+        Self {
+            eta,
+            k,
+            film_ior,
+            thickness,
+            weight: 1.0,
+        }
+    }
+
+    /// base of dielectrics with reflectance `r0` at normal incidence in each channel
+    pub fn schlick(r0: Color, film_ior: f32, thickness: f32) -> Self {
+        let ior = |r0: f32| {
+            let sqrt_r0 = r0.clamp(0.0, 0.9999).sqrt();
+            (1.0 + sqrt_r0) / (1.0 - sqrt_r0)
+        };
+        Self {
+            eta: Color::new(ior(r0.r), ior(r0.g), ior(r0.b)),
+            k: Color::BLACK,
+            film_ior,
+            thickness,
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl FresnelT for ThinFilmFresnel {
+    fn fresnel(&self, i: glam::Vec3A, n: glam::Vec3A) -> Color {
+        let film = if self.weight > 0.0 {
+            util::fresnel_thin_film_n(self.eta, self.k, self.film_ior, self.thickness, i, n)
+        } else {
+            Color::BLACK
+        };
+        if self.weight < 1.0 {
+            let base = util::fresnel_thin_film_n(self.eta, self.k, 1.0, 0.0, i, n);
+            base * (1.0 - self.weight) + film * self.weight
+        } else {
+            film
+        }
+    }
+
+    fn ior(&self) -> f32 {
+        self.eta.luminance()
+    }
+
+    fn average(&self) -> Color {
+        numerical_average(self)
+    }
+}
+
+fn numerical_average<F: FresnelT>(fresnel: &F) -> Color {
+    const STEPS: usize = 32;
+    let mut sum = Color::BLACK;
+    for i in 0..STEPS {
+        let mu = (i as f32 + 0.5) / STEPS as f32;
+        let i = glam::Vec3A::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+        sum += fresnel.fresnel(i, glam::Vec3A::Z) * mu;
+    }
+    sum * 2.0 / STEPS as f32
 }
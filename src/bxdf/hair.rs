@@ -0,0 +1,368 @@
+use crate::core::{color::Color, rng::Rng};
+
+use super::{util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT};
+
+/// number of explicit lobes R, TT and TRT, the residual lobe is the last one
+const P_MAX: usize = 3;
+
+/// parameters of the hair bcsdf at a shading point
+pub struct HairParams {
+    /// offset across the fiber in [-1, 1]
+    pub h: f32,
+    pub ior: f32,
+    /// absorption coefficient inside the fiber, relative to its radius
+    pub sigma_a: Color,
+    /// longitudinal roughness in [0, 1]
+    pub beta_m: f32,
+    /// azimuthal roughness in [0, 1]
+    pub beta_n: f32,
+    /// tilt of cuticle scales in degrees
+    pub alpha: f32,
+}
+
+/// hair scattering model of Chiang et al. 2016, following the implementation of pbrt-v3,
+/// in local coordinate x is along the fiber and z is the normal of the ribbon,
+/// all lobes of the fiber are evaluated at a single hit, so it shouldn't be a closed tube
+pub struct Hair {
+    h: f32,
+    gamma_o: f32,
+    ior: f32,
+    sigma_a: Color,
+    /// longitudinal variances of each lobe
+    v: [f32; P_MAX + 1],
+    /// logistic scale of azimuthal distributions
+    s: f32,
+    sin_2k_alpha: [f32; P_MAX],
+    cos_2k_alpha: [f32; P_MAX],
+}
+
+impl Hair {
+    pub fn new(params: &HairParams) -> Self {
+        let h = params.h.clamp(-1.0, 1.0);
+        let beta_m = params.beta_m.clamp(0.001, 1.0);
+        let beta_n = params.beta_n.clamp(0.001, 1.0);
+
+        let v0 = pow2(0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20));
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s =
+            SQRT_PI_OVER_8 * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [0.0; P_MAX];
+        let mut cos_2k_alpha = [0.0; P_MAX];
+        sin_2k_alpha[0] = params.alpha.to_radians().sin();
+        cos_2k_alpha[0] = safe_sqrt(1.0 - pow2(sin_2k_alpha[0]));
+        for i in 1..P_MAX {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = pow2(cos_2k_alpha[i - 1]) - pow2(sin_2k_alpha[i - 1]);
+        }
+
+        Self {
+            h,
+            gamma_o: safe_asin(h),
+            ior: params.ior,
+            sigma_a: params.sigma_a,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// absorption coefficient of given concentrations of eumelanin and pheomelanin
+    pub fn sigma_a_from_melanin(eumelanin: f32, pheomelanin: f32) -> Color {
+        eumelanin * Color::new(0.419, 0.697, 1.37) + pheomelanin * Color::new(0.187, 0.4, 1.05)
+    }
+
+    /// absorption coefficient giving approximately `color` as the multiple scattering albedo
+    pub fn sigma_a_from_color(color: Color, beta_n: f32) -> Color {
+        let denom = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let sigma_a = |value: f32| pow2(value.max(0.0001).ln() / denom);
+        Color::new(sigma_a(color.r), sigma_a(color.g), sigma_a(color.b))
+    }
+
+    /// `(sin, cos)` of theta_o tilted by scales for lobe `p`
+    fn tilt(&self, p: usize, sin_theta_o: f32, cos_theta_o: f32) -> (f32, f32) {
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_op, cos_theta_op.abs())
+    }
+
+    /// attenuations of all lobes and gamma_t for outgoing direction with `sin_theta_o`
+    fn attenuation(&self, sin_theta_o: f32) -> ([Color; P_MAX + 1], f32) {
+        let cos_theta_o = safe_sqrt(1.0 - pow2(sin_theta_o));
+        let sin_theta_t = sin_theta_o / self.ior;
+        let cos_theta_t = safe_sqrt(1.0 - pow2(sin_theta_t));
+        let etap =
+            (self.ior * self.ior - pow2(sin_theta_o)).max(0.0).sqrt() / cos_theta_o.max(0.0001);
+        let sin_gamma_t = self.h / etap;
+        let cos_gamma_t = safe_sqrt(1.0 - pow2(sin_gamma_t));
+        let gamma_t = safe_asin(sin_gamma_t);
+
+        let transmittance = (-self.sigma_a * (2.0 * cos_gamma_t / cos_theta_t.max(0.0001))).exp();
+
+        let cos_gamma_o = safe_sqrt(1.0 - self.h * self.h);
+        let cos_theta = cos_theta_o * cos_gamma_o;
+        let f = util::fresnel(
+            self.ior,
+            glam::Vec3A::new(safe_sqrt(1.0 - cos_theta * cos_theta), 0.0, cos_theta),
+        );
+
+        let mut ap = [Color::BLACK; P_MAX + 1];
+        ap[0] = Color::gray(f);
+        ap[1] = pow2(1.0 - f) * transmittance;
+        for p in 2..P_MAX {
+            ap[p] = ap[p - 1] * transmittance * f;
+        }
+        ap[P_MAX] = ap[P_MAX - 1] * f * transmittance / (Color::WHITE - transmittance * f);
+
+        (ap, gamma_t)
+    }
+
+    /// probabilities of choosing each lobe in sampling
+    fn lobe_pdfs(ap: &[Color; P_MAX + 1]) -> [f32; P_MAX + 1] {
+        let sum: f32 = ap.iter().map(|a| a.luminance()).sum();
+        let mut pdfs = [0.0; P_MAX + 1];
+        if sum > 0.0 {
+            for p in 0..=P_MAX {
+                pdfs[p] = ap[p].luminance() / sum;
+            }
+        }
+        pdfs
+    }
+
+    /// sum of `weights[p] * M_p * N_p` over all lobes
+    fn sum_lobes<T, F>(&self, wo: glam::Vec3A, wi: glam::Vec3A, gamma_t: f32, weight: F) -> T
+    where
+        T: std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T> + Default,
+        F: Fn(usize) -> T,
+    {
+        let sin_theta_o = wo.x;
+        let cos_theta_o = safe_sqrt(1.0 - pow2(sin_theta_o));
+        let phi_o = wo.z.atan2(wo.y);
+        let sin_theta_i = wi.x;
+        let cos_theta_i = safe_sqrt(1.0 - pow2(sin_theta_i));
+        let phi_i = wi.z.atan2(wi.y);
+        let phi = phi_i - phi_o;
+
+        let mut sum = T::default();
+        for p in 0..P_MAX {
+            let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+            let mp = longitudinal(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            );
+            let np = azimuthal(phi, p, self.s, self.gamma_o, gamma_t);
+            sum = sum + weight(p) * (mp * np);
+        }
+        let mp = longitudinal(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        );
+        sum + weight(P_MAX) * (mp * 0.5 * std::f32::consts::FRAC_1_PI)
+    }
+}
+
+impl BxdfT for Hair {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let wo = inputs.wo;
+        let sin_theta_o = wo.x;
+        let cos_theta_o = safe_sqrt(1.0 - pow2(sin_theta_o));
+        let phi_o = wo.z.atan2(wo.y);
+
+        let (ap, gamma_t) = self.attenuation(sin_theta_o);
+        let lobe_pdfs = Self::lobe_pdfs(&ap);
+        let mut rand = rng.uniform_1d();
+        let mut p = P_MAX;
+        for (ind, lobe_pdf) in lobe_pdfs.iter().enumerate() {
+            if rand < *lobe_pdf {
+                p = ind;
+                break;
+            }
+            rand -= lobe_pdf;
+        }
+
+        // sample longitudinal scattering
+        let (sin_theta_op, cos_theta_op) = self.tilt(p, sin_theta_o, cos_theta_o);
+        let (rand_m, rand_phi) = rng.uniform_2d();
+        let rand_m = rand_m.max(0.00001);
+        let v = self.v[p];
+        let cos_theta = 1.0 + v * (rand_m + (1.0 - rand_m) * (-2.0 / v).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - pow2(cos_theta));
+        let cos_phi = (2.0 * std::f32::consts::PI * rand_phi).cos();
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - pow2(sin_theta_i));
+
+        // sample azimuthal scattering
+        let rand_n = rng.uniform_1d();
+        let dphi = if p < P_MAX {
+            phi_of_lobe(p, self.gamma_o, gamma_t)
+                + sample_trimmed_logistic(
+                    rand_n,
+                    self.s,
+                    -std::f32::consts::PI,
+                    std::f32::consts::PI,
+                )
+        } else {
+            2.0 * std::f32::consts::PI * rand_n
+        };
+        let phi_i = phi_o + dphi;
+        let wi = glam::Vec3A::new(
+            sin_theta_i,
+            cos_theta_i * phi_i.cos(),
+            cos_theta_i * phi_i.sin(),
+        );
+
+        let pdf = self.sum_lobes(wo, wi, gamma_t, |p| lobe_pdfs[p]);
+        let bxdf = self.bxdf(wo, wi);
+
+        BxdfSample {
+            wi,
+            ty: BxdfSampleType {
+                lobe: BxdfLobeType::Glossy,
+                dir: if wo.z * wi.z >= 0.0 {
+                    BxdfDirType::Reflect
+                } else {
+                    BxdfDirType::Transmit
+                },
+                subsurface: false,
+            },
+            bxdf,
+            pdf,
+            subsurface: None,
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let (ap, gamma_t) = self.attenuation(wo.x);
+        let lobe_pdfs = Self::lobe_pdfs(&ap);
+        self.sum_lobes(wo, wi, gamma_t, |p| lobe_pdfs[p])
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let (ap, gamma_t) = self.attenuation(wo.x);
+        let bxdf = self.sum_lobes(wo, wi, gamma_t, |p| ap[p]);
+        // the model is defined with respect to the cosine of the fiber, not the normal
+        if wi.z != 0.0 {
+            bxdf / wi.z.abs()
+        } else {
+            bxdf
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+const SQRT_PI_OVER_8: f32 = 0.626_657_07;
+
+fn pow2(x: f32) -> f32 {
+    x * x
+}
+
+fn safe_sqrt(x: f32) -> f32 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f32) -> f32 {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+fn bessel_i0(x: f32) -> f32 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut ifact = 1.0;
+    let mut i4 = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= i as f32;
+        }
+        value += x2i / (i4 * ifact * ifact);
+        x2i *= x * x;
+        i4 *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: f32) -> f32 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * std::f32::consts::PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+/// longitudinal scattering function `M_p`
+fn longitudinal(
+    cos_theta_i: f32,
+    cos_theta_o: f32,
+    sin_theta_i: f32,
+    sin_theta_o: f32,
+    v: f32,
+) -> f32 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_bessel_i0(a) - b - 1.0 / v + std::f32::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        (-b).exp() * bessel_i0(a) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// azimuthal scattering function `N_p`
+fn azimuthal(phi: f32, p: usize, s: f32, gamma_o: f32, gamma_t: f32) -> f32 {
+    let mut dphi = phi - phi_of_lobe(p, gamma_o, gamma_t);
+    while dphi > std::f32::consts::PI {
+        dphi -= 2.0 * std::f32::consts::PI;
+    }
+    while dphi < -std::f32::consts::PI {
+        dphi += 2.0 * std::f32::consts::PI;
+    }
+    trimmed_logistic(dphi, s, -std::f32::consts::PI, std::f32::consts::PI)
+}
+
+/// net azimuthal deflection of lobe `p`
+fn phi_of_lobe(p: usize, gamma_o: f32, gamma_t: f32) -> f32 {
+    2.0 * p as f32 * gamma_t - 2.0 * gamma_o + p as f32 * std::f32::consts::PI
+}
+
+fn logistic(x: f32, s: f32) -> f32 {
+    let x = x.abs();
+    let e = (-x / s).exp();
+    e / (s * pow2(1.0 + e))
+}
+
+fn logistic_cdf(x: f32, s: f32) -> f32 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f32, s: f32, a: f32, b: f32) -> f32 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(rand: f32, s: f32, a: f32, b: f32) -> f32 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (rand * k + logistic_cdf(a, s)) - 1.0).ln();
+    x.clamp(a, b)
+}
@@ -0,0 +1,345 @@
+use crate::core::{color::Color, coord::Coordinate, rng::Rng};
+
+use super::{Bxdf, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT};
+
+/// a dielectric interface `top` over an arbitrary `bottom` bsdf with an absorbing medium between,
+/// evaluated stochastically by random walks between the two interfaces (Guo et al. 2018),
+/// the coating is on both sides of the surface
+pub struct Layered {
+    top: Box<Bxdf>,
+    bottom: Box<Bxdf>,
+    /// relative ior of `top`
+    ior: f32,
+    /// optical depth of the medium at normal incidence
+    absorption: Color,
+    max_depth: u32,
+    samples: u32,
+}
+
+impl Layered {
+    pub fn new(
+        top: Bxdf,
+        bottom: Bxdf,
+        ior: f32,
+        absorption: Color,
+        max_depth: u32,
+        samples: u32,
+    ) -> Self {
+        Self {
+            top: Box::new(top),
+            bottom: Box::new(bottom),
+            ior,
+            absorption,
+            max_depth,
+            samples: samples.max(1),
+        }
+    }
+
+    fn transmittance(&self, w: glam::Vec3A) -> Color {
+        (-self.absorption / w.z.abs().max(0.0001)).exp()
+    }
+
+    /// `wo` should be in the upper hemisphere
+    fn bxdf_estimate(&self, wo: glam::Vec3A, wi: glam::Vec3A, rng: &mut Rng) -> Color {
+        let same_hemisphere = wi.z > 0.0;
+        let (exit, non_exit) = if same_hemisphere {
+            (self.top.as_ref(), self.bottom.as_ref())
+        } else {
+            (self.bottom.as_ref(), self.top.as_ref())
+        };
+
+        let mut bxdf = if same_hemisphere && !self.top.is_delta() {
+            self.samples as f32 * self.top.bxdf(wo, wi)
+        } else {
+            Color::BLACK
+        };
+
+        for _ in 0..self.samples {
+            let wos = match sample_interface(&self.top, wo, Some(BxdfDirType::Transmit), rng) {
+                Some(samp) => samp,
+                None => continue,
+            };
+            let wis = match sample_interface(exit, wi, Some(BxdfDirType::Transmit), rng) {
+                Some(samp) => samp,
+                None => continue,
+            };
+            // light enters at `wi`, so the adjoint bsdf of `exit` is needed
+            let wis_bxdf = if !exit.is_delta() {
+                exit.bxdf(wis.wi, wi)
+            } else if same_hemisphere {
+                wis.bxdf * self.ior * self.ior
+            } else {
+                // ior of a smooth transmissive base is unknown, keep its radiance scaling
+                wis.bxdf
+            };
+
+            let mut beta = wos.bxdf * wos.wi.z.abs() / wos.pdf;
+            let mut w = wos.wi;
+            let mut at_bottom = false;
+            for depth in 0..self.max_depth {
+                if depth > 3 && !russian_roulette(&mut beta, rng) {
+                    break;
+                }
+
+                at_bottom = !at_bottom;
+                beta *= self.transmittance(w);
+
+                if at_bottom != same_hemisphere {
+                    let samp = match sample_interface(exit, -w, Some(BxdfDirType::Reflect), rng) {
+                        Some(samp) => samp,
+                        None => break,
+                    };
+                    beta *= samp.bxdf * samp.wi.z.abs() / samp.pdf;
+                    w = samp.wi;
+                } else {
+                    if !non_exit.is_delta() {
+                        let weight = if exit.is_delta() {
+                            1.0
+                        } else {
+                            power_heuristic(wis.pdf, non_exit.pdf(-w, -wis.wi))
+                        };
+                        bxdf += beta
+                            * non_exit.bxdf(-w, -wis.wi)
+                            * wis.wi.z.abs()
+                            * weight
+                            * self.transmittance(wis.wi)
+                            * wis_bxdf
+                            / wis.pdf;
+                    }
+
+                    let samp = match sample_interface(non_exit, -w, Some(BxdfDirType::Reflect), rng)
+                    {
+                        Some(samp) => samp,
+                        None => break,
+                    };
+                    beta *= samp.bxdf * samp.wi.z.abs() / samp.pdf;
+                    w = samp.wi;
+
+                    if !exit.is_delta() {
+                        let exit_bxdf = exit.bxdf(-w, wi);
+                        if !is_black(exit_bxdf) {
+                            let weight = if non_exit.is_delta() {
+                                1.0
+                            } else {
+                                power_heuristic(samp.pdf, exit.pdf(-w, wi))
+                            };
+                            bxdf += beta * self.transmittance(w) * exit_bxdf * weight;
+                        }
+                    }
+                }
+            }
+        }
+
+        bxdf / self.samples as f32
+    }
+
+    /// `wo` should be in the upper hemisphere
+    fn pdf_estimate(&self, wo: glam::Vec3A, wi: glam::Vec3A, rng: &mut Rng) -> f32 {
+        let same_hemisphere = wi.z > 0.0;
+
+        let mut pdf = if same_hemisphere && !self.top.is_delta() {
+            self.samples as f32 * self.top.pdf(wo, wi)
+        } else {
+            0.0
+        };
+
+        for _ in 0..self.samples {
+            let wos = match sample_interface(&self.top, wo, Some(BxdfDirType::Transmit), rng) {
+                Some(samp) => samp,
+                None => continue,
+            };
+            let exit = if same_hemisphere {
+                &self.top
+            } else {
+                &self.bottom
+            };
+            let wis = match sample_interface(exit, wi, Some(BxdfDirType::Transmit), rng) {
+                Some(samp) => samp,
+                None => continue,
+            };
+
+            if same_hemisphere {
+                if self.top.is_delta() {
+                    pdf += self.bottom.pdf(-wos.wi, -wis.wi);
+                } else if let Some(samp) =
+                    sample_interface(&self.bottom, -wos.wi, Some(BxdfDirType::Reflect), rng)
+                {
+                    let top_pdf = self.top.pdf(-samp.wi, wi);
+                    if self.bottom.is_delta() {
+                        pdf += top_pdf;
+                    } else {
+                        let bottom_pdf = self.bottom.pdf(-wos.wi, -wis.wi);
+                        pdf += power_heuristic(wis.pdf, bottom_pdf) * bottom_pdf;
+                        pdf += power_heuristic(samp.pdf, top_pdf) * top_pdf;
+                    }
+                }
+            } else if self.top.is_delta() {
+                pdf += self.bottom.pdf(-wos.wi, wi);
+            } else if self.bottom.is_delta() {
+                pdf += self.top.pdf(wo, -wis.wi);
+            } else {
+                pdf += 0.5 * (self.top.pdf(wo, -wis.wi) + self.bottom.pdf(-wos.wi, wi));
+            }
+        }
+
+        // mix with uniform pdf to cover paths missed by the estimate
+        0.1 * 0.25 * std::f32::consts::FRAC_1_PI + 0.9 * pdf / self.samples as f32
+    }
+}
+
+impl BxdfT for Layered {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let flip = inputs.wo.z < 0.0;
+        let wo = if flip { -inputs.wo } else { inputs.wo };
+        let finish = |wi: glam::Vec3A, bxdf: Color, pdf: f32, lobe: BxdfLobeType| {
+            let wi = if flip { -wi } else { wi };
+            BxdfSample {
+                wi,
+                ty: BxdfSampleType {
+                    lobe,
+                    dir: if inputs.wo.z * wi.z >= 0.0 {
+                        BxdfDirType::Reflect
+                    } else {
+                        BxdfDirType::Transmit
+                    },
+                    subsurface: false,
+                },
+                bxdf,
+                pdf,
+                subsurface: None,
+            }
+        };
+        let invalid = || finish(glam::Vec3A::ZERO, Color::BLACK, 1.0, BxdfLobeType::Glossy);
+
+        let samp = match sample_interface(&self.top, wo, None, rng) {
+            Some(samp) => samp,
+            None => return invalid(),
+        };
+        if samp.wi.z > 0.0 && samp.ty.lobe == BxdfLobeType::Specular {
+            return finish(samp.wi, samp.bxdf, samp.pdf, samp.ty.lobe);
+        }
+
+        let mut beta = samp.bxdf * samp.wi.z.abs() / samp.pdf;
+        let mut w = samp.wi;
+        let mut specular = samp.ty.lobe == BxdfLobeType::Specular;
+        let mut at_bottom = false;
+        let mut depth = 0;
+        while w.z < 0.0 || at_bottom {
+            if depth == self.max_depth || (depth > 3 && !russian_roulette(&mut beta, rng)) {
+                return invalid();
+            }
+            depth += 1;
+
+            at_bottom = !at_bottom;
+            beta *= self.transmittance(w);
+
+            let interface = if at_bottom { &self.bottom } else { &self.top };
+            let samp = match sample_interface(interface, -w, None, rng) {
+                Some(samp) => samp,
+                None => return invalid(),
+            };
+            beta *= samp.bxdf * samp.wi.z.abs() / samp.pdf;
+            specular &= samp.ty.lobe == BxdfLobeType::Specular;
+            w = samp.wi;
+
+            // leaves through the bottom
+            if at_bottom && w.z < 0.0 {
+                break;
+            }
+        }
+
+        if specular {
+            finish(w, beta / w.z.abs(), 1.0, BxdfLobeType::Specular)
+        } else {
+            let pdf = self.pdf_estimate(wo, w, &mut Rng::with_seed(hash_dirs(wo, w)));
+            finish(w, beta * pdf / w.z.abs(), pdf, BxdfLobeType::Glossy)
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let (wo, wi) = if wo.z < 0.0 { (-wo, -wi) } else { (wo, wi) };
+        if wi.z == 0.0 {
+            return 1.0;
+        }
+        self.pdf_estimate(wo, wi, &mut Rng::with_seed(hash_dirs(wo, wi)))
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let (wo, wi) = if wo.z < 0.0 { (-wo, -wi) } else { (wo, wi) };
+        if wi.z == 0.0 {
+            return Color::BLACK;
+        }
+        self.bxdf_estimate(wo, wi, &mut Rng::with_seed(hash_dirs(wo, wi)))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.top.is_delta() && self.bottom.is_delta()
+    }
+}
+
+/// samples one interface, `None` if the sample is invalid or not towards `dir`
+fn sample_interface(
+    bxdf: &Bxdf,
+    wo: glam::Vec3A,
+    dir: Option<BxdfDirType>,
+    rng: &mut Rng,
+) -> Option<BxdfSample> {
+    let inputs = BxdfInputs {
+        po: glam::Vec3A::ZERO,
+        po_error: glam::Vec3A::ZERO,
+        geo_normal_po: glam::Vec3A::Z,
+        coord_po: Coordinate::from_z(glam::Vec3A::Z, glam::Vec3A::Z),
+        wo,
+        scene: None,
+        instance: None,
+    };
+    let samp = bxdf.sample(&inputs, rng);
+    if samp.wi.z == 0.0 || samp.pdf <= 0.0 || is_black(samp.bxdf) {
+        return None;
+    }
+    let sampled_dir = if wo.z * samp.wi.z > 0.0 {
+        BxdfDirType::Reflect
+    } else {
+        BxdfDirType::Transmit
+    };
+    match dir {
+        Some(dir) if dir != sampled_dir => None,
+        _ => Some(samp),
+    }
+}
+
+/// returns `false` if the walk is terminated
+fn russian_roulette(beta: &mut Color, rng: &mut Rng) -> bool {
+    let max = beta.r.max(beta.g).max(beta.b);
+    if max < 0.25 {
+        let q = (1.0 - max).max(0.0);
+        if rng.uniform_1d() < q {
+            return false;
+        }
+        *beta /= 1.0 - q;
+    }
+    true
+}
+
+fn is_black(color: Color) -> bool {
+    color.r <= 0.0 && color.g <= 0.0 && color.b <= 0.0
+}
+
+fn power_heuristic(pdf0: f32, pdf1: f32) -> f32 {
+    let sum = pdf0 * pdf0 + pdf1 * pdf1;
+    if sum > 0.0 {
+        pdf0 * pdf0 / sum
+    } else {
+        0.0
+    }
+}
+
+/// seed of the walks in `bxdf` and `pdf`, so that they are deterministic for the same directions
+fn hash_dirs(wo: glam::Vec3A, wi: glam::Vec3A) -> u64 {
+    let values = [wo.x, wo.y, wo.z, wi.x, wi.y, wi.z];
+    let mut hash = 0xcbf29ce484222325_u64;
+    for value in values.iter() {
+        hash = (hash ^ value.to_bits() as u64).wrapping_mul(0x100000001b3);
+    }
+    hash
+}
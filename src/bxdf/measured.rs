@@ -0,0 +1,561 @@
+use std::{collections::HashMap, convert::TryInto, path::Path, sync::Arc};
+
+use crate::core::{color::Color, marginal_2d::Marginal2D, rng::Rng};
+
+use super::{util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT};
+
+const MERL_RES_THETA_H: usize = 90;
+const MERL_RES_THETA_D: usize = 90;
+const MERL_RES_PHI_D: usize = 180;
+const MERL_SCALE: [f32; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+/// isotropic brdf of the MERL database, tabulated in half and difference angles
+pub struct MerlData {
+    /// values of r, g and b one after another, each of
+    /// `MERL_RES_THETA_H * MERL_RES_THETA_D * MERL_RES_PHI_D`
+    values: Vec<f32>,
+    /// alpha of the ggx lobe used to sample the brdf
+    proxy_alpha: f32,
+    /// probability to sample the ggx lobe rather than the cosine one
+    proxy_specular_prob: f32,
+}
+
+/// tabulated brdf of the RGL database by Dupuy and Jakob 2018, stored in the rgb variant of its
+/// tensor file format, evaluated and sampled in the same way as its reference implementation
+pub struct RglData {
+    ndf: Marginal2D,
+    sigma: Marginal2D,
+    vndf: Marginal2D,
+    luminance: Marginal2D,
+    rgb: Marginal2D,
+    isotropic: bool,
+    /// the data only covers `2 * pi / reduction` of `phi_i`, and is mirrored for other ones
+    reduction: usize,
+}
+
+pub enum MeasuredData {
+    Merl(MerlData),
+    Rgl(Box<RglData>),
+}
+
+/// a measured brdf, only reflection on both sides
+pub struct Measured {
+    data: Arc<MeasuredData>,
+}
+
+impl Measured {
+    pub fn new(data: Arc<MeasuredData>) -> Self {
+        Self { data }
+    }
+}
+
+impl MeasuredData {
+    /// loads a MERL `.binary` file
+    pub fn load_merl(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < 12 {
+            anyhow::bail!("unexpected end of file");
+        }
+        let dims = (0..3)
+            .map(|i| i32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let count = MERL_RES_THETA_H * MERL_RES_THETA_D * MERL_RES_PHI_D;
+        if dims.iter().product::<i32>() as usize != count {
+            anyhow::bail!(format!("unexpected dimensions {:?}", dims));
+        }
+        if bytes.len() < 12 + count * 3 * 8 {
+            anyhow::bail!("unexpected end of file");
+        }
+
+        let values = bytes[12..12 + count * 3 * 8]
+            .chunks_exact(8)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let value = f64::from_le_bytes(chunk.try_into().unwrap()) as f32;
+                // missing values are negative
+                (value * MERL_SCALE[i / count]).max(0.0)
+            })
+            .collect();
+
+        let mut data = MerlData {
+            values,
+            proxy_alpha: 0.5,
+            proxy_specular_prob: 0.5,
+        };
+        data.fit_proxy();
+        Ok(Self::Merl(data))
+    }
+
+    /// loads a RGL `.bsdf` file with the `rgb` field
+    pub fn load_rgl(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut fields = read_tensor_file(&bytes)?;
+        let mut field = |name: &str| {
+            fields
+                .remove(name)
+                .ok_or_else(|| anyhow::anyhow!(format!("no field '{}'", name)))
+        };
+
+        let mut theta_i = field("theta_i")?;
+        let mut phi_i = field("phi_i")?;
+        let ndf = field("ndf")?;
+        let sigma = field("sigma")?;
+        let vndf = field("vndf")?;
+        let luminance = field("luminance")?;
+        let rgb = field("rgb").map_err(|_| {
+            anyhow::anyhow!("no field 'rgb', only rgb tabulated files are supported")
+        })?;
+
+        // angles of some files are in degrees
+        if theta_i
+            .data
+            .iter()
+            .any(|theta| *theta > std::f32::consts::FRAC_PI_2 + 0.01)
+        {
+            theta_i
+                .data
+                .iter_mut()
+                .for_each(|theta| *theta = theta.to_radians());
+            phi_i
+                .data
+                .iter_mut()
+                .for_each(|phi| *phi = phi.to_radians());
+        }
+
+        let n_theta_i = theta_i.data.len();
+        let n_phi_i = phi_i.data.len();
+        if ndf.shape.len() != 2
+            || sigma.shape.len() != 2
+            || vndf.shape[..] != [n_phi_i, n_theta_i, vndf.shape[2], vndf.shape[3]]
+            || luminance.shape[..] != [n_phi_i, n_theta_i, luminance.shape[2], luminance.shape[3]]
+            || rgb.shape[..] != [n_phi_i, n_theta_i, 3, rgb.shape[3], rgb.shape[4]]
+        {
+            anyhow::bail!("unexpected shapes of fields");
+        }
+
+        let isotropic = n_phi_i <= 2;
+        let reduction = if isotropic {
+            1
+        } else {
+            let range = phi_i.data[n_phi_i - 1] - phi_i.data[0];
+            ((2.0 * std::f32::consts::PI / range).round() as usize).max(1)
+        };
+
+        let params = vec![phi_i.data.clone(), theta_i.data.clone()];
+        Ok(Self::Rgl(Box::new(RglData {
+            ndf: Marginal2D::new(ndf.shape[1], ndf.shape[0], &ndf.data, vec![], false, false),
+            sigma: Marginal2D::new(
+                sigma.shape[1],
+                sigma.shape[0],
+                &sigma.data,
+                vec![],
+                false,
+                false,
+            ),
+            vndf: Marginal2D::new(
+                vndf.shape[3],
+                vndf.shape[2],
+                &vndf.data,
+                params.clone(),
+                true,
+                true,
+            ),
+            luminance: Marginal2D::new(
+                luminance.shape[3],
+                luminance.shape[2],
+                &luminance.data,
+                params,
+                true,
+                true,
+            ),
+            rgb: Marginal2D::new(
+                rgb.shape[4],
+                rgb.shape[3],
+                &rgb.data,
+                vec![phi_i.data, theta_i.data, vec![0.0, 1.0, 2.0]],
+                false,
+                false,
+            ),
+            isotropic,
+            reduction,
+        })))
+    }
+}
+
+impl MerlData {
+    fn lookup(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let half = (wo + wi).normalize();
+        let theta_h = half.z.clamp(-1.0, 1.0).acos();
+        let phi_h = half.y.atan2(half.x);
+
+        // rotate `wi` so that the half vector is the normal
+        let (sin_phi, cos_phi) = (-phi_h).sin_cos();
+        let temp = glam::Vec3A::new(
+            wi.x * cos_phi - wi.y * sin_phi,
+            wi.x * sin_phi + wi.y * cos_phi,
+            wi.z,
+        );
+        let (sin_theta, cos_theta) = (-theta_h).sin_cos();
+        let diff = glam::Vec3A::new(
+            temp.x * cos_theta + temp.z * sin_theta,
+            temp.y,
+            temp.z * cos_theta - temp.x * sin_theta,
+        );
+        let theta_d = diff.z.clamp(-1.0, 1.0).acos();
+        let mut phi_d = diff.y.atan2(diff.x);
+        // brdf is the same for `phi_d` and `phi_d + pi` by reciprocity
+        if phi_d < 0.0 {
+            phi_d += std::f32::consts::PI;
+        }
+
+        let theta_h_ind = ((theta_h / std::f32::consts::FRAC_PI_2).max(0.0).sqrt()
+            * MERL_RES_THETA_H as f32) as usize;
+        let theta_d_ind =
+            (theta_d / std::f32::consts::FRAC_PI_2 * MERL_RES_THETA_D as f32) as usize;
+        let phi_d_ind = (phi_d / std::f32::consts::PI * MERL_RES_PHI_D as f32) as usize;
+        let ind = phi_d_ind.min(MERL_RES_PHI_D - 1)
+            + theta_d_ind.min(MERL_RES_THETA_D - 1) * MERL_RES_PHI_D
+            + theta_h_ind.min(MERL_RES_THETA_H - 1) * MERL_RES_PHI_D * MERL_RES_THETA_D;
+
+        let count = MERL_RES_THETA_H * MERL_RES_THETA_D * MERL_RES_PHI_D;
+        Color::new(
+            self.values[ind],
+            self.values[ind + count],
+            self.values[ind + count * 2],
+        )
+    }
+
+    /// fits a ggx lobe over a diffuse one, which are used to sample the brdf
+    fn fit_proxy(&mut self) {
+        // luminance along `theta_h` where `theta_d` and `phi_d` are 0
+        let count = MERL_RES_THETA_H * MERL_RES_THETA_D * MERL_RES_PHI_D;
+        let row = (0..MERL_RES_THETA_H)
+            .map(|i| {
+                let ind = i * MERL_RES_PHI_D * MERL_RES_THETA_D;
+                Color::new(
+                    self.values[ind],
+                    self.values[ind + count],
+                    self.values[ind + count * 2],
+                )
+                .luminance()
+            })
+            .collect::<Vec<_>>();
+        let floor = row.iter().cloned().fold(f32::MAX, f32::min);
+        let peak = row[0] - floor;
+
+        // half width of ggx is where `cos^2 * (a2 - 1) + 1 = sqrt(2) * a2`
+        if let Some(half_ind) = (1..MERL_RES_THETA_H).find(|i| row[*i] - floor < 0.5 * peak) {
+            let u = half_ind as f32 / MERL_RES_THETA_H as f32;
+            let theta = u * u * std::f32::consts::FRAC_PI_2;
+            let cos2 = theta.cos().powi(2);
+            let a2 = (1.0 - cos2) / (std::f32::consts::SQRT_2 - cos2);
+            self.proxy_alpha = a2.sqrt().clamp(0.01, 1.0);
+        }
+
+        // split of albedo at normal incidence between the two lobes
+        const STEPS: usize = 32;
+        let wo = glam::Vec3A::Z;
+        let mut specular = 0.0;
+        for i in 0..STEPS {
+            let cos = ((i as f32 + 0.5) / STEPS as f32).sqrt();
+            let sin = (1.0 - cos * cos).sqrt();
+            for j in 0..STEPS {
+                let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * std::f32::consts::PI;
+                let wi = glam::Vec3A::new(sin * phi.cos(), sin * phi.sin(), cos);
+                specular += (self.lookup(wo, wi).luminance() - floor).max(0.0);
+            }
+        }
+        let specular = specular * std::f32::consts::PI / (STEPS * STEPS) as f32;
+        let diffuse = floor * std::f32::consts::PI;
+        if specular + diffuse > 0.0 {
+            self.proxy_specular_prob = (specular / (specular + diffuse)).clamp(0.1, 0.9);
+        }
+    }
+
+    fn sample(&self, wo: glam::Vec3A, rng: &mut Rng) -> glam::Vec3A {
+        if rng.uniform_1d() < self.proxy_specular_prob {
+            let (half, _) = util::ggx_smith_vndf_sample(
+                wo,
+                self.proxy_alpha,
+                self.proxy_alpha,
+                rng.uniform_2d(),
+            );
+            util::reflect_n(wo, half)
+        } else {
+            rng.cosine_weighted_on_hemisphere()
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let half = util::half_from_reflect(wo, wi);
+        let half_pdf = util::ggx_smith_vndf_pdf(half, wo, self.proxy_alpha, self.proxy_alpha);
+        self.proxy_specular_prob * half_pdf / (4.0 * wo.dot(half).abs()).max(0.0001)
+            + (1.0 - self.proxy_specular_prob) * wi.z * std::f32::consts::FRAC_1_PI
+    }
+}
+
+impl RglData {
+    /// rotates or mirrors `wo` into the range of the data, and `wi` along with it,
+    /// which is `phi_i` in `[-pi, 0]` for reduction 2 and `[-pi, -pi/2]` for reduction 4,
+    /// applying it again with signs of the original `wo` undoes it
+    fn reduce(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> (glam::Vec3A, glam::Vec3A) {
+        let mut wo = wo;
+        let mut wi = wi;
+        if self.reduction >= 2 {
+            let sign_y = wo.y;
+            let sign_x = if self.reduction == 4 { wo.x } else { sign_y };
+            if sign_x >= 0.0 {
+                wo.x = -wo.x;
+                wi.x = -wi.x;
+            }
+            if sign_y >= 0.0 {
+                wo.y = -wo.y;
+                wi.y = -wi.y;
+            }
+        }
+        (wo, wi)
+    }
+
+    /// `phi_i` and `theta_i` of reduced `wo` as parameters of the tables,
+    /// which are clamped to the range of the data, e.g. the single `phi_i` of isotropic ones
+    fn params(&self, wo: glam::Vec3A) -> [f32; 2] {
+        let theta_i = wo.z.clamp(-1.0, 1.0).acos();
+        let phi_i = wo.y.atan2(wo.x);
+        [phi_i, theta_i]
+    }
+
+    /// rgb values of the brdf, where `u_wm` is the position of the half vector in the tables,
+    /// the tables include the cosine of `wi` which is divided out
+    fn eval(
+        &self,
+        wo: glam::Vec3A,
+        wi: glam::Vec3A,
+        u_wm: glam::Vec2,
+        sample: glam::Vec2,
+    ) -> Color {
+        let [phi_i, theta_i] = self.params(wo);
+        let u_wi = glam::Vec2::new(theta_to_u(theta_i), phi_to_u(wo.y.atan2(wo.x)));
+        let rgb = Color::new(
+            self.rgb.eval(sample, &[phi_i, theta_i, 0.0]),
+            self.rgb.eval(sample, &[phi_i, theta_i, 1.0]),
+            self.rgb.eval(sample, &[phi_i, theta_i, 2.0]),
+        );
+        let scale = self.ndf.eval(u_wm, &[])
+            / (4.0 * self.sigma.eval(u_wi, &[]) * wi.z.max(1e-4)).max(1e-6);
+        Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0)) * scale
+    }
+
+    /// position of the half vector in the tables
+    fn half_to_u(&self, wo: glam::Vec3A, half: glam::Vec3A) -> glam::Vec2 {
+        let theta_m = half.z.clamp(-1.0, 1.0).acos();
+        let mut phi_m = half.y.atan2(half.x);
+        if self.isotropic {
+            phi_m -= wo.y.atan2(wo.x);
+        }
+        let u_phi = phi_to_u(phi_m);
+        glam::Vec2::new(theta_to_u(theta_m), u_phi - u_phi.floor())
+    }
+
+    /// pdf of sampling `u_wm` is converted to pdf of `wi`
+    fn jacobian(&self, wo: glam::Vec3A, u_wm: glam::Vec2, half: glam::Vec3A) -> f32 {
+        let sin_theta_m = (half.x * half.x + half.y * half.y).sqrt();
+        (2.0 * std::f32::consts::PI * std::f32::consts::PI * u_wm.x * sin_theta_m).max(1e-6)
+            * 4.0
+            * wo.dot(half)
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let (wo, wi) = self.reduce(wo, wi);
+        let half = (wo + wi).normalize();
+        let u_wm = self.half_to_u(wo, half);
+        let (sample, _) = self.vndf.invert(u_wm, &self.params(wo));
+        self.eval(wo, wi, u_wm, sample)
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let (wo, wi) = self.reduce(wo, wi);
+        let half = (wo + wi).normalize();
+        let u_wm = self.half_to_u(wo, half);
+        let params = self.params(wo);
+        let (sample, vndf_pdf) = self.vndf.invert(u_wm, &params);
+        let luminance_pdf = self.luminance.eval(sample, &params);
+        vndf_pdf * luminance_pdf / self.jacobian(wo, u_wm, half)
+    }
+
+    /// returns `wi`, the brdf and the pdf
+    fn sample(&self, wo: glam::Vec3A, rng: &mut Rng) -> (glam::Vec3A, Color, f32) {
+        let (wo_reduced, _) = self.reduce(wo, wo);
+        let params = self.params(wo_reduced);
+        let (u0, u1) = rng.uniform_2d();
+        let (sample, luminance_pdf) = self.luminance.sample(glam::Vec2::new(u1, u0), &params);
+        let (u_wm, vndf_pdf) = self.vndf.sample(sample, &params);
+
+        let theta_m = u_wm.x * u_wm.x * std::f32::consts::FRAC_PI_2;
+        let mut phi_m = (2.0 * u_wm.y - 1.0) * std::f32::consts::PI;
+        if self.isotropic {
+            phi_m += wo_reduced.y.atan2(wo_reduced.x);
+        }
+        let half = glam::Vec3A::new(
+            theta_m.sin() * phi_m.cos(),
+            theta_m.sin() * phi_m.sin(),
+            theta_m.cos(),
+        );
+        let wi_reduced = util::reflect_n(wo_reduced, half);
+        if wi_reduced.z <= 0.0 {
+            return (wi_reduced, Color::BLACK, 1.0);
+        }
+
+        let bxdf = self.eval(wo_reduced, wi_reduced, u_wm, sample);
+        let pdf = vndf_pdf * luminance_pdf / self.jacobian(wo_reduced, u_wm, half);
+        // undo the mirroring
+        let (_, wi) = self.reduce(wo, wi_reduced);
+        (wi, bxdf, pdf)
+    }
+}
+
+impl BxdfT for Measured {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let wo = if inputs.wo.z >= 0.0 {
+            inputs.wo
+        } else {
+            flip_z(inputs.wo)
+        };
+
+        let (wi, bxdf, pdf) = match self.data.as_ref() {
+            MeasuredData::Merl(data) => {
+                let wi = data.sample(wo, rng);
+                if wi.z > 0.0 {
+                    (wi, data.lookup(wo, wi), data.pdf(wo, wi))
+                } else {
+                    (wi, Color::BLACK, 1.0)
+                }
+            }
+            MeasuredData::Rgl(data) => data.sample(wo, rng),
+        };
+        let wi = if inputs.wo.z >= 0.0 { wi } else { flip_z(wi) };
+
+        BxdfSample {
+            wi,
+            ty: BxdfSampleType {
+                lobe: BxdfLobeType::Glossy,
+                dir: BxdfDirType::Reflect,
+                subsurface: false,
+            },
+            bxdf,
+            pdf,
+            subsurface: None,
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        if wo.z * wi.z <= 0.0 {
+            return 1.0;
+        }
+        let (wo, wi) = if wo.z >= 0.0 {
+            (wo, wi)
+        } else {
+            (flip_z(wo), flip_z(wi))
+        };
+        match self.data.as_ref() {
+            MeasuredData::Merl(data) => data.pdf(wo, wi),
+            MeasuredData::Rgl(data) => data.pdf(wo, wi),
+        }
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        if wo.z * wi.z <= 0.0 {
+            return Color::BLACK;
+        }
+        let (wo, wi) = if wo.z >= 0.0 {
+            (wo, wi)
+        } else {
+            (flip_z(wo), flip_z(wi))
+        };
+        match self.data.as_ref() {
+            MeasuredData::Merl(data) => data.lookup(wo, wi),
+            MeasuredData::Rgl(data) => data.bxdf(wo, wi),
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// mirrors `w` to the other side, which keeps the azimuth unlike negation
+fn flip_z(w: glam::Vec3A) -> glam::Vec3A {
+    glam::Vec3A::new(w.x, w.y, -w.z)
+}
+
+fn theta_to_u(theta: f32) -> f32 {
+    (theta * std::f32::consts::FRAC_2_PI).max(0.0).sqrt()
+}
+
+fn phi_to_u(phi: f32) -> f32 {
+    (phi + std::f32::consts::PI) * 0.5 * std::f32::consts::FRAC_1_PI
+}
+
+struct TensorField {
+    shape: Vec<usize>,
+    data: Vec<f32>,
+}
+
+/// reads fields of the tensor file format used by Mitsuba and the RGL database
+fn read_tensor_file(bytes: &[u8]) -> anyhow::Result<HashMap<String, TensorField>> {
+    let read = |offset: usize, len: usize| -> anyhow::Result<&[u8]> {
+        bytes
+            .get(offset..offset + len)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of file"))
+    };
+    let read_u16 = |offset: usize| -> anyhow::Result<usize> {
+        Ok(u16::from_le_bytes(read(offset, 2)?.try_into()?) as usize)
+    };
+    let read_u64 = |offset: usize| -> anyhow::Result<usize> {
+        Ok(u64::from_le_bytes(read(offset, 8)?.try_into()?) as usize)
+    };
+
+    if read(0, 12)? != b"tensor_file\0" {
+        anyhow::bail!("not a tensor file");
+    }
+    let version = read(12, 2)?;
+    if version != [1, 0] {
+        anyhow::bail!(format!("unsupported version {}.{}", version[0], version[1]));
+    }
+    let n_fields = u32::from_le_bytes(read(14, 4)?.try_into()?);
+
+    let mut fields = HashMap::new();
+    let mut pos = 18;
+    for _ in 0..n_fields {
+        let name_len = read_u16(pos)?;
+        let name = String::from_utf8_lossy(read(pos + 2, name_len)?).into_owned();
+        pos += 2 + name_len;
+        let ndim = read_u16(pos)?;
+        let dtype = read(pos + 2, 1)?[0];
+        let offset = read_u64(pos + 3)?;
+        pos += 11;
+        let shape = (0..ndim)
+            .map(|i| read_u64(pos + i * 8))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        pos += ndim * 8;
+
+        let count = shape.iter().product::<usize>();
+        let data = match dtype {
+            // uint8
+            1 => read(offset, count)?.iter().map(|v| *v as f32).collect(),
+            // float32
+            10 => read(offset, count * 4)?
+                .chunks_exact(4)
+                .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+                .collect(),
+            // float64
+            11 => read(offset, count * 8)?
+                .chunks_exact(8)
+                .map(|v| f64::from_le_bytes(v.try_into().unwrap()) as f32)
+                .collect(),
+            _ => anyhow::bail!(format!("unsupported type {} of field '{}'", dtype, name)),
+        };
+        fields.insert(name, TensorField { shape, data });
+    }
+
+    Ok(fields)
+}
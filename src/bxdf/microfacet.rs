@@ -12,11 +12,37 @@ pub trait MicrofacetT {
 }
 
 #[enum_dispatch::enum_dispatch]
+#[allow(clippy::enum_variant_names)]
 pub enum Microfacet {
     GgxMicrofacet,
+    BeckmannMicrofacet,
+    GtrMicrofacet,
     PndfMicrofacet,
 }
 
+/// analytic distributions that can be selected by materials
+#[derive(Clone, Copy)]
+pub enum MicrofacetDistribution {
+    Ggx,
+    Beckmann,
+    /// generalized Trowbridge-Reitz with the given gamma, only isotropic
+    Gtr(f32),
+}
+
+impl MicrofacetDistribution {
+    pub fn microfacet(&self, roughness_x: f32, roughness_y: f32) -> Microfacet {
+        match *self {
+            MicrofacetDistribution::Ggx => GgxMicrofacet::new(roughness_x, roughness_y).into(),
+            MicrofacetDistribution::Beckmann => {
+                BeckmannMicrofacet::new(roughness_x, roughness_y).into()
+            }
+            MicrofacetDistribution::Gtr(gamma) => {
+                GtrMicrofacet::new((roughness_x * roughness_y).sqrt(), gamma).into()
+            }
+        }
+    }
+}
+
 pub struct GgxMicrofacet {
     roughness_x: f32,
     roughness_y: f32,
@@ -29,6 +55,11 @@ impl GgxMicrofacet {
             roughness_y,
         }
     }
+
+    /// isotropic alpha with the same area of the anisotropic one
+    pub fn alpha(&self) -> f32 {
+        (self.roughness_x * self.roughness_y).sqrt()
+    }
 }
 
 impl MicrofacetT for GgxMicrofacet {
@@ -53,6 +84,79 @@ impl MicrofacetT for GgxMicrofacet {
     }
 }
 
+pub struct BeckmannMicrofacet {
+    roughness_x: f32,
+    roughness_y: f32,
+}
+
+impl BeckmannMicrofacet {
+    pub fn new(roughness_x: f32, roughness_y: f32) -> Self {
+        Self {
+            roughness_x,
+            roughness_y,
+        }
+    }
+}
+
+impl MicrofacetT for BeckmannMicrofacet {
+    fn sample_half(&self, inputs: &BxdfInputs, rng: &mut Rng) -> (glam::Vec3A, f32) {
+        util::beckmann_vndf_sample(
+            inputs.wo,
+            self.roughness_x,
+            self.roughness_y,
+            rng.uniform_2d(),
+        )
+    }
+
+    fn half_pdf(&self, wo: glam::Vec3A, half: glam::Vec3A) -> f32 {
+        util::beckmann_vndf_pdf(half, wo, self.roughness_x, self.roughness_y)
+    }
+
+    fn ndf_visible(&self, wo: glam::Vec3A, wi: glam::Vec3A, half: glam::Vec3A) -> f32 {
+        let ndf = util::beckmann_ndf_aniso(half, self.roughness_x, self.roughness_y);
+        let visible =
+            util::beckmann_separable_visible_aniso(wo, wi, self.roughness_x, self.roughness_y);
+        ndf * visible
+    }
+}
+
+/// masking of GTR uses the ggx one, normals are sampled from the whole distribution
+pub struct GtrMicrofacet {
+    roughness: f32,
+    gamma: f32,
+}
+
+impl GtrMicrofacet {
+    pub fn new(roughness: f32, gamma: f32) -> Self {
+        Self { roughness, gamma }
+    }
+}
+
+impl MicrofacetT for GtrMicrofacet {
+    fn sample_half(&self, _inputs: &BxdfInputs, rng: &mut Rng) -> (glam::Vec3A, f32) {
+        let (rand_cos, rand_phi) = rng.uniform_2d();
+        let a2 = self.roughness * self.roughness;
+        let cos_sqr = util::gtr_ndf_cdf_inverse(a2, self.gamma, rand_cos);
+        let cos = cos_sqr.sqrt();
+        let sin = (1.0 - cos_sqr).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * rand_phi;
+        let half = glam::Vec3A::new(sin * phi.cos(), sin * phi.sin(), cos);
+        (half, util::gtr_ndf(cos, a2, self.gamma) * cos)
+    }
+
+    fn half_pdf(&self, _wo: glam::Vec3A, half: glam::Vec3A) -> f32 {
+        let a2 = self.roughness * self.roughness;
+        util::gtr_ndf(half.z, a2, self.gamma) * half.z.max(0.0)
+    }
+
+    fn ndf_visible(&self, wo: glam::Vec3A, wi: glam::Vec3A, half: glam::Vec3A) -> f32 {
+        let a2 = self.roughness * self.roughness;
+        let ndf = util::gtr_ndf(half.z, a2, self.gamma);
+        let visible = util::smith_separable_visible_aniso(wo, wi, self.roughness, self.roughness);
+        ndf * visible
+    }
+}
+
 pub struct PndfMicrofacet {
     u: glam::Vec2,
     sigma_p: f32,
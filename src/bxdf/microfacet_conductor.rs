@@ -1,13 +1,14 @@
 use crate::core::{color::Color, rng::Rng};
 
 use super::{
-    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT, Fresnel,
-    FresnelT, Microfacet, MicrofacetT,
+    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT,
+    ConductorCompensation, Fresnel, FresnelT, Microfacet, MicrofacetT,
 };
 
 pub struct MicrofacetConductor {
     microfacet: Microfacet,
     fresnel: Fresnel,
+    compensation: Option<ConductorCompensation>,
 }
 
 impl MicrofacetConductor {
@@ -15,12 +16,28 @@ impl MicrofacetConductor {
         Self {
             microfacet,
             fresnel,
+            compensation: None,
         }
     }
-}
 
-impl BxdfT for MicrofacetConductor {
-    fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
+    /// add multiple scattering energy compensation, only ggx is supported
+    pub fn with_energy_compensation(mut self) -> Self {
+        if let Microfacet::GgxMicrofacet(ggx) = &self.microfacet {
+            self.compensation = Some(ConductorCompensation::new(
+                ggx.alpha(),
+                self.fresnel.average(),
+            ));
+        }
+        self
+    }
+
+    /// same as `with_energy_compensation` with a compensation computed ahead
+    pub fn with_compensation(mut self, compensation: ConductorCompensation) -> Self {
+        self.compensation = Some(compensation);
+        self
+    }
+
+    fn sample_single(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
         let (half, half_pdf) = self.microfacet.sample_half(inputs, rng);
         let fresnel = self.fresnel.fresnel(inputs.wo, half);
         let wi = util::reflect_n(inputs.wo, half);
@@ -40,7 +57,7 @@ impl BxdfT for MicrofacetConductor {
         }
     }
 
-    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+    fn pdf_single(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
         if wo.z * wi.z >= 0.0 {
             let half = util::half_from_reflect(wo, wi);
             let half_pdf = self.microfacet.half_pdf(wo, half);
@@ -51,7 +68,7 @@ impl BxdfT for MicrofacetConductor {
         }
     }
 
-    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+    fn bxdf_single(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
         if wo.z * wi.z >= 0.0 {
             let half = util::half_from_reflect(wo, wi);
             let fresnel = self.fresnel.fresnel(wo, half);
@@ -61,6 +78,52 @@ impl BxdfT for MicrofacetConductor {
             Color::BLACK
         }
     }
+}
+
+impl BxdfT for MicrofacetConductor {
+    fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        if let Some(compensation) = &self.compensation {
+            let wi = if rng.uniform_1d() < compensation.sample_prob(inputs.wo) {
+                compensation.sample_wi(inputs.wo, rng)
+            } else {
+                self.sample_single(inputs, rng).wi
+            };
+
+            BxdfSample {
+                wi,
+                ty: BxdfSampleType {
+                    lobe: BxdfLobeType::Glossy,
+                    dir: BxdfDirType::Reflect,
+                    subsurface: false,
+                },
+                bxdf: self.bxdf(inputs.wo, wi),
+                pdf: self.pdf(inputs.wo, wi),
+                subsurface: None,
+            }
+        } else {
+            self.sample_single(inputs, rng)
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let pdf = self.pdf_single(wo, wi);
+        if let Some(compensation) = &self.compensation {
+            if wo.z * wi.z >= 0.0 {
+                let prob = compensation.sample_prob(wo);
+                return (1.0 - prob) * pdf + prob * compensation.pdf(wo, wi);
+            }
+        }
+        pdf
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let bxdf = self.bxdf_single(wo, wi);
+        if let Some(compensation) = &self.compensation {
+            bxdf + compensation.bxdf(wo, wi)
+        } else {
+            bxdf
+        }
+    }
 
     fn is_delta(&self) -> bool {
         false
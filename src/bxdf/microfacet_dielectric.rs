@@ -1,13 +1,14 @@
 use crate::core::{color::Color, rng::Rng};
 
 use super::{
-    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT, Fresnel,
-    FresnelT, Microfacet, MicrofacetT,
+    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT,
+    DielectricCompensation, Fresnel, FresnelT, Microfacet, MicrofacetT,
 };
 
 pub struct MicrofacetDielectric {
     microfacet: Microfacet,
     fresnel: Fresnel,
+    compensation: Option<DielectricCompensation>,
 }
 
 impl MicrofacetDielectric {
@@ -15,12 +16,19 @@ impl MicrofacetDielectric {
         Self {
             microfacet,
             fresnel,
+            compensation: None,
         }
     }
-}
 
-impl BxdfT for MicrofacetDielectric {
-    fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
+    /// add multiple scattering energy compensation, only ggx is supported
+    pub fn with_energy_compensation(mut self) -> Self {
+        if let Microfacet::GgxMicrofacet(ggx) = &self.microfacet {
+            self.compensation = Some(DielectricCompensation::new(ggx.alpha(), self.fresnel.ior()));
+        }
+        self
+    }
+
+    fn sample_single(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
         let (half, half_pdf) = self.microfacet.sample_half(inputs, rng);
         let fresnel = self.fresnel.fresnel(inputs.wo, half);
         let sample_reflect_pdf = fresnel.luminance();
@@ -83,7 +91,7 @@ impl BxdfT for MicrofacetDielectric {
         }
     }
 
-    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+    fn pdf_single(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
         if wo.z * wi.z >= 0.0 {
             let half = util::half_from_reflect(wo, wi);
             let half_pdf = self.microfacet.half_pdf(wo, half);
@@ -110,7 +118,7 @@ impl BxdfT for MicrofacetDielectric {
         }
     }
 
-    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+    fn bxdf_single(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
         if wo.z * wi.z >= 0.0 {
             let half = util::half_from_reflect(wo, wi);
             let fresnel = self.fresnel.fresnel(wo, half);
@@ -135,6 +143,59 @@ impl BxdfT for MicrofacetDielectric {
             bxdf
         }
     }
+}
+
+impl BxdfT for MicrofacetDielectric {
+    fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        if let Some(compensation) = &self.compensation {
+            let wi = if rng.uniform_1d() < compensation.sample_prob(inputs.wo) {
+                compensation.sample_wi(inputs.wo, rng)
+            } else {
+                let samp = self.sample_single(inputs, rng);
+                if samp.wi == glam::Vec3A::ZERO {
+                    return samp;
+                }
+                samp.wi
+            };
+
+            BxdfSample {
+                wi,
+                ty: BxdfSampleType {
+                    lobe: BxdfLobeType::Glossy,
+                    dir: if inputs.wo.z * wi.z >= 0.0 {
+                        BxdfDirType::Reflect
+                    } else {
+                        BxdfDirType::Transmit
+                    },
+                    subsurface: false,
+                },
+                bxdf: self.bxdf(inputs.wo, wi),
+                pdf: self.pdf(inputs.wo, wi),
+                subsurface: None,
+            }
+        } else {
+            self.sample_single(inputs, rng)
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let pdf = self.pdf_single(wo, wi);
+        if let Some(compensation) = &self.compensation {
+            let prob = compensation.sample_prob(wo);
+            (1.0 - prob) * pdf + prob * compensation.pdf(wo, wi)
+        } else {
+            pdf
+        }
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let bxdf = self.bxdf_single(wo, wi);
+        if let Some(compensation) = &self.compensation {
+            bxdf + compensation.bxdf(wo, wi)
+        } else {
+            bxdf
+        }
+    }
 
     fn is_delta(&self) -> bool {
         false
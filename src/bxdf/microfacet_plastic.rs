@@ -1,14 +1,15 @@
 use crate::core::{color::Color, rng::Rng};
 
 use super::{
-    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT, Fresnel,
-    FresnelT, Microfacet, MicrofacetT, Substrate, SubstrateT,
+    util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT,
+    ConductorCompensation, Fresnel, FresnelT, Microfacet, MicrofacetT, Substrate, SubstrateT,
 };
 
 pub struct MicrofacetPlastic {
     microfacet: Microfacet,
     fresnel: Fresnel,
     substrate: Substrate,
+    compensation: Option<ConductorCompensation>,
 }
 
 impl MicrofacetPlastic {
@@ -17,6 +18,51 @@ impl MicrofacetPlastic {
             microfacet,
             fresnel,
             substrate,
+            compensation: None,
+        }
+    }
+
+    /// add multiple scattering energy compensation to the specular lobe, only ggx is supported
+    pub fn with_energy_compensation(mut self) -> Self {
+        if let Microfacet::GgxMicrofacet(ggx) = &self.microfacet {
+            self.compensation = Some(ConductorCompensation::new(
+                ggx.alpha(),
+                self.fresnel.average(),
+            ));
+        }
+        self
+    }
+
+    /// part of energy left to the substrate, not reflected by the specular lobes
+    fn substrate_scale(&self, wo: glam::Vec3A) -> Color {
+        let fresnel_macro = self.fresnel.fresnel(wo, glam::Vec3A::Z);
+        if let Some(compensation) = &self.compensation {
+            (Color::WHITE - fresnel_macro) * (Color::WHITE - compensation.albedo(wo))
+        } else {
+            Color::WHITE - fresnel_macro
+        }
+    }
+
+    fn specular_pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let half = util::half_from_reflect(wo, wi);
+        let half_pdf = self.microfacet.half_pdf(wo, half);
+        let pdf = half_pdf / (4.0 * wo.dot(half).abs());
+        if let Some(compensation) = &self.compensation {
+            let prob = compensation.sample_prob(wo);
+            (1.0 - prob) * pdf + prob * compensation.pdf(wo, wi)
+        } else {
+            pdf
+        }
+    }
+
+    fn specular_bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let half = util::half_from_reflect(wo, wi);
+        let fresnel = self.fresnel.fresnel(wo, half);
+        let bxdf = fresnel * self.microfacet.ndf_visible(wo, wi, half);
+        if let Some(compensation) = &self.compensation {
+            bxdf + compensation.bxdf(wo, wi)
+        } else {
+            bxdf
         }
     }
 }
@@ -24,20 +70,25 @@ impl MicrofacetPlastic {
 impl BxdfT for MicrofacetPlastic {
     fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
         let fresnel_macro = self.fresnel.fresnel(inputs.wo, glam::Vec3A::Z);
+        let substrate_scale = self.substrate_scale(inputs.wo);
         let specular_weight = fresnel_macro.luminance();
-        let substrate_weight =
-            ((Color::WHITE - fresnel_macro) * self.substrate.reflectance()).luminance();
+        let substrate_weight = (substrate_scale * self.substrate.reflectance()).luminance();
         let sample_reflect_pdf = specular_weight / (specular_weight + substrate_weight);
 
         if rng.uniform_1d() < sample_reflect_pdf {
-            let (half, half_pdf) = self.microfacet.sample_half(inputs, rng);
-            let fresnel = self.fresnel.fresnel(inputs.wo, half);
-            let wi = util::reflect_n(inputs.wo, half);
-            let specular_bxdf = fresnel * self.microfacet.ndf_visible(inputs.wo, wi, half);
-            let specular_pdf = sample_reflect_pdf * half_pdf / (4.0 * inputs.wo.dot(half).abs());
-
-            let substrate_bxdf =
-                (Color::WHITE - fresnel_macro) * self.substrate.bxdf(inputs.wo, wi);
+            let wi = match &self.compensation {
+                Some(compensation) if rng.uniform_1d() < compensation.sample_prob(inputs.wo) => {
+                    compensation.sample_wi(inputs.wo, rng)
+                }
+                _ => {
+                    let (half, _) = self.microfacet.sample_half(inputs, rng);
+                    util::reflect_n(inputs.wo, half)
+                }
+            };
+            let specular_bxdf = self.specular_bxdf(inputs.wo, wi);
+            let specular_pdf = sample_reflect_pdf * self.specular_pdf(inputs.wo, wi);
+
+            let substrate_bxdf = substrate_scale * self.substrate.bxdf(inputs.wo, wi);
             let substrate_pdf = (1.0 - sample_reflect_pdf) * self.substrate.pdf(inputs.wo, wi);
 
             BxdfSample {
@@ -54,14 +105,10 @@ impl BxdfT for MicrofacetPlastic {
         } else {
             let samp = self.substrate.sample(inputs, rng);
             let substrate_pdf = (1.0 - sample_reflect_pdf) * samp.pdf;
-            let substrate_bxdf = (Color::WHITE - fresnel_macro) * samp.bxdf;
-
-            let half = util::half_from_reflect(inputs.wo, samp.wi);
-            let half_pdf = self.microfacet.half_pdf(inputs.wo, half);
-            let specular_pdf = sample_reflect_pdf * half_pdf / (4.0 * inputs.wo.dot(half).abs());
+            let substrate_bxdf = substrate_scale * samp.bxdf;
 
-            let fresnel = self.fresnel.fresnel(inputs.wo, half);
-            let specular_bxdf = fresnel * self.microfacet.ndf_visible(inputs.wo, samp.wi, half);
+            let specular_pdf = sample_reflect_pdf * self.specular_pdf(inputs.wo, samp.wi);
+            let specular_bxdf = self.specular_bxdf(inputs.wo, samp.wi);
 
             BxdfSample {
                 bxdf: substrate_bxdf + specular_bxdf,
@@ -76,12 +123,10 @@ impl BxdfT for MicrofacetPlastic {
             let fresnel_macro = self.fresnel.fresnel(wo, glam::Vec3A::Z);
             let specular_weight = fresnel_macro.luminance();
             let substrate_weight =
-                ((Color::WHITE - fresnel_macro) * self.substrate.reflectance()).luminance();
+                (self.substrate_scale(wo) * self.substrate.reflectance()).luminance();
             let sample_reflect_pdf = specular_weight / (specular_weight + substrate_weight);
 
-            let half = util::half_from_reflect(wo, wi);
-            let half_pdf = self.microfacet.half_pdf(wo, half);
-            let specular_pdf = sample_reflect_pdf * half_pdf / (4.0 * wo.dot(half).abs());
+            let specular_pdf = sample_reflect_pdf * self.specular_pdf(wo, wi);
 
             let substrate_pdf = (1.0 - sample_reflect_pdf) * self.substrate.pdf(wo, wi);
 
@@ -93,15 +138,8 @@ impl BxdfT for MicrofacetPlastic {
 
     fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
         if wo.z * wi.z >= 0.0 {
-            let reflect = {
-                let half = util::half_from_reflect(wo, wi);
-                let fresnel = self.fresnel.fresnel(wo, half);
-                fresnel * self.microfacet.ndf_visible(wo, wi, half)
-            };
-            let substrate = {
-                let fresnel_macro = self.fresnel.fresnel(wo, glam::Vec3A::Z);
-                (Color::WHITE - fresnel_macro) * self.substrate.bxdf(wo, wi)
-            };
+            let reflect = self.specular_bxdf(wo, wi);
+            let substrate = self.substrate_scale(wo) * self.substrate.bxdf(wo, wi);
 
             reflect + substrate
         } else {
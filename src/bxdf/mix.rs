@@ -0,0 +1,86 @@
+use crate::core::{color::Color, rng::Rng};
+
+use super::{Bxdf, BxdfInputs, BxdfLobeType, BxdfSample, BxdfT};
+
+/// weighted sum of bxdfs, one of them is sampled with probability of its weight
+pub struct Mix {
+    /// weights sum to 1
+    components: Vec<(f32, Bxdf)>,
+}
+
+impl Mix {
+    /// weights are normalized, components with zero weight are dropped
+    pub fn new(components: Vec<(f32, Bxdf)>) -> Self {
+        let sum: f32 = components.iter().map(|(weight, _)| weight.max(0.0)).sum();
+        let components = components
+            .into_iter()
+            .filter(|(weight, _)| *weight > 0.0)
+            .map(|(weight, bxdf)| (weight / sum, bxdf))
+            .collect();
+        Self { components }
+    }
+
+    fn choose(&self, rng: &mut Rng) -> &(f32, Bxdf) {
+        let mut rand = rng.uniform_1d();
+        for component in &self.components {
+            if rand < component.0 {
+                return component;
+            }
+            rand -= component.0;
+        }
+        self.components.last().unwrap()
+    }
+}
+
+impl BxdfT for Mix {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let (_, bxdf) = self.choose(rng);
+        let mut samp = bxdf.sample(inputs, rng);
+        // probability of choosing the component cancels out with its weight
+        if samp.ty.lobe == BxdfLobeType::Specular
+            || samp.subsurface.is_some()
+            || samp.wi == glam::Vec3A::ZERO
+        {
+            return samp;
+        }
+
+        samp.bxdf = self.bxdf(inputs.wo, samp.wi);
+        samp.pdf = self.pdf(inputs.wo, samp.wi);
+        samp
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        self.components
+            .iter()
+            .map(|(weight, bxdf)| weight * component_pdf(bxdf, wo, wi))
+            .sum()
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        self.components
+            .iter()
+            .filter(|(_, bxdf)| !bxdf.is_delta())
+            .fold(Color::BLACK, |sum, (weight, bxdf)| {
+                sum + bxdf.bxdf(wo, wi) * *weight
+            })
+    }
+
+    fn is_delta(&self) -> bool {
+        self.components.iter().all(|(_, bxdf)| bxdf.is_delta())
+    }
+}
+
+/// pdf of a component as a density over directions
+fn component_pdf(bxdf: &Bxdf, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+    if bxdf.is_delta() {
+        return 0.0;
+    }
+    // reflection only bxdfs report a dummy pdf in the other hemisphere
+    if wo.z * wi.z < 0.0 {
+        let value = bxdf.bxdf(wo, wi);
+        if value.r <= 0.0 && value.g <= 0.0 && value.b <= 0.0 {
+            return 0.0;
+        }
+    }
+    bxdf.pdf(wo, wi)
+}
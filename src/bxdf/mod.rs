@@ -1,30 +1,46 @@
 mod util;
 
 mod lambert;
+mod layered;
+mod measured;
+mod mix;
 mod pseudo;
 
+mod energy_compensation;
 mod fresnel;
+mod hair;
 mod microfacet;
 mod microfacet_conductor;
 mod microfacet_dielectric;
 mod microfacet_plastic;
 mod pndf_bvh;
+mod principled;
+mod sheen;
 mod substrate;
+mod woven;
 
 mod specular_conductor;
 mod specular_dielectric;
 mod specular_plastic;
 
 pub use lambert::*;
+pub use layered::*;
+pub use measured::*;
+pub use mix::*;
 pub use pseudo::*;
 
+pub use energy_compensation::*;
 pub use fresnel::*;
+pub use hair::*;
 pub use microfacet::*;
 pub use microfacet_conductor::*;
 pub use microfacet_dielectric::*;
 pub use microfacet_plastic::*;
 pub use pndf_bvh::*;
+pub use principled::*;
+pub use sheen::*;
 pub use substrate::*;
+pub use woven::*;
 
 pub use specular_conductor::*;
 pub use specular_dielectric::*;
@@ -32,7 +48,7 @@ pub use specular_plastic::*;
 
 use crate::{
     core::{color::Color, coord::Coordinate, rng::Rng},
-    primitive::Primitive,
+    primitive::{Instance, Primitive},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -57,13 +73,22 @@ pub struct BxdfSampleType {
 
 pub struct BxdfInputs<'a> {
     pub po: glam::Vec3A,
+    /// error bound of `po`, see `Intersection::p_error`
+    pub po_error: glam::Vec3A,
+    pub geo_normal_po: glam::Vec3A,
     pub coord_po: Coordinate,
     pub wo: glam::Vec3A,
-    pub scene: &'a Primitive,
+    /// `None` where no position can be traced, e.g. between layers of a layered bsdf
+    pub scene: Option<&'a Primitive>,
+    /// instance `po` lies on, if known
+    pub instance: Option<&'a Instance>,
 }
 
 pub struct BxdfSubsurfaceSample {
     pub pi: glam::Vec3A,
+    /// error bound of `pi`, see `Intersection::p_error`
+    pub pi_error: glam::Vec3A,
+    pub geo_normal_pi: glam::Vec3A,
     pub coord_pi: Coordinate,
     pub sp: Color,
     pub pdf_pi: f32,
@@ -91,12 +116,19 @@ pub trait BxdfT {
 
 #[enum_dispatch::enum_dispatch]
 pub enum Bxdf {
+    Hair,
     Lambert,
+    Layered,
+    Measured,
+    Mix,
     Pseudo,
     MicrofacetConductor,
     MicrofacetDielectric,
     MicrofacetPlastic,
+    Principled,
+    Sheen,
     SpecularConductor,
     SpecularDielectric,
     SpecularPlastic,
+    Woven,
 }
@@ -0,0 +1,283 @@
+use crate::core::{color::Color, rng::Rng};
+
+use super::{
+    charlie_bxdf, util, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT,
+    DielectricFresnel, FresnelT, GgxMicrofacet, Lambert, MicrofacetDielectric, MicrofacetT,
+    SchlickFresnel,
+};
+
+/// smallest alpha of microfacet lobes, as a mixture can't contain delta lobes
+const MIN_ALPHA: f32 = 0.001;
+const CLEARCOAT_IOR: f32 = 1.5;
+
+/// parameters of the principled bsdf at a shading point, all in [0, 1] except `ior`
+pub struct PrincipledParams {
+    pub base_color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub anisotropic: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub sheen_roughness: f32,
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub transmission: f32,
+    pub ior: f32,
+    pub subsurface: f32,
+}
+
+/// Disney principled bsdf (Burley 2012, 2015),
+/// the base is a mixture of metal, opaque dielectric and rough glass sharing one ggx lobe,
+/// with Disney diffuse (flattened to Hanrahan-Krueger by `subsurface`) and Charlie sheen on dielectric,
+/// and a ggx clearcoat on top,
+/// one lobe is chosen for sampling and the direction is weighted by pdfs of all lobes
+pub struct Principled {
+    diffuse: Lambert,
+    roughness: f32,
+    subsurface: f32,
+    sheen: Color,
+    sheen_alpha: f32,
+    specular: GgxMicrofacet,
+    metal_fresnel: SchlickFresnel,
+    metal_weight: f32,
+    dielectric_fresnel: SchlickFresnel,
+    dielectric_weight: f32,
+    glass_fresnel: DielectricFresnel,
+    glass_weight: f32,
+    /// only refraction of it is used, reflection is in `specular`
+    transmission: MicrofacetDielectric,
+    transmittance: Color,
+    clearcoat: GgxMicrofacet,
+    clearcoat_fresnel: DielectricFresnel,
+    clearcoat_weight: f32,
+}
+
+#[derive(Clone, Copy)]
+enum PrincipledLobe {
+    Diffuse,
+    Specular,
+    Transmission,
+    Clearcoat,
+}
+
+impl Principled {
+    pub fn new(params: &PrincipledParams) -> Self {
+        let base_color = params.base_color;
+        let base_lum = base_color.luminance();
+        let tint = if base_lum > 0.0 {
+            base_color / base_lum
+        } else {
+            Color::WHITE
+        };
+        let lerp = |a: Color, b: Color, t: f32| a * (1.0 - t) + b * t;
+
+        let metal_weight = params.metallic;
+        let glass_weight = (1.0 - params.metallic) * params.transmission;
+        let dielectric_weight = (1.0 - params.metallic) * (1.0 - params.transmission);
+
+        let alpha = params.roughness * params.roughness;
+        let aspect = (1.0 - 0.9 * params.anisotropic).sqrt();
+        let alpha_x = (alpha / aspect).max(MIN_ALPHA);
+        let alpha_y = (alpha * aspect).max(MIN_ALPHA);
+        let clearcoat_alpha =
+            (params.clearcoat_roughness * params.clearcoat_roughness).max(MIN_ALPHA);
+
+        let specular_r0 = 0.08 * params.specular * lerp(Color::WHITE, tint, params.specular_tint);
+
+        Self {
+            diffuse: Lambert::new(base_color * dielectric_weight),
+            roughness: params.roughness,
+            subsurface: params.subsurface,
+            sheen: dielectric_weight * params.sheen * lerp(Color::WHITE, tint, params.sheen_tint),
+            sheen_alpha: (params.sheen_roughness * params.sheen_roughness).max(MIN_ALPHA),
+            specular: GgxMicrofacet::new(alpha_x, alpha_y),
+            metal_fresnel: SchlickFresnel::new(base_color),
+            metal_weight,
+            dielectric_fresnel: SchlickFresnel::new(specular_r0),
+            dielectric_weight,
+            glass_fresnel: DielectricFresnel::new(params.ior),
+            glass_weight,
+            transmission: MicrofacetDielectric::new(
+                GgxMicrofacet::new(alpha_x, alpha_y).into(),
+                DielectricFresnel::new(params.ior).into(),
+            ),
+            transmittance: base_color * glass_weight,
+            clearcoat: GgxMicrofacet::new(clearcoat_alpha, clearcoat_alpha),
+            clearcoat_fresnel: DielectricFresnel::new(CLEARCOAT_IOR),
+            clearcoat_weight: params.clearcoat,
+        }
+    }
+
+    fn specular_fresnel(&self, wo: glam::Vec3A, half: glam::Vec3A) -> Color {
+        let mut fresnel = Color::BLACK;
+        if self.metal_weight > 0.0 {
+            fresnel += self.metal_weight * self.metal_fresnel.fresnel(wo, half);
+        }
+        if self.dielectric_weight > 0.0 {
+            fresnel += self.dielectric_weight * self.dielectric_fresnel.fresnel(wo, half);
+        }
+        if self.glass_weight > 0.0 {
+            fresnel += self.glass_weight * self.glass_fresnel.fresnel(wo, half);
+        }
+        fresnel
+    }
+
+    /// probabilities of choosing each lobe to sample, estimated at normal incidence of `wo`
+    fn lobe_probs(&self, wo: glam::Vec3A) -> [f32; 4] {
+        let n = if wo.z >= 0.0 {
+            glam::Vec3A::Z
+        } else {
+            -glam::Vec3A::Z
+        };
+        let mut probs = [
+            (self.diffuse.reflectance() + self.sheen).luminance(),
+            self.specular_fresnel(wo, n).luminance(),
+            self.transmittance.luminance(),
+            self.clearcoat_weight * self.clearcoat_fresnel.fresnel(wo, n).luminance(),
+        ];
+        let sum: f32 = probs.iter().sum();
+        if sum > 0.0 {
+            probs.iter_mut().for_each(|prob| *prob /= sum);
+        }
+        probs
+    }
+
+    /// Disney diffuse with retro-reflection, blended to the Hanrahan-Krueger approximation
+    fn diffuse_bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A, half: glam::Vec3A) -> Color {
+        let cos_o = wo.z.abs();
+        let cos_i = wi.z.abs();
+        let cos_d = wi.dot(half).abs();
+        let fl = schlick_weight(cos_i);
+        let fv = schlick_weight(cos_o);
+        let rr = self.roughness * cos_d * cos_d;
+
+        let fd90 = 0.5 + 2.0 * rr;
+        let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+        let shape = if self.subsurface > 0.0 {
+            let fss = (1.0 + (rr - 1.0) * fl) * (1.0 + (rr - 1.0) * fv);
+            let ss = 1.25 * (fss * (1.0 / (cos_i + cos_o).max(0.0001) - 0.5) + 0.5);
+            fd * (1.0 - self.subsurface) + ss * self.subsurface
+        } else {
+            fd
+        };
+
+        self.diffuse.reflectance() * std::f32::consts::FRAC_1_PI * shape
+            + self.sheen * charlie_bxdf(wo, wi, self.sheen_alpha)
+    }
+
+    fn reflect_pdf(microfacet: &GgxMicrofacet, wo: glam::Vec3A, half: glam::Vec3A) -> f32 {
+        microfacet.half_pdf(wo, half) / (4.0 * wo.dot(half).abs()).max(0.0001)
+    }
+}
+
+impl BxdfT for Principled {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let wo = inputs.wo;
+        let probs = self.lobe_probs(wo);
+        let mut rand = rng.uniform_1d();
+        let mut lobe = PrincipledLobe::Clearcoat;
+        for (prob, candidate) in probs.iter().zip([
+            PrincipledLobe::Diffuse,
+            PrincipledLobe::Specular,
+            PrincipledLobe::Transmission,
+        ]) {
+            if rand < *prob {
+                lobe = candidate;
+                break;
+            }
+            rand -= prob;
+        }
+
+        let wi = match lobe {
+            PrincipledLobe::Diffuse => BxdfT::sample(&self.diffuse, inputs, rng).wi,
+            PrincipledLobe::Specular => {
+                let (half, _) = self.specular.sample_half(inputs, rng);
+                util::reflect_n(wo, half)
+            }
+            PrincipledLobe::Transmission => self.transmission.sample(inputs, rng).wi,
+            PrincipledLobe::Clearcoat => {
+                let (half, _) = self.clearcoat.sample_half(inputs, rng);
+                util::reflect_n(wo, half)
+            }
+        };
+
+        let dir = if wo.z * wi.z >= 0.0 {
+            BxdfDirType::Reflect
+        } else {
+            BxdfDirType::Transmit
+        };
+        let lobe = match lobe {
+            PrincipledLobe::Diffuse => BxdfLobeType::Diffuse,
+            _ => BxdfLobeType::Glossy,
+        };
+        let pdf = self.pdf(wo, wi);
+        let bxdf = if wi == glam::Vec3A::ZERO || pdf <= 0.0 {
+            Color::BLACK
+        } else {
+            self.bxdf(wo, wi)
+        };
+
+        BxdfSample {
+            wi,
+            ty: BxdfSampleType {
+                lobe,
+                dir,
+                subsurface: false,
+            },
+            bxdf,
+            pdf: if pdf > 0.0 { pdf } else { 1.0 },
+            subsurface: None,
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let [diffuse_prob, specular_prob, transmission_prob, clearcoat_prob] = self.lobe_probs(wo);
+        let mut pdf = 0.0;
+        if wo.z * wi.z >= 0.0 {
+            let half = util::half_from_reflect(wo, wi);
+            if diffuse_prob > 0.0 {
+                pdf += diffuse_prob * wi.z.abs() * std::f32::consts::FRAC_1_PI;
+            }
+            if specular_prob > 0.0 {
+                pdf += specular_prob * Self::reflect_pdf(&self.specular, wo, half);
+            }
+            if clearcoat_prob > 0.0 {
+                pdf += clearcoat_prob * Self::reflect_pdf(&self.clearcoat, wo, half);
+            }
+        }
+        if transmission_prob > 0.0 {
+            pdf += transmission_prob * self.transmission.pdf(wo, wi);
+        }
+        pdf
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        if wo.z * wi.z >= 0.0 {
+            let half = util::half_from_reflect(wo, wi);
+            let mut bxdf = self.diffuse_bxdf(wo, wi, half);
+            bxdf += self.specular_fresnel(wo, half) * self.specular.ndf_visible(wo, wi, half);
+            if self.clearcoat_weight > 0.0 {
+                bxdf += self.clearcoat_weight
+                    * self.clearcoat_fresnel.fresnel(wo, half)
+                    * self.clearcoat.ndf_visible(wo, wi, half);
+            }
+            bxdf
+        } else if self.glass_weight > 0.0 {
+            self.transmittance * self.transmission.bxdf(wo, wi)
+        } else {
+            Color::BLACK
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+fn schlick_weight(cos: f32) -> f32 {
+    let m = (1.0 - cos).clamp(0.0, 1.0);
+    let m2 = m * m;
+    m2 * m2 * m
+}
@@ -0,0 +1,166 @@
+use crate::core::{color::Color, rng::Rng};
+
+use super::{util, Bxdf, BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT};
+
+/// resolution of `cos(theta_o)` and alpha in the albedo table
+const TABLE_RES: usize = 32;
+/// resolution of `cos(theta_i)` and `phi_i` in quadrature
+const QUADRATURE_RES: usize = 64;
+/// alpha is clamped to avoid numerical issue of the distribution
+const MIN_ALPHA: f32 = 0.01;
+
+lazy_static! {
+    /// directional albedo of Charlie sheen, indexed by alpha then by `cos(theta_o)`
+    static ref SHEEN_ALBEDO: Vec<f32> = {
+        let mut albedo = Vec::with_capacity(TABLE_RES * TABLE_RES);
+        for alpha_ind in 0..TABLE_RES {
+            let alpha = table_value(alpha_ind).max(MIN_ALPHA);
+            for mu_ind in 0..TABLE_RES {
+                let mu = table_value(mu_ind);
+                let wo = glam::Vec3A::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let mut sum = 0.0;
+                for i in 0..QUADRATURE_RES {
+                    // uniform in `cos^2`, so that the cosine term is absorbed
+                    let cos_i = ((i as f32 + 0.5) / QUADRATURE_RES as f32).sqrt();
+                    let sin_i = (1.0 - cos_i * cos_i).sqrt();
+                    for j in 0..QUADRATURE_RES {
+                        let phi = (j as f32 + 0.5) / QUADRATURE_RES as f32
+                            * 2.0
+                            * std::f32::consts::PI;
+                        let wi = glam::Vec3A::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+                        sum += charlie_bxdf(wo, wi, alpha);
+                    }
+                }
+                let value = sum * std::f32::consts::PI / (QUADRATURE_RES * QUADRATURE_RES) as f32;
+                albedo.push(value.clamp(0.0, 1.0));
+            }
+        }
+        albedo
+    };
+}
+
+fn table_value(ind: usize) -> f32 {
+    (ind as f32 + 0.5) / TABLE_RES as f32
+}
+
+fn table_coord(value: f32) -> (usize, usize, f32) {
+    let x = (value * TABLE_RES as f32 - 0.5).clamp(0.0, (TABLE_RES - 1) as f32);
+    let ind = (x as usize).min(TABLE_RES - 2);
+    (ind, ind + 1, x - ind as f32)
+}
+
+/// Charlie sheen brdf without color, `wo` and `wi` should be in the same hemisphere
+pub fn charlie_bxdf(wo: glam::Vec3A, wi: glam::Vec3A, alpha: f32) -> f32 {
+    let half = util::half_from_reflect(wo, wi);
+    util::charlie_ndf(half.z, alpha) * util::charlie_visible(wo, wi, alpha)
+}
+
+/// directional albedo of Charlie sheen brdf
+pub fn sheen_albedo(alpha: f32, mu: f32) -> f32 {
+    let (a0, a1, at) = table_coord(alpha);
+    let (m0, m1, mt) = table_coord(mu.abs());
+    let value = |a: usize, m: usize| SHEEN_ALBEDO[a * TABLE_RES + m];
+    let v0 = value(a0, m0) * (1.0 - mt) + value(a0, m1) * mt;
+    let v1 = value(a1, m0) * (1.0 - mt) + value(a1, m1) * mt;
+    v0 * (1.0 - at) + v1 * at
+}
+
+/// a Charlie sheen lobe over `base`, the base is scaled by energy not reflected by the sheen
+pub struct Sheen {
+    color: Color,
+    alpha: f32,
+    base: Box<Bxdf>,
+}
+
+impl Sheen {
+    /// `alpha` is the squared sheen roughness
+    pub fn new(color: Color, alpha: f32, base: Bxdf) -> Self {
+        Self {
+            color,
+            alpha: alpha.max(MIN_ALPHA),
+            base: Box::new(base),
+        }
+    }
+
+    fn base_scale(&self, w: glam::Vec3A) -> f32 {
+        let max = self.color.r.max(self.color.g).max(self.color.b);
+        (1.0 - max * sheen_albedo(self.alpha, w.z)).max(0.0)
+    }
+
+    /// probability of sampling the sheen lobe
+    fn sample_prob(&self, wo: glam::Vec3A) -> f32 {
+        let sheen = self.color.luminance() * sheen_albedo(self.alpha, wo.z);
+        let base = self.base_scale(wo);
+        if sheen + base > 0.0 {
+            sheen / (sheen + base)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl BxdfT for Sheen {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let wo = inputs.wo;
+        let sample_prob = self.sample_prob(wo);
+        let wi = if rng.uniform_1d() < sample_prob {
+            let mut wi = rng.cosine_weighted_on_hemisphere();
+            if wo.z < 0.0 {
+                wi.z = -wi.z;
+            }
+            wi
+        } else {
+            let mut samp = self.base.sample(inputs, rng);
+            if samp.ty.lobe == BxdfLobeType::Specular {
+                samp.bxdf *= self.base_scale(wo) / (1.0 - sample_prob);
+                return samp;
+            }
+            if samp.subsurface.is_some() || samp.wi == glam::Vec3A::ZERO {
+                samp.bxdf *= self.base_scale(wo);
+                samp.pdf *= 1.0 - sample_prob;
+                return samp;
+            }
+            samp.wi
+        };
+
+        BxdfSample {
+            wi,
+            ty: BxdfSampleType {
+                lobe: BxdfLobeType::Glossy,
+                dir: if wo.z * wi.z >= 0.0 {
+                    BxdfDirType::Reflect
+                } else {
+                    BxdfDirType::Transmit
+                },
+                subsurface: false,
+            },
+            bxdf: self.bxdf(wo, wi),
+            pdf: self.pdf(wo, wi),
+            subsurface: None,
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let sample_prob = self.sample_prob(wo);
+        let sheen_pdf = if wo.z * wi.z >= 0.0 {
+            wi.z.abs() * std::f32::consts::FRAC_1_PI
+        } else {
+            0.0
+        };
+        sample_prob * sheen_pdf + (1.0 - sample_prob) * self.base.pdf(wo, wi)
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let base = self.base.bxdf(wo, wi);
+        if wo.z * wi.z >= 0.0 {
+            let scale = self.base_scale(wo).min(self.base_scale(wi));
+            self.color * charlie_bxdf(wo, wi, self.alpha) + base * scale
+        } else {
+            base * self.base_scale(wo)
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
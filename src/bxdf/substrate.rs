@@ -1,5 +1,6 @@
 use crate::{
     core::{color::Color, intersection::Intersection, ray::Ray, rng::Rng},
+    medium::{Homogeneous, MediumT},
     primitive::PrimitiveT,
 };
 
@@ -24,6 +25,7 @@ pub enum Substrate {
     Lambert,
     Diffuse,
     Subsurface,
+    RandomWalk,
 }
 
 impl SubstrateT for Lambert {
@@ -229,6 +231,11 @@ impl Subsurface {
 
 impl SubstrateT for Subsurface {
     fn sample<'a>(&self, inputs: &'a BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let scene = match inputs.scene {
+            Some(scene) => scene,
+            None => return self.diffuse.sample(inputs, rng),
+        };
+
         let mut samp = BxdfSample {
             wi: glam::Vec3A::ZERO,
             ty: BxdfSampleType {
@@ -287,12 +294,18 @@ impl SubstrateT for Subsurface {
         let mut inter = Intersection::with_t_max(2.0 * sample_l);
         let mut intersects = vec![];
         loop {
-            if inputs.scene.intersect(&ray, &mut inter) {
+            if scene.intersect(&ray, &mut inter) {
                 // TODO - check if the intersected one is the same as self
                 let surf = inter.surface.unwrap();
                 let coord_temp = surf.coord(&ray, &inter);
-                intersects.push((ray.point_at(inter.t), inter.normal, coord_temp));
-                ray.t_min = inter.t + Ray::T_MIN_EPS;
+                intersects.push((
+                    inter.position,
+                    inter.p_error,
+                    inter.geo_normal,
+                    inter.normal,
+                    coord_temp,
+                ));
+                ray.t_min = inter.t_after(&ray);
             } else {
                 break;
             }
@@ -302,7 +315,7 @@ impl SubstrateT for Subsurface {
             return samp;
         }
         let sample_inter = ((rand_u * intersects.len() as f32) as usize).min(intersects.len() - 1);
-        let (pi, sample_normal, sample_coord) = intersects[sample_inter];
+        let (pi, pi_error, geo_normal_pi, sample_normal, sample_coord) = intersects[sample_inter];
 
         let sp = self.sp(pi.distance(inputs.po));
 
@@ -318,6 +331,8 @@ impl SubstrateT for Subsurface {
 
         samp.subsurface = Some(BxdfSubsurfaceSample {
             pi,
+            pi_error,
+            geo_normal_pi,
             coord_pi: sample_coord,
             sp,
             pdf_pi,
@@ -348,3 +363,180 @@ impl SubstrateT for Subsurface {
         self.diffuse.reflectance
     }
 }
+
+/// volumetric subsurface scattering by a random walk inside the closed mesh,
+/// the walk is reflected back by the dielectric boundary with probability of fresnel
+/// and otherwise leaves the surface with a white lambert lobe
+pub struct RandomWalk {
+    medium: Homogeneous,
+    reflectance: Color,
+    ior: f32,
+    max_bounces: u32,
+}
+
+impl RandomWalk {
+    /// `reflectance` (multiple-scattering albedo) and mean free path `mfp` are mapped to medium
+    /// coefficients as Christensen and Burley 2015, `sigma_t` is `1 / mfp` and single-scattering
+    /// albedo is inverted from the reflectance by their fit
+    pub fn new(
+        reflectance: Color,
+        mfp: Color,
+        ior: f32,
+        asymmetric: f32,
+        max_bounces: u32,
+    ) -> Self {
+        let single_albedo = |a: f32| {
+            let a = a.clamp(0.0, 1.0);
+            let x = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            (1.0 - x * x).clamp(0.0, 1.0)
+        };
+        let sigma_t = |mfp: f32| 1.0 / mfp.max(1e-6);
+        let sigma_t = Color::new(sigma_t(mfp.r), sigma_t(mfp.g), sigma_t(mfp.b));
+        let single_albedo = Color::new(
+            single_albedo(reflectance.r),
+            single_albedo(reflectance.g),
+            single_albedo(reflectance.b),
+        );
+        let sigma_s = sigma_t * single_albedo;
+
+        Self {
+            medium: Homogeneous::new(sigma_t - sigma_s, sigma_s, asymmetric),
+            reflectance,
+            ior,
+            max_bounces,
+        }
+    }
+
+    /// a sample which ends the path, so that no light is gathered at `po` either
+    fn absorbed(inputs: &BxdfInputs) -> BxdfSample {
+        BxdfSample {
+            wi: glam::Vec3A::ZERO,
+            ty: BxdfSampleType {
+                lobe: BxdfLobeType::Diffuse,
+                dir: BxdfDirType::Reflect,
+                subsurface: true,
+            },
+            bxdf: Color::BLACK,
+            pdf: 1.0,
+            subsurface: Some(BxdfSubsurfaceSample {
+                pi: inputs.po,
+                pi_error: inputs.po_error,
+                geo_normal_pi: inputs.geo_normal_po,
+                coord_pi: inputs.coord_po,
+                sp: Color::BLACK,
+                pdf_pi: 1.0,
+            }),
+        }
+    }
+}
+
+impl SubstrateT for RandomWalk {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let scene = match inputs.scene {
+            Some(scene) => scene,
+            None => return BxdfT::sample(&Lambert::new(self.reflectance), inputs, rng),
+        };
+
+        // enter the surface with a cosine weighted direction
+        let mut wi_enter = -rng.cosine_weighted_on_hemisphere();
+        if inputs.wo.z < 0.0 {
+            wi_enter.z = -wi_enter.z;
+        }
+        let mut ray = Ray::spawn(
+            inputs.po,
+            inputs.po_error,
+            inputs.geo_normal_po,
+            inputs.coord_po.to_world(wi_enter),
+        );
+
+        let mut sp = Color::WHITE;
+        for _ in 0..self.max_bounces {
+            let mut inter = Intersection::default();
+            if !scene.intersect(&ray, &mut inter) {
+                // the mesh is not closed
+                return Self::absorbed(inputs);
+            }
+
+            let (pi, still_in_medium, attenuation) =
+                self.medium
+                    .sample_pi(ray.origin, -ray.direction, inter.t, rng);
+            sp *= attenuation;
+            if still_in_medium {
+                let (wi, _) = self.medium.sample_wi(-ray.direction, rng);
+                ray = Ray::new(pi, wi);
+                continue;
+            }
+
+            // the walk only leaves through the surface it entered,
+            // hitting anything else inside the mesh ends it
+            if let Some(instance) = inputs.instance {
+                let same = inter
+                    .instance
+                    .filter(|inst| std::ptr::eq(*inst, instance))
+                    .is_some();
+                if !same {
+                    return Self::absorbed(inputs);
+                }
+            }
+
+            // reflected with probability of fresnel, so the transmittance 1 - F
+            // cancels out with the probability of leaving
+            let geo_normal_out = if inter.geo_normal.dot(ray.direction) >= 0.0 {
+                inter.geo_normal
+            } else {
+                -inter.geo_normal
+            };
+            let fresnel = util::fresnel_n(self.ior, -ray.direction, geo_normal_out);
+            if rng.uniform_1d() < fresnel {
+                let wi = util::reflect_n(-ray.direction, geo_normal_out);
+                ray = Ray::spawn(inter.position, inter.p_error, inter.geo_normal, wi);
+                continue;
+            }
+
+            // shading frame of the exit point facing outside
+            let surf = inter.surface.unwrap();
+            let coord_pi = surf.coord(&Ray::new(pi, -inter.geo_normal), &inter);
+            let wi = rng.cosine_weighted_on_hemisphere();
+            return BxdfSample {
+                wi,
+                ty: BxdfSampleType {
+                    lobe: BxdfLobeType::Diffuse,
+                    dir: BxdfDirType::Reflect,
+                    subsurface: true,
+                },
+                bxdf: Color::WHITE * std::f32::consts::FRAC_1_PI,
+                pdf: wi.z * std::f32::consts::FRAC_1_PI,
+                subsurface: Some(BxdfSubsurfaceSample {
+                    pi: inter.position,
+                    pi_error: inter.p_error,
+                    geo_normal_pi: inter.geo_normal,
+                    coord_pi,
+                    sp,
+                    pdf_pi: 1.0,
+                }),
+            };
+        }
+
+        Self::absorbed(inputs)
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        if wo.z * wi.z >= 0.0 {
+            wi.z.abs() * std::f32::consts::FRAC_1_PI
+        } else {
+            0.0
+        }
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        if wo.z * wi.z >= 0.0 {
+            Color::WHITE * std::f32::consts::FRAC_1_PI
+        } else {
+            Color::BLACK
+        }
+    }
+
+    fn reflectance(&self) -> Color {
+        self.reflectance
+    }
+}
@@ -133,6 +133,191 @@ pub fn fresnel_moment1(eta: f32) -> f32 {
     }
 }
 
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+
+    fn div(self, rhs: Self) -> Self {
+        let denom = (rhs.re * rhs.re + rhs.im * rhs.im).max(1e-12);
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denom,
+            (self.im * rhs.re - self.re * rhs.im) / denom,
+        )
+    }
+
+    fn sqrt(self) -> Self {
+        let norm = (self.re * self.re + self.im * self.im).sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn arg(self) -> f32 {
+        self.im.atan2(self.re)
+    }
+}
+
+/// reflectance and phase shift of s and p polarization,
+/// from a medium of real `n1` to a medium of complex ior `n2 + i * k2`
+fn fresnel_polarized(cos1: f32, n1: f32, n2: f32, k2: f32) -> ([f32; 2], [f32; 2]) {
+    let n1 = Complex::new(n1, 0.0);
+    let n2 = Complex::new(n2, k2);
+    let cos1 = Complex::new(cos1, 0.0);
+    let sin1_sqr = Complex::new(1.0, 0.0).sub(cos1.mul(cos1));
+    // n2 * cos2
+    let n2_cos2 = n2.mul(n2).sub(n1.mul(n1).mul(sin1_sqr)).sqrt();
+    let n1_cos1 = n1.mul(cos1);
+    let rs = n1_cos1.sub(n2_cos2).div(n1_cos1.add(n2_cos2));
+    let n2_sqr_cos1 = n2.mul(n2).mul(cos1);
+    let n1_n2_cos2 = n1.mul(n2_cos2);
+    let rp = n2_sqr_cos1.sub(n1_n2_cos2).div(n2_sqr_cos1.add(n1_n2_cos2));
+    (
+        [rs.norm_sqr().min(1.0), rp.norm_sqr().min(1.0)],
+        [rs.arg(), rp.arg()],
+    )
+}
+
+/// spectral integration of a cosine with `opd` in nm and `shift`, against xyz sensitivity,
+/// using Gaussian fits in Fourier space of Belcour and Barla 2017
+#[allow(clippy::excessive_precision)]
+fn thin_film_sensitivity(opd: f32, shift: f32) -> [f32; 3] {
+    let phase = 2.0 * std::f32::consts::PI * opd * 1.0e-9;
+    let gaussian = |val: f32, pos: f32, var: f32| {
+        val * (2.0 * std::f32::consts::PI * var).sqrt()
+            * (pos * phase + shift).cos()
+            * (-var * phase * phase).exp()
+    };
+    let x =
+        gaussian(5.4856e-13, 1.6810e+06, 4.3278e+09) + gaussian(9.7470e-14, 2.2399e+06, 4.5282e+09);
+    let y = gaussian(4.4201e-13, 1.7953e+06, 9.3046e+09);
+    let z = gaussian(5.2481e-13, 2.2084e+06, 6.6121e+09);
+    [x / 1.0685e-7, y / 1.0685e-7, z / 1.0685e-7]
+}
+
+#[allow(clippy::excessive_precision)]
+fn xyz_to_rgb(xyz: [f32; 3]) -> Color {
+    Color::new(
+        3.2404542 * xyz[0] - 1.5371385 * xyz[1] - 0.4985314 * xyz[2],
+        -0.9692660 * xyz[0] + 1.8760108 * xyz[1] + 0.0415560 * xyz[2],
+        0.0556434 * xyz[0] - 0.2040259 * xyz[1] + 1.0572252 * xyz[2],
+    )
+}
+
+/// reflectance of a thin film of `film_ior` and `thickness` in nm over a base of `n3 + i * k3`,
+/// all iors are relative to the outside medium
+fn thin_film_airy(cos1: f32, film_ior: f32, thickness: f32, n3: f32, k3: f32) -> Color {
+    // film vanishes smoothly when it is too thin
+    let t = (thickness / 30.0).clamp(0.0, 1.0);
+    let film_ior = 1.0 + (film_ior - 1.0) * t * t * (3.0 - 2.0 * t);
+
+    let cos2_sqr = 1.0 - (1.0 - cos1 * cos1) / (film_ior * film_ior);
+    if cos2_sqr <= 0.0 {
+        return Color::WHITE;
+    }
+    let cos2 = cos2_sqr.sqrt();
+
+    let (r12, phi12) = fresnel_polarized(cos1, 1.0, film_ior, 0.0);
+    let (r23, phi23) = fresnel_polarized(cos2, film_ior, n3, k3);
+    let opd = 2.0 * film_ior * thickness * cos2;
+
+    let mut xyz = [0.0; 3];
+    for pol in 0..2 {
+        let t121 = 1.0 - r12[pol];
+        let r123 = (r12[pol] * r23[pol]).clamp(0.0, 0.9999);
+        let rs = t121 * t121 * r23[pol] / (1.0 - r123);
+        let phi = std::f32::consts::PI - phi12[pol] + phi23[pol];
+
+        let c0 = r12[pol] + rs;
+        let s0 = thin_film_sensitivity(0.0, 0.0);
+        let mut cm = rs - t121;
+        let mut sum = [c0 * s0[0], c0 * s0[1], c0 * s0[2]];
+        for m in 1..=3 {
+            cm *= r123.sqrt();
+            let sm = thin_film_sensitivity(m as f32 * opd, m as f32 * phi);
+            for c in 0..3 {
+                sum[c] += 2.0 * cm * sm[c];
+            }
+        }
+        for c in 0..3 {
+            xyz[c] += 0.5 * sum[c];
+        }
+    }
+
+    // balanced so that a vanished film gives the reflectance of the base
+    let white = xyz_to_rgb(thin_film_sensitivity(0.0, 0.0));
+    let rgb = xyz_to_rgb(xyz) / white;
+    Color::new(
+        rgb.r.clamp(0.0, 1.0),
+        rgb.g.clamp(0.0, 1.0),
+        rgb.b.clamp(0.0, 1.0),
+    )
+}
+
+/// fresnel reflectance of a thin film of `film_ior` and `thickness` in nm over a base of ior
+/// `eta` and `k`, all iors are relative to the outside medium, and the base is a dielectric if
+/// `k` is zero
+pub fn fresnel_thin_film_n(
+    eta: Color,
+    k: Color,
+    film_ior: f32,
+    thickness: f32,
+    i: glam::Vec3A,
+    n: glam::Vec3A,
+) -> Color {
+    let mut cos = i.dot(n);
+    if cos < 0.0 {
+        if k.r == 0.0 && k.g == 0.0 && k.b == 0.0 {
+            // a lossless film stack reflects the same from both sides,
+            // so evaluate it from outside with the direction refracted out
+            let ior = eta.luminance();
+            let sin_sqr = ior * ior * (1.0 - cos * cos);
+            if sin_sqr >= 1.0 {
+                return Color::WHITE;
+            }
+            cos = (1.0 - sin_sqr).sqrt();
+        } else {
+            cos = -cos;
+        }
+    }
+
+    if eta.r == eta.g && eta.r == eta.b && k.r == k.g && k.r == k.b {
+        thin_film_airy(cos, film_ior, thickness, eta.r, k.r)
+    } else {
+        // each channel is evaluated spectrally with the base of that channel
+        Color::new(
+            thin_film_airy(cos, film_ior, thickness, eta.r, k.r).r,
+            thin_film_airy(cos, film_ior, thickness, eta.g, k.g).g,
+            thin_film_airy(cos, film_ior, thickness, eta.b, k.b).b,
+        )
+    }
+}
+
 pub fn half_from_reflect(i: glam::Vec3A, o: glam::Vec3A) -> glam::Vec3A {
     if i.z >= 0.0 {
         (i + o).normalize()
@@ -223,6 +408,228 @@ pub fn ggx_smith_vndf_sample(
     return (ne, pdf);
 }
 
+pub fn beckmann_ndf_aniso(h: glam::Vec3A, ax: f32, ay: f32) -> f32 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let cos2 = pow2(h.z);
+    let tan2 = (pow2(h.x / ax) + pow2(h.y / ay)) / cos2;
+    (-tan2).exp() * std::f32::consts::FRAC_1_PI / (ax * ay * cos2 * cos2).max(0.0001)
+}
+
+/// rational approximation of Smith lambda of beckmann distribution
+pub fn beckmann_lambda_aniso(v: glam::Vec3A, ax: f32, ay: f32) -> f32 {
+    let alpha_tan = (pow2(ax * v.x) + pow2(ay * v.y)).sqrt();
+    if alpha_tan <= 0.0 {
+        return 0.0;
+    }
+    let a = v.z.abs() / alpha_tan;
+    if a >= 1.6 {
+        0.0
+    } else {
+        (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+    }
+}
+
+pub fn beckmann_g1_aniso(v: glam::Vec3A, ax: f32, ay: f32) -> f32 {
+    1.0 / (1.0 + beckmann_lambda_aniso(v, ax, ay))
+}
+
+pub fn beckmann_separable_visible_aniso(v: glam::Vec3A, l: glam::Vec3A, ax: f32, ay: f32) -> f32 {
+    beckmann_g1_aniso(v, ax, ay) * beckmann_g1_aniso(l, ax, ay)
+        / (4.0 * v.z.abs() * l.z.abs()).max(0.0001)
+}
+
+pub fn beckmann_vndf_pdf(h: glam::Vec3A, v: glam::Vec3A, ax: f32, ay: f32) -> f32 {
+    let v = if v.z >= 0.0 { v } else { -v };
+
+    beckmann_g1_aniso(v, ax, ay) * beckmann_ndf_aniso(h, ax, ay) * v.dot(h).max(0.0)
+        / v.z.max(0.0001)
+}
+
+/// visible normal sampling by stretching to unit roughness (Heitz and d'Eon 2014)
+pub fn beckmann_vndf_sample(
+    ve: glam::Vec3A,
+    ax: f32,
+    ay: f32,
+    rand: (f32, f32),
+) -> (glam::Vec3A, f32) {
+    let ve = if ve.z >= 0.0 { ve } else { -ve };
+
+    let vh = glam::Vec3A::new(ax * ve.x, ay * ve.y, ve.z).normalize();
+    let (slope_x, slope_y) = beckmann_sample_slope11(vh.z, rand);
+
+    let sin_theta = (1.0 - vh.z * vh.z).max(0.0).sqrt();
+    let (cos_phi, sin_phi) = if sin_theta > 0.0 {
+        (
+            (vh.x / sin_theta).clamp(-1.0, 1.0),
+            (vh.y / sin_theta).clamp(-1.0, 1.0),
+        )
+    } else {
+        (1.0, 0.0)
+    };
+    let rotated_x = cos_phi * slope_x - sin_phi * slope_y;
+    let rotated_y = sin_phi * slope_x + cos_phi * slope_y;
+    let ne = glam::Vec3A::new(-rotated_x * ax, -rotated_y * ay, 1.0).normalize();
+
+    let pdf = beckmann_vndf_pdf(ne, ve, ax, ay);
+    (ne, pdf)
+}
+
+/// sample slopes of visible normals of unit roughness beckmann distribution,
+/// the incident direction is in xz-plane
+fn beckmann_sample_slope11(cos_theta: f32, rand: (f32, f32)) -> (f32, f32) {
+    if cos_theta > 0.9999 {
+        let r = (-(1.0 - rand.0).max(1e-6).ln()).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * rand.1;
+        return (r * phi.cos(), r * phi.sin());
+    }
+
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let tan_theta = sin_theta / cos_theta.max(0.0001);
+    let cot_theta = 1.0 / tan_theta;
+    let sqrt_pi_inv = 1.0 / std::f32::consts::PI.sqrt();
+
+    // find slope_x by the inverse cdf with a few newton-bisection steps
+    let mut a = -1.0;
+    let mut c = erf(cot_theta);
+    let sample_x = rand.0.max(1e-6);
+    let theta = cos_theta.acos();
+    let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+    let mut b = c - (1.0 + c) * (1.0 - sample_x).powf(fit);
+    let normalization = 1.0 / (1.0 + c + sqrt_pi_inv * tan_theta * (-cot_theta * cot_theta).exp());
+    for _ in 0..9 {
+        if !(b >= a && b <= c) {
+            b = 0.5 * (a + c);
+        }
+        let inv_erf = erf_inv(b);
+        let value = normalization
+            * (1.0 + b + sqrt_pi_inv * tan_theta * (-inv_erf * inv_erf).exp())
+            - sample_x;
+        if value.abs() < 1e-5 {
+            break;
+        }
+        if value > 0.0 {
+            c = b;
+        } else {
+            a = b;
+        }
+        let derivative = normalization * (1.0 - inv_erf * tan_theta);
+        b -= value / derivative;
+    }
+
+    (erf_inv(b), erf_inv(2.0 * rand.1.max(1e-6) - 1.0))
+}
+
+#[allow(clippy::excessive_precision)]
+fn erf(x: f32) -> f32 {
+    let a1 = 0.254829592;
+    let a2 = -0.284496736;
+    let a3 = 1.421413741;
+    let a4 = -1.453152027;
+    let a5 = 1.061405429;
+    let p = 0.3275911;
+
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + p * x);
+    let y = 1.0 - (((((a5 * t + a4) * t) + a3) * t + a2) * t + a1) * t * (-x * x).exp();
+    sign * y
+}
+
+#[allow(clippy::excessive_precision)]
+fn erf_inv(x: f32) -> f32 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let w = -((1.0 - x) * (1.0 + x)).ln();
+    let p = if w < 5.0 {
+        let w = w - 2.5;
+        let mut p = 2.81022636e-08;
+        p = 3.43273939e-07 + p * w;
+        p = -3.5233877e-06 + p * w;
+        p = -4.39150654e-06 + p * w;
+        p = 0.00021858087 + p * w;
+        p = -0.00125372503 + p * w;
+        p = -0.00417768164 + p * w;
+        p = 0.246640727 + p * w;
+        1.50140941 + p * w
+    } else {
+        let w = w.sqrt() - 3.0;
+        let mut p = -0.000200214257;
+        p = 0.000100950558 + p * w;
+        p = 0.00134934322 + p * w;
+        p = -0.00367342844 + p * w;
+        p = 0.00573950773 + p * w;
+        p = -0.0076224613 + p * w;
+        p = 0.00943887047 + p * w;
+        p = 1.00167406 + p * w;
+        2.83297682 + p * w
+    };
+    p * x
+}
+
+/// generalized Trowbridge-Reitz distribution (Burley 2012), `gamma` = 2 is ggx
+pub fn gtr_ndf(ndoth: f32, a2: f32, gamma: f32) -> f32 {
+    if ndoth <= 0.0 {
+        return 0.0;
+    }
+    if a2 >= 0.9999 {
+        return std::f32::consts::FRAC_1_PI;
+    }
+    let a2 = a2.max(1e-6);
+    let norm = if (gamma - 1.0).abs() < 0.001 {
+        (a2 - 1.0) / (std::f32::consts::PI * a2.ln())
+    } else {
+        (gamma - 1.0) * (a2 - 1.0) / (std::f32::consts::PI * (1.0 - a2.powf(1.0 - gamma)))
+    };
+    norm / (1.0 + (a2 - 1.0) * ndoth * ndoth).powf(gamma)
+}
+
+/// return sampled (n dot h)^2
+pub fn gtr_ndf_cdf_inverse(a2: f32, gamma: f32, rand: f32) -> f32 {
+    if a2 >= 0.9999 {
+        return 1.0 - rand;
+    }
+    let a2 = a2.max(1e-6);
+    let t = if (gamma - 1.0).abs() < 0.001 {
+        a2.powf(1.0 - rand)
+    } else {
+        ((1.0 - rand) * a2.powf(1.0 - gamma) + rand).powf(1.0 / (1.0 - gamma))
+    };
+    ((1.0 - t) / (1.0 - a2)).clamp(0.0, 1.0)
+}
+
+/// Charlie sheen distribution (Estevez and Kulla 2017)
+pub fn charlie_ndf(ndoth: f32, alpha: f32) -> f32 {
+    let inv_alpha = 1.0 / alpha;
+    let sin = (1.0 - ndoth * ndoth).max(0.0).sqrt();
+    (2.0 + inv_alpha) * sin.powf(inv_alpha) * 0.5 * std::f32::consts::FRAC_1_PI
+}
+
+fn charlie_lambda_fit(x: f32, alpha: f32) -> f32 {
+    let t = pow2(1.0 - alpha);
+    let a = 21.5473 * (1.0 - t) + 25.3245 * t;
+    let b = 3.82987 * (1.0 - t) + 3.32435 * t;
+    let c = 0.19823 * (1.0 - t) + 0.16801 * t;
+    let d = -1.97760 * (1.0 - t) - 1.27393 * t;
+    let e = -4.32054 * (1.0 - t) - 4.85967 * t;
+    a / (1.0 + b * x.powf(c)) + d * x + e
+}
+
+fn charlie_lambda(cos: f32, alpha: f32) -> f32 {
+    let cos = cos.abs();
+    if cos < 0.5 {
+        charlie_lambda_fit(cos, alpha).exp()
+    } else {
+        (2.0 * charlie_lambda_fit(0.5, alpha) - charlie_lambda_fit(1.0 - cos, alpha)).exp()
+    }
+}
+
+/// height correlated shadowing-masking of Charlie sheen divided by `4 * ndotv * ndotl`
+pub fn charlie_visible(v: glam::Vec3A, l: glam::Vec3A, alpha: f32) -> f32 {
+    let g = 1.0 / (1.0 + charlie_lambda(v.z, alpha) + charlie_lambda(l.z, alpha));
+    g / (4.0 * v.z.abs() * l.z.abs()).max(0.0001)
+}
+
 fn pow2(x: f32) -> f32 {
     x * x
 }
@@ -0,0 +1,134 @@
+use crate::core::{color::Color, rng::Rng};
+
+use super::{BxdfDirType, BxdfInputs, BxdfLobeType, BxdfSample, BxdfSampleType, BxdfT};
+
+/// woven cloth in the style of Irawan and Marschner 2012, a diffuse base with the specular lobe
+/// of the visible thread, which is a Marschner R lobe of a fiber along `tangent`
+pub struct Woven {
+    diffuse: Color,
+    specular: Color,
+    /// local direction of the fiber, may be tilted out of the surface by the yarn curvature
+    tangent: glam::Vec3A,
+    /// standard deviation of the longitudinal lobe in radians
+    roughness: f32,
+}
+
+impl Woven {
+    pub fn new(diffuse: Color, specular: Color, tangent: glam::Vec3A, roughness: f32) -> Self {
+        Self {
+            diffuse,
+            specular,
+            tangent: tangent.normalize(),
+            roughness: roughness.max(0.001),
+        }
+    }
+
+    fn specular_prob(&self) -> f32 {
+        let specular = self.specular.luminance();
+        let diffuse = self.diffuse.luminance();
+        if specular + diffuse > 0.0 {
+            specular / (specular + diffuse)
+        } else {
+            0.0
+        }
+    }
+
+    /// longitudinal angles of `wo`, `wi` and azimuthal angle between them around the fiber
+    fn fiber_angles(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> (f32, f32, f32) {
+        let sin_theta_o = wo.dot(self.tangent).clamp(-1.0, 1.0);
+        let sin_theta_i = wi.dot(self.tangent).clamp(-1.0, 1.0);
+        let wo_perp = (wo - sin_theta_o * self.tangent).normalize_or_zero();
+        let wi_perp = (wi - sin_theta_i * self.tangent).normalize_or_zero();
+        let phi = wo_perp.dot(wi_perp).clamp(-1.0, 1.0).acos();
+        (sin_theta_o.asin(), sin_theta_i.asin(), phi)
+    }
+
+    fn longitudinal(&self, theta_h: f32) -> f32 {
+        (-0.5 * theta_h * theta_h / (self.roughness * self.roughness)).exp()
+            / ((2.0 * std::f32::consts::PI).sqrt() * self.roughness)
+    }
+
+    fn thread_pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        let (theta_o, theta_i, phi) = self.fiber_angles(wo, wi);
+        let theta_h = 0.5 * (theta_o + theta_i);
+        0.5 * self.longitudinal(theta_h) * azimuthal(phi) / theta_i.cos().max(0.0001)
+    }
+
+    fn thread_bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        let (theta_o, theta_i, phi) = self.fiber_angles(wo, wi);
+        let theta_h = 0.5 * (theta_o + theta_i);
+        let cos_theta_d = (0.5 * (theta_o - theta_i)).cos();
+        self.specular * self.longitudinal(theta_h) * azimuthal(phi)
+            / (cos_theta_d * cos_theta_d).max(0.0001)
+    }
+
+    fn sample_thread(&self, wo: glam::Vec3A, rng: &mut Rng) -> glam::Vec3A {
+        let sin_theta_o = wo.dot(self.tangent).clamp(-1.0, 1.0);
+        let theta_h = rng.gaussian_1d(0.0, self.roughness);
+        let theta_i = (2.0 * theta_h - sin_theta_o.asin())
+            .clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+        let phi = 2.0 * (2.0 * rng.uniform_1d() - 1.0).asin();
+
+        let wo_perp = (wo - sin_theta_o * self.tangent).normalize_or_zero();
+        let bitangent = self.tangent.cross(wo_perp);
+        theta_i.sin() * self.tangent + theta_i.cos() * (phi.cos() * wo_perp + phi.sin() * bitangent)
+    }
+}
+
+impl BxdfT for Woven {
+    fn sample(&self, inputs: &BxdfInputs, rng: &mut Rng) -> BxdfSample {
+        let wo = inputs.wo;
+        let wi = if rng.uniform_1d() < self.specular_prob() {
+            self.sample_thread(wo, rng)
+        } else {
+            let mut wi = rng.cosine_weighted_on_hemisphere();
+            if wo.z < 0.0 {
+                wi.z = -wi.z;
+            }
+            wi
+        };
+
+        BxdfSample {
+            wi,
+            ty: BxdfSampleType {
+                lobe: BxdfLobeType::Glossy,
+                dir: if wo.z * wi.z >= 0.0 {
+                    BxdfDirType::Reflect
+                } else {
+                    BxdfDirType::Transmit
+                },
+                subsurface: false,
+            },
+            bxdf: self.bxdf(wo, wi),
+            pdf: self.pdf(wo, wi),
+            subsurface: None,
+        }
+    }
+
+    fn pdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> f32 {
+        if wo.z * wi.z >= 0.0 {
+            let specular_prob = self.specular_prob();
+            specular_prob * self.thread_pdf(wo, wi)
+                + (1.0 - specular_prob) * wi.z.abs() * std::f32::consts::FRAC_1_PI
+        } else {
+            1.0
+        }
+    }
+
+    fn bxdf(&self, wo: glam::Vec3A, wi: glam::Vec3A) -> Color {
+        if wo.z * wi.z >= 0.0 {
+            self.diffuse * std::f32::consts::FRAC_1_PI + self.thread_bxdf(wo, wi)
+        } else {
+            Color::BLACK
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+/// azimuthal distribution of the R lobe of a smooth cylinder, normalized in [-pi, pi]
+fn azimuthal(phi: f32) -> f32 {
+    0.25 * (0.5 * phi).cos()
+}
@@ -12,6 +12,9 @@ use crate::core::{
 pub trait CameraT: Send + Sync {
     fn generate_ray(&self, point: (f32, f32)) -> Ray;
 
+    /// inverse of `generate_ray`, returns `None` if `position` is behind the camera
+    fn project(&self, position: glam::Vec3A) -> Option<(f32, f32)>;
+
     fn generate_ray_with_aux_ray(&self, point: (f32, f32), offset: (f32, f32)) -> Ray {
         let mut ray = self.generate_ray(point);
         let ray_x = self.generate_ray((point.0 + offset.0, point.1));
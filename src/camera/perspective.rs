@@ -45,4 +45,15 @@ impl CameraT for PerspectiveCamera {
                 .normalize();
         Ray::new(origin, direction)
     }
+
+    fn project(&self, position: glam::Vec3A) -> Option<(f32, f32)> {
+        let dir = position - self.eye;
+        let depth = dir.dot(self.forward);
+        if depth > 0.0 {
+            let scale = self.half_cot_half_fov / depth;
+            Some((dir.dot(self.right) * scale, dir.dot(self.up) * scale))
+        } else {
+            None
+        }
+    }
 }
@@ -84,20 +84,12 @@ impl Bbox {
         }
     }
 
-    pub fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        if let Some((t0, t1)) = self.intersect_ray(ray) {
-            t1 > ray.t_min && t0 < t_max
-        } else {
-            false
-        }
-    }
-
     pub fn surface_area(&self) -> f32 {
         if self.is_empty() {
             0.0
         } else {
             let diff = self.p_max - self.p_min;
-            diff.x * diff.y * diff.z
+            2.0 * (diff.x * diff.y + diff.y * diff.z + diff.z * diff.x)
         }
     }
 
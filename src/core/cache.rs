@@ -0,0 +1,229 @@
+use std::{
+    convert::TryInto,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::core::loader::InputParams;
+
+const MAGIC: &[u8; 4] = b"SPTC";
+/// bump when the layout of any cached data changes
+const VERSION: u32 = 3;
+
+/// a binary file of processed primitive data (vertex buffers, patches, flattened bvhs),
+/// named by a hash of the source file content and build parameters
+pub struct Cache {
+    path: PathBuf,
+    key: u64,
+}
+
+pub trait Cacheable: Sized {
+    fn write_cache(&self, writer: &mut CacheWriter);
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self>;
+}
+
+pub struct CacheWriter {
+    data: Vec<u8>,
+}
+
+pub struct CacheReader<'a> {
+    data: &'a [u8],
+}
+
+impl Cache {
+    /// returns `None` if cache is disabled by `"cache": false`
+    pub fn open(
+        params: &mut InputParams,
+        kind: &str,
+        source: &Path,
+        build_params: &str,
+    ) -> anyhow::Result<Option<Self>> {
+        if !params.get_bool_or("cache", true) {
+            return Ok(None);
+        }
+
+        let content = fs::read(source).context(format!(
+            "{} - can't read '{}'",
+            params.name(),
+            source.display()
+        ))?;
+        let key = fnv1a(
+            fnv1a(fnv1a(FNV_OFFSET, kind.as_bytes()), build_params.as_bytes()),
+            &content,
+        );
+        let path = params
+            .cache_dir()
+            .join(format!("{}-{:016x}.bin", kind, key));
+
+        Ok(Some(Self { path, key }))
+    }
+
+    /// loads the cached value if the cache file is valid, otherwise builds and stores it
+    pub fn load_or_build<T: Cacheable>(
+        cache: Option<Self>,
+        build: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let cache = if let Some(cache) = cache {
+            cache
+        } else {
+            return build();
+        };
+
+        if cache.path.exists() {
+            match cache.load() {
+                Ok(value) => {
+                    log::info!("loaded cache '{}'", cache.path.display());
+                    return Ok(value);
+                }
+                Err(err) => log::warn!("invalid cache '{}' - {}", cache.path.display(), err),
+            }
+        }
+
+        let value = build()?;
+        if let Err(err) = cache.store(&value) {
+            log::warn!("failed to write cache '{}' - {}", cache.path.display(), err);
+        }
+        Ok(value)
+    }
+
+    fn load<T: Cacheable>(&self) -> anyhow::Result<T> {
+        let data = fs::read(&self.path)?;
+        let mut reader = CacheReader { data: &data };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            anyhow::bail!("not a cache file");
+        }
+        if reader.read_u32()? != VERSION {
+            anyhow::bail!("version mismatch");
+        }
+        if reader.read_u64()? != self.key {
+            anyhow::bail!("key mismatch");
+        }
+        let value = T::read_cache(&mut reader)?;
+        if !reader.data.is_empty() {
+            anyhow::bail!("trailing data");
+        }
+        Ok(value)
+    }
+
+    fn store<T: Cacheable>(&self, value: &T) -> anyhow::Result<()> {
+        let mut writer = CacheWriter { data: vec![] };
+        writer.data.extend_from_slice(MAGIC);
+        writer.write_u32(VERSION);
+        writer.write_u64(self.key);
+        value.write_cache(&mut writer);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write to a temporary file first so that a partially written cache is never read
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, &writer.data)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl CacheWriter {
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_len(&mut self, len: usize) {
+        self.write_u64(len as u64);
+    }
+
+    pub fn write_vec2(&mut self, value: glam::Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    pub fn write_vec3a(&mut self, value: glam::Vec3A) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+        self.write_f32(value.z);
+    }
+
+    pub fn write_u32s(&mut self, values: &[u32]) {
+        self.write_len(values.len());
+        values.iter().for_each(|value| self.write_u32(*value));
+    }
+
+    pub fn write_slice<T: Cacheable>(&mut self, values: &[T]) {
+        self.write_len(values.len());
+        values.iter().for_each(|value| value.write_cache(self));
+    }
+}
+
+impl<'a> CacheReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < len {
+            anyhow::bail!("unexpected end of cache");
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    pub fn read_u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    pub fn read_f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    /// reads a length of elements which take at least `min_size` bytes each
+    pub fn read_len(&mut self, min_size: usize) -> anyhow::Result<usize> {
+        let len = self.read_u64()? as usize;
+        if len.saturating_mul(min_size) > self.data.len() {
+            anyhow::bail!("unexpected end of cache");
+        }
+        Ok(len)
+    }
+
+    pub fn read_vec2(&mut self) -> anyhow::Result<glam::Vec2> {
+        Ok(glam::Vec2::new(self.read_f32()?, self.read_f32()?))
+    }
+
+    pub fn read_vec3a(&mut self) -> anyhow::Result<glam::Vec3A> {
+        Ok(glam::Vec3A::new(
+            self.read_f32()?,
+            self.read_f32()?,
+            self.read_f32()?,
+        ))
+    }
+
+    pub fn read_u32s(&mut self) -> anyhow::Result<Vec<u32>> {
+        let len = self.read_len(4)?;
+        (0..len).map(|_| self.read_u32()).collect()
+    }
+
+    pub fn read_vec<T: Cacheable>(&mut self) -> anyhow::Result<Vec<T>> {
+        let len = self.read_len(1)?;
+        (0..len).map(|_| T::read_cache(self)).collect()
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// 64-bit fnv-1a, which is stable across runs and platforms unlike `DefaultHasher`
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
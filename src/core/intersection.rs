@@ -1,15 +1,25 @@
 use crate::{
-    core::{ray::Ray, surface::Surface},
+    core::{
+        ray::{self, Ray},
+        surface::Surface,
+        transform::Transform,
+    },
     primitive::{BasicPrimitiveRef, Instance},
 };
 
 pub struct Intersection<'a> {
     pub t: f32,
     pub position: glam::Vec3A,
+    /// conservative bound of the absolute error of `position` on each axis
+    pub p_error: glam::Vec3A,
+    /// normal of the actual geometry, while `normal` may be interpolated or bent
+    pub geo_normal: glam::Vec3A,
     pub tangent: glam::Vec3A,
     pub bitangent: glam::Vec3A,
     pub normal: glam::Vec3A,
     pub texcoords: glam::Vec2,
+    /// parametric coordinates on `primitive`, only used by Bezier patch and heightfield now
+    pub prim_uv: glam::Vec2,
     pub instance: Option<&'a Instance>,
     pub primitive: Option<BasicPrimitiveRef<'a>>,
     pub surface: Option<&'a Surface>,
@@ -25,6 +35,26 @@ impl Intersection<'_> {
         }
     }
 
+    /// smallest `t` along `ray` that is surely past this intersection,
+    /// used to continue tracing from the intersection without hitting it again
+    pub fn t_after(&self, ray: &Ray) -> f32 {
+        let cos = ray.direction.dot(self.geo_normal).abs();
+        let dist = if cos > 0.0 {
+            self.geo_normal.abs().dot(self.p_error) / cos
+        } else {
+            self.p_error.length() / ray.direction.length()
+        };
+        ray::next_float_up(self.t + dist)
+    }
+
+    /// transforms `position` with its error bound and `geo_normal`
+    pub fn transform_position(&mut self, trans: &Transform) {
+        let (position, p_error) = trans.transform_point3a_with_error(self.position, self.p_error);
+        self.position = position;
+        self.p_error = p_error;
+        self.geo_normal = trans.transform_normal3a(self.geo_normal);
+    }
+
     pub fn calc_differential(&mut self, ray: &Ray) {
         if let Some(aux_ray) = &ray.aux_ray {
             let p = ray.point_at(self.t);
@@ -88,10 +118,13 @@ impl Default for Intersection<'_> {
         Self {
             t: f32::MAX,
             position: glam::Vec3A::ZERO,
+            p_error: glam::Vec3A::ZERO,
+            geo_normal: glam::Vec3A::Z,
             tangent: glam::Vec3A::X,
             bitangent: glam::Vec3A::Y,
             normal: glam::Vec3A::Z,
             texcoords: glam::Vec2::ZERO,
+            prim_uv: glam::Vec2::ZERO,
             instance: None,
             primitive: None,
             surface: None,
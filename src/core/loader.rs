@@ -22,6 +22,7 @@ pub enum InputParamsValue {
     Bool(bool),
     String(String),
     Array(Vec<InputParamsValue>),
+    Object(HashMap<String, InputParamsValue>),
 }
 
 macro_rules! params_get {
@@ -334,6 +335,57 @@ impl InputParams {
         anyhow::bail!(format!("{} - there is no '{}' field", self.name, key));
     }
 
+    /// takes a nested object as params of its own, named `<name>.<key>`
+    pub fn get_params(&mut self, key: &str) -> anyhow::Result<InputParams> {
+        match self.params.remove(key) {
+            Some(InputParamsValue::Object(params)) => Ok(self.nested(key, params)),
+            Some(value) => {
+                self.params.insert(key.to_owned(), value);
+                anyhow::bail!(format!("{} - '{}' should be an object", self.name, key))
+            }
+            None => anyhow::bail!(format!("{} - there is no '{}' field", self.name, key)),
+        }
+    }
+
+    /// takes an array of nested objects, see `get_params`
+    pub fn get_params_array(&mut self, key: &str) -> anyhow::Result<Vec<InputParams>> {
+        match self.params.remove(key) {
+            Some(InputParamsValue::Array(arr))
+                if arr
+                    .iter()
+                    .all(|value| matches!(value, InputParamsValue::Object(_))) =>
+            {
+                Ok(arr
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| match value {
+                        InputParamsValue::Object(params) => {
+                            self.nested(&format!("{}[{}]", key, i), params)
+                        }
+                        _ => unreachable!(),
+                    })
+                    .collect())
+            }
+            Some(value) => {
+                self.params.insert(key.to_owned(), value);
+                anyhow::bail!(format!(
+                    "{} - '{}' should be an array of objects",
+                    self.name, key
+                ))
+            }
+            None => anyhow::bail!(format!("{} - there is no '{}' field", self.name, key)),
+        }
+    }
+
+    fn nested(&self, key: &str, params: HashMap<String, InputParamsValue>) -> InputParams {
+        InputParams {
+            params,
+            name: Cow::Owned(format!("{}.{}", self.name, key)),
+            visited_names: HashSet::new(),
+            base_path: self.base_path.clone(),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn get_str(&mut self, key: &str) -> anyhow::Result<String> {
         if let Some(value) = self.params.get(key) {
@@ -355,6 +407,27 @@ impl InputParams {
         }
     }
 
+    #[allow(dead_code)]
+    pub fn get_str_array(&mut self, key: &str) -> anyhow::Result<Vec<String>> {
+        if let Some(value) = self.params.get(key) {
+            let error_info = format!("{} - '{}' should be array of strings", self.name, key);
+            if let InputParamsValue::Array(arr) = value {
+                let mut result = Vec::with_capacity(arr.len());
+                for ele in arr {
+                    if let InputParamsValue::String(ele) = ele {
+                        result.push(ele.clone());
+                    } else {
+                        anyhow::bail!(error_info);
+                    }
+                }
+                self.visited_names.insert(key.to_owned());
+                return Ok(result);
+            }
+            anyhow::bail!(error_info);
+        }
+        anyhow::bail!(format!("{} - there is no '{}' field", self.name, key));
+    }
+
     #[allow(dead_code)]
     pub fn get_file_path(&mut self, key: &str) -> anyhow::Result<PathBuf> {
         let filename = self.get_str(key)?;
@@ -362,6 +435,11 @@ impl InputParams {
         Ok(path)
     }
 
+    /// directory of processed data caches, next to the scene file
+    pub fn cache_dir(&self) -> PathBuf {
+        self.base_path.with_file_name(".cache")
+    }
+
     #[allow(dead_code)]
     pub fn get_image(&mut self, key: &str) -> anyhow::Result<image::DynamicImage> {
         let filename = self.get_str(key)?;
@@ -433,14 +511,25 @@ impl TryFrom<&serde_json::Value> for InputParamsValue {
                     match v.try_into() {
                         Ok(v) => values.push(v),
                         Err(e) => {
-                            anyhow::bail!(format!("can't convert array element: {}", e.to_string()))
+                            anyhow::bail!(format!("can't convert array element: {}", e))
                         }
                     }
                 }
                 Ok(Self::Array(values))
             }
-            serde_json::Value::Object(_) => {
-                anyhow::bail!("can't convert to InputParamsValue from object json")
+            serde_json::Value::Object(obj) => {
+                let mut values = HashMap::<String, InputParamsValue>::with_capacity(obj.len());
+                for (k, v) in obj {
+                    match v.try_into() {
+                        Ok(v) => {
+                            values.insert(k.clone(), v);
+                        }
+                        Err(e) => {
+                            anyhow::bail!(format!("can't convert member '{}': {}", k, e))
+                        }
+                    }
+                }
+                Ok(Self::Object(values))
             }
         }
     }
@@ -469,14 +558,25 @@ impl TryFrom<serde_json::Value> for InputParamsValue {
                     match v.try_into() {
                         Ok(v) => values.push(v),
                         Err(e) => {
-                            anyhow::bail!(format!("can't convert array element: {}", e.to_string()))
+                            anyhow::bail!(format!("can't convert array element: {}", e))
                         }
                     }
                 }
                 Ok(Self::Array(values))
             }
-            serde_json::Value::Object(_) => {
-                anyhow::bail!("can't convert to InputParamsValue from object json")
+            serde_json::Value::Object(obj) => {
+                let mut values = HashMap::<String, InputParamsValue>::with_capacity(obj.len());
+                for (k, v) in obj {
+                    match v.try_into() {
+                        Ok(v) => {
+                            values.insert(k.clone(), v);
+                        }
+                        Err(e) => {
+                            anyhow::bail!(format!("can't convert member '{}': {}", k, e))
+                        }
+                    }
+                }
+                Ok(Self::Object(values))
             }
         }
     }
@@ -494,7 +594,7 @@ impl TryFrom<&serde_json::Value> for InputParams {
                         params.insert(k.clone(), v);
                     }
                     Err(e) => {
-                        anyhow::bail!(format!("can't convert member '{}': {}", k, e.to_string()))
+                        anyhow::bail!(format!("can't convert member '{}': {}", k, e))
                     }
                 }
             }
@@ -522,7 +622,7 @@ impl TryFrom<serde_json::Value> for InputParams {
                         params.insert(k, v);
                     }
                     Err(e) => {
-                        anyhow::bail!(format!("can't convert member '{}': {}", k, e.to_string()))
+                        anyhow::bail!(format!("can't convert member '{}': {}", k, e))
                     }
                 }
             }
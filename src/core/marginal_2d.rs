@@ -0,0 +1,329 @@
+/// largest f32 less than 1
+const ONE_MINUS_EPSILON: f32 = 0.99999994;
+
+/// max number of extra parameters, the number of interpolated slices is `2 ^ MAX_PARAMS`
+const MAX_PARAMS: usize = 3;
+
+/// piecewise bilinear distribution on `[0, 1]^2` given by values on a regular grid,
+/// which can depend on extra parameters by linear interpolation between slices,
+/// in the same way as `Marginal2D` of Mitsuba
+pub struct Marginal2D {
+    size_x: usize,
+    size_y: usize,
+    /// grid values of each extra parameter
+    param_values: Vec<Vec<f32>>,
+    /// stride of each extra parameter in number of slices, the last one is the innermost
+    param_strides: Vec<usize>,
+    data: Vec<f32>,
+    marginal_cdf: Vec<f32>,
+    conditional_cdf: Vec<f32>,
+}
+
+/// slices to interpolate and their weights for some values of extra parameters
+struct Slices {
+    offsets: [usize; 1 << MAX_PARAMS],
+    weights: [f32; 1 << MAX_PARAMS],
+    len: usize,
+}
+
+impl Marginal2D {
+    /// `data` is of `size_y` rows of `size_x` values for each slice,
+    /// if `build_cdf` is false, it can only be evaluated and is normalized only if `normalize`
+    pub fn new(
+        size_x: usize,
+        size_y: usize,
+        data: &[f32],
+        param_values: Vec<Vec<f32>>,
+        normalize: bool,
+        build_cdf: bool,
+    ) -> Self {
+        assert!(param_values.len() <= MAX_PARAMS);
+        assert!(size_x >= 2 && size_y >= 2);
+
+        let mut param_strides = vec![0; param_values.len()];
+        let mut slices = 1;
+        for (dim, values) in param_values.iter().enumerate().rev() {
+            param_strides[dim] = if values.len() > 1 { slices } else { 0 };
+            slices *= values.len();
+        }
+
+        let n_values = size_x * size_y;
+        let inv_patch_area = ((size_x - 1) * (size_y - 1)) as f32;
+        let mut data_out = vec![0.0; slices * n_values];
+        let mut marginal_cdf = vec![];
+        let mut conditional_cdf = vec![];
+
+        if build_cdf {
+            marginal_cdf = vec![0.0; slices * size_y];
+            conditional_cdf = vec![0.0; slices * n_values];
+
+            for slice in 0..slices {
+                let data = &data[slice * n_values..(slice + 1) * n_values];
+                let conditional = &mut conditional_cdf[slice * n_values..(slice + 1) * n_values];
+                let marginal = &mut marginal_cdf[slice * size_y..(slice + 1) * size_y];
+
+                for y in 0..size_y {
+                    let mut sum = 0.0f64;
+                    let row = y * size_x;
+                    conditional[row] = 0.0;
+                    for x in 0..size_x - 1 {
+                        sum += 0.5 * (data[row + x] as f64 + data[row + x + 1] as f64);
+                        conditional[row + x + 1] = sum as f32;
+                    }
+                }
+
+                let mut sum = 0.0f64;
+                marginal[0] = 0.0;
+                for y in 0..size_y - 1 {
+                    sum += 0.5
+                        * (conditional[(y + 1) * size_x - 1] as f64
+                            + conditional[(y + 2) * size_x - 1] as f64);
+                    marginal[y + 1] = sum as f32;
+                }
+
+                let normalization = 1.0 / marginal[size_y - 1];
+                conditional
+                    .iter_mut()
+                    .for_each(|value| *value *= normalization);
+                marginal
+                    .iter_mut()
+                    .for_each(|value| *value *= normalization);
+                for (i, value) in data.iter().enumerate() {
+                    data_out[slice * n_values + i] = value * normalization;
+                }
+            }
+        } else {
+            for slice in 0..slices {
+                let data = &data[slice * n_values..(slice + 1) * n_values];
+                let normalization = if normalize {
+                    let mut sum = 0.0f64;
+                    for y in 0..size_y - 1 {
+                        for x in 0..size_x - 1 {
+                            let i = y * size_x + x;
+                            sum += 0.25
+                                * (data[i] + data[i + 1] + data[i + size_x] + data[i + size_x + 1])
+                                    as f64;
+                        }
+                    }
+                    (1.0 / sum) as f32
+                } else {
+                    1.0 / inv_patch_area
+                };
+                for (i, value) in data.iter().enumerate() {
+                    data_out[slice * n_values + i] = value * normalization;
+                }
+            }
+        }
+
+        Self {
+            size_x,
+            size_y,
+            param_values,
+            param_strides,
+            data: data_out,
+            marginal_cdf,
+            conditional_cdf,
+        }
+    }
+
+    /// warps a uniform sample to the distribution, returns the warped point and its pdf
+    pub fn sample(&self, sample: glam::Vec2, params: &[f32]) -> (glam::Vec2, f32) {
+        let slices = self.slices(params);
+        let size_x = self.size_x;
+        let slice_size = self.size_x * self.size_y;
+        let mut sample = sample.clamp(
+            glam::Vec2::splat(1.0 - ONE_MINUS_EPSILON),
+            glam::Vec2::splat(ONE_MINUS_EPSILON),
+        );
+
+        // sample the row first
+        let fetch_marginal = |ind: usize| lookup(&self.marginal_cdf, ind, self.size_y, &slices);
+        let row = find_interval(self.size_y, |ind| fetch_marginal(ind) < sample.y);
+        sample.y -= fetch_marginal(row);
+
+        let offset = row * size_x;
+        let r0 = lookup(
+            &self.conditional_cdf,
+            offset + size_x - 1,
+            slice_size,
+            &slices,
+        );
+        let r1 = lookup(
+            &self.conditional_cdf,
+            offset + size_x * 2 - 1,
+            slice_size,
+            &slices,
+        );
+        sample.y = solve_linear_pdf(sample.y, r0, r1);
+
+        // then the column
+        sample.x *= (1.0 - sample.y) * r0 + sample.y * r1;
+        let fetch_conditional = |ind: usize| {
+            let v0 = lookup(&self.conditional_cdf, offset + ind, slice_size, &slices);
+            let v1 = lookup(
+                &self.conditional_cdf,
+                offset + size_x + ind,
+                slice_size,
+                &slices,
+            );
+            (1.0 - sample.y) * v0 + sample.y * v1
+        };
+        let col = find_interval(size_x, |ind| fetch_conditional(ind) < sample.x);
+        sample.x -= fetch_conditional(col);
+
+        let offset = offset + col;
+        let v00 = lookup(&self.data, offset, slice_size, &slices);
+        let v10 = lookup(&self.data, offset + 1, slice_size, &slices);
+        let v01 = lookup(&self.data, offset + size_x, slice_size, &slices);
+        let v11 = lookup(&self.data, offset + size_x + 1, slice_size, &slices);
+        let c0 = (1.0 - sample.y) * v00 + sample.y * v01;
+        let c1 = (1.0 - sample.y) * v10 + sample.y * v11;
+        sample.x = solve_linear_pdf(sample.x, c0, c1);
+
+        let pos = (glam::Vec2::new(col as f32, row as f32) + sample) / self.patch_counts();
+        let pdf = ((1.0 - sample.x) * c0 + sample.x * c1) * self.inv_patch_area();
+        (pos, pdf)
+    }
+
+    /// inverse of `sample`, returns the uniform sample which is warped to `pos` and the pdf
+    pub fn invert(&self, pos: glam::Vec2, params: &[f32]) -> (glam::Vec2, f32) {
+        let slices = self.slices(params);
+        let size_x = self.size_x;
+        let slice_size = self.size_x * self.size_y;
+
+        let (col, row, mut sample) = self.patch(pos);
+
+        let offset = col + row * size_x;
+        let v00 = lookup(&self.data, offset, slice_size, &slices);
+        let v10 = lookup(&self.data, offset + 1, slice_size, &slices);
+        let v01 = lookup(&self.data, offset + size_x, slice_size, &slices);
+        let v11 = lookup(&self.data, offset + size_x + 1, slice_size, &slices);
+        let c0 = (1.0 - sample.y) * v00 + sample.y * v01;
+        let c1 = (1.0 - sample.y) * v10 + sample.y * v11;
+        let pdf = (1.0 - sample.x) * c0 + sample.x * c1;
+
+        // invert the column
+        sample.x *= c0 + 0.5 * sample.x * (c1 - c0);
+        let v0 = lookup(&self.conditional_cdf, offset, slice_size, &slices);
+        let v1 = lookup(&self.conditional_cdf, offset + size_x, slice_size, &slices);
+        sample.x += (1.0 - sample.y) * v0 + sample.y * v1;
+
+        let offset = row * size_x;
+        let r0 = lookup(
+            &self.conditional_cdf,
+            offset + size_x - 1,
+            slice_size,
+            &slices,
+        );
+        let r1 = lookup(
+            &self.conditional_cdf,
+            offset + size_x * 2 - 1,
+            slice_size,
+            &slices,
+        );
+        sample.x /= (1.0 - sample.y) * r0 + sample.y * r1;
+
+        // then the row
+        sample.y *= r0 + 0.5 * sample.y * (r1 - r0);
+        sample.y += lookup(&self.marginal_cdf, row, self.size_y, &slices);
+
+        (sample, pdf * self.inv_patch_area())
+    }
+
+    /// bilinear interpolated value at `pos`, which is the pdf if the data is normalized
+    pub fn eval(&self, pos: glam::Vec2, params: &[f32]) -> f32 {
+        let slices = self.slices(params);
+        let size_x = self.size_x;
+        let slice_size = self.size_x * self.size_y;
+
+        let (col, row, w1) = self.patch(pos);
+        let w0 = glam::Vec2::ONE - w1;
+
+        let offset = col + row * size_x;
+        let v00 = lookup(&self.data, offset, slice_size, &slices);
+        let v10 = lookup(&self.data, offset + 1, slice_size, &slices);
+        let v01 = lookup(&self.data, offset + size_x, slice_size, &slices);
+        let v11 = lookup(&self.data, offset + size_x + 1, slice_size, &slices);
+        (w0.y * (w0.x * v00 + w1.x * v10) + w1.y * (w0.x * v01 + w1.x * v11))
+            * self.inv_patch_area()
+    }
+
+    fn patch_counts(&self) -> glam::Vec2 {
+        glam::Vec2::new((self.size_x - 1) as f32, (self.size_y - 1) as f32)
+    }
+
+    fn inv_patch_area(&self) -> f32 {
+        ((self.size_x - 1) * (self.size_y - 1)) as f32
+    }
+
+    /// column and row of the patch containing `pos`, and the position in the patch
+    fn patch(&self, pos: glam::Vec2) -> (usize, usize, glam::Vec2) {
+        let pos = (pos * self.patch_counts()).max(glam::Vec2::ZERO);
+        let col = (pos.x as usize).min(self.size_x - 2);
+        let row = (pos.y as usize).min(self.size_y - 2);
+        (col, row, pos - glam::Vec2::new(col as f32, row as f32))
+    }
+
+    fn slices(&self, params: &[f32]) -> Slices {
+        let mut slices = Slices {
+            offsets: [0; 1 << MAX_PARAMS],
+            weights: [0.0; 1 << MAX_PARAMS],
+            len: 1,
+        };
+        slices.weights[0] = 1.0;
+
+        for (dim, values) in self.param_values.iter().enumerate() {
+            if values.len() == 1 {
+                continue;
+            }
+
+            let ind = find_interval(values.len(), |ind| values[ind] <= params[dim]);
+            let weight =
+                ((params[dim] - values[ind]) / (values[ind + 1] - values[ind])).clamp(0.0, 1.0);
+            let stride = self.param_strides[dim];
+            let len = slices.len;
+            for i in 0..len {
+                slices.offsets[len + i] = slices.offsets[i] + (ind + 1) * stride;
+                slices.weights[len + i] = slices.weights[i] * weight;
+                slices.offsets[i] += ind * stride;
+                slices.weights[i] *= 1.0 - weight;
+            }
+            slices.len *= 2;
+        }
+
+        slices
+    }
+}
+
+/// value at `ind` interpolated between slices, each slice has `size` values
+fn lookup(data: &[f32], ind: usize, size: usize, slices: &Slices) -> f32 {
+    (0..slices.len)
+        .map(|i| slices.weights[i] * data[ind + slices.offsets[i] * size])
+        .sum()
+}
+
+/// the largest index `i` in `[0, size - 2]` such that `pred(i)` is true, `pred` should be monotonic
+fn find_interval<F: Fn(usize) -> bool>(size: usize, pred: F) -> usize {
+    let mut first = 1;
+    let mut len = size as isize - 2;
+    while len > 0 {
+        let half = len >> 1;
+        let middle = first + half as usize;
+        if pred(middle) {
+            first = middle + 1;
+            len -= half + 1;
+        } else {
+            len = half;
+        }
+    }
+    (first.max(1) - 1).min(size - 2)
+}
+
+/// inverts the cdf of a linear pdf on `[0, 1]` from `v0` to `v1`, which is not normalized
+fn solve_linear_pdf(sample: f32, v0: f32, v1: f32) -> f32 {
+    if (v0 - v1).abs() < 1e-4 * (v0 + v1) {
+        2.0 * sample / (v0 + v1)
+    } else {
+        (v0 - (v0 * v0 - 2.0 * sample * (v0 - v1)).max(0.0).sqrt()) / (v0 - v1)
+    }
+}
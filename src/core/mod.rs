@@ -1,10 +1,12 @@
 pub mod alias_table;
 pub mod bbox;
+pub mod cache;
 pub mod color;
 pub mod coord;
 pub mod film;
 pub mod intersection;
 pub mod loader;
+pub mod marginal_2d;
 pub mod ray;
 pub mod rng;
 pub mod scene;
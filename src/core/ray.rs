@@ -15,17 +15,33 @@ pub struct AuxiliaryRay {
 }
 
 impl Ray {
+    /// a shadow ray towards a point `dist` away stops at `dist * (1 - SHADOW_EPS)`
+    /// so that the surface the point lies on is not hit
+    pub const SHADOW_EPS: f32 = 0.0001;
+    /// `t_min` of rays leaving a point whose error bounds are unknown
     pub const T_MIN_EPS: f32 = 0.0001;
 
     pub fn new(origin: glam::Vec3A, direction: glam::Vec3A) -> Self {
         Self {
             origin,
             direction,
-            t_min: Self::T_MIN_EPS,
+            t_min: 0.0,
             aux_ray: None,
         }
     }
 
+    /// ray leaving a surface point `p` with error bound `p_error`, the origin is pushed out of
+    /// the error bounds along geometric normal `n` to the side `direction` points to,
+    /// so the ray never hits the surface it starts from
+    pub fn spawn(
+        p: glam::Vec3A,
+        p_error: glam::Vec3A,
+        n: glam::Vec3A,
+        direction: glam::Vec3A,
+    ) -> Self {
+        Self::new(offset_ray_origin(p, p_error, n, direction), direction)
+    }
+
     pub fn point_at(&self, t: f32) -> glam::Vec3A {
         self.origin + self.direction * t
     }
@@ -59,3 +75,55 @@ impl AuxiliaryRay {
         self.y_origin + self.y_direction * t
     }
 }
+
+/// bound of the relative error accumulated by `n` floating-point operations
+pub fn gamma(n: u32) -> f32 {
+    let n_eps = n as f32 * f32::EPSILON * 0.5;
+    n_eps / (1.0 - n_eps)
+}
+
+pub fn next_float_up(v: f32) -> f32 {
+    if v.is_infinite() && v > 0.0 {
+        return v;
+    }
+    // -0.0 and 0.0 have different bits
+    let v = if v == 0.0 { 0.0 } else { v };
+    let bits = v.to_bits();
+    let bits = if v >= 0.0 { bits + 1 } else { bits - 1 };
+    f32::from_bits(bits)
+}
+
+pub fn next_float_down(v: f32) -> f32 {
+    if v.is_infinite() && v < 0.0 {
+        return v;
+    }
+    let v = if v == 0.0 { -0.0 } else { v };
+    let bits = v.to_bits();
+    let bits = if v > 0.0 { bits - 1 } else { bits + 1 };
+    f32::from_bits(bits)
+}
+
+/// offsets `p` along `n` by the projection of its error box onto `n`,
+/// then rounds away from `p` so that the offset point is not rounded back into the box
+pub fn offset_ray_origin(
+    p: glam::Vec3A,
+    p_error: glam::Vec3A,
+    n: glam::Vec3A,
+    direction: glam::Vec3A,
+) -> glam::Vec3A {
+    let d = n.abs().dot(p_error);
+    let offset = if direction.dot(n) < 0.0 {
+        -d * n
+    } else {
+        d * n
+    };
+    let mut po = p + offset;
+    for axis in 0..3 {
+        if offset[axis] > 0.0 {
+            po[axis] = next_float_up(po[axis]);
+        } else if offset[axis] < 0.0 {
+            po[axis] = next_float_down(po[axis]);
+        }
+    }
+    po
+}
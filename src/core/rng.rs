@@ -11,6 +11,13 @@ impl Rng {
         }
     }
 
+    /// deterministic sequence, used where results should be reproducible
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            rng: rand::rngs::SmallRng::seed_from_u64(seed),
+        }
+    }
+
     pub fn uniform_1d(&mut self) -> f32 {
         rand::Rng::gen(&mut self.rng)
     }
@@ -7,7 +7,7 @@ use crate::{
     light_sampler::{LightSampler, PowerIsLightSampler, UniformLightSampler},
     material::Material,
     medium::Medium,
-    primitive::{BvhAccel, Group, Instance, InstancePtr, Primitive},
+    primitive::{Bvh4Accel, Bvh8Accel, BvhAccel, Group, Instance, InstancePtr, Primitive},
     texture::Texture,
 };
 
@@ -23,6 +23,8 @@ pub struct SceneResources {
     lights: HashMap<String, Arc<Light>>,
     environment: Option<Arc<Light>>,
     environment_light_index: Option<usize>,
+    /// store vertices of triangle meshes in `CompactVertices`
+    compact_meshes: bool,
 }
 
 impl SceneResources {
@@ -93,6 +95,8 @@ impl SceneResources {
             match ty {
                 "group" => Group::new(instances).into(),
                 "bvh" => BvhAccel::new(instances, 4, 16).into(),
+                "bvh4" => Bvh4Accel::new(instances, 4, 16).into(),
+                "bvh8" => Bvh8Accel::new(instances, 4, 16).into(),
                 _ => anyhow::bail!(format!("Unknown aggregate type '{}'", ty)),
             }
         } else {
@@ -146,6 +150,14 @@ impl SceneResources {
         }
     }
 
+    pub fn clone_camera(&self, name: String) -> anyhow::Result<Arc<Camera>> {
+        if let Some(camera) = self.cameras.get(&name) {
+            Ok(camera.clone())
+        } else {
+            anyhow::bail!(format!("There is no camera named '{}'", name))
+        }
+    }
+
     pub fn add_light(&mut self, name: String, light: Light) -> anyhow::Result<()> {
         if self.lights.contains_key(&name) {
             anyhow::bail!(format!("Duplicated light name '{}'", name));
@@ -176,6 +188,14 @@ impl SceneResources {
         }
     }
 
+    pub fn clone_instance(&self, name: String) -> anyhow::Result<Arc<Instance>> {
+        if let Some(instance) = self.instances.get(&name) {
+            Ok(instance.clone())
+        } else {
+            anyhow::bail!(format!("There is no instance named '{}'", name))
+        }
+    }
+
     pub fn add_material(&mut self, name: String, material: Material) -> anyhow::Result<()> {
         if self.materials.contains_key(&name) {
             anyhow::bail!(format!("Duplicated material name '{}'", name));
@@ -261,6 +281,14 @@ impl SceneResources {
         }
     }
 
+    pub fn set_compact_meshes(&mut self, compact_meshes: bool) {
+        self.compact_meshes = compact_meshes;
+    }
+
+    pub fn compact_meshes(&self) -> bool {
+        self.compact_meshes
+    }
+
     pub fn merge(&mut self, another: SceneResources) {
         for (name, cam) in another.cameras {
             if !self.cameras.contains_key(&name) {
@@ -8,13 +8,115 @@ use crate::{
     },
     material::{Material, MaterialT},
     medium::Medium,
-    texture::{Texture, TextureT},
+    primitive::Displacement,
+    texture::{Texture, TextureChannel, TextureT},
 };
 
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    /// hits with opacity below the cutoff are ignored
+    Mask(f32),
+    /// hits are ignored with probability of `1 - opacity`
+    Blend,
+}
+
+pub struct Opacity {
+    factor: f32,
+    map: Option<Arc<Texture>>,
+    channel: TextureChannel,
+    mode: AlphaMode,
+}
+
+impl Opacity {
+    pub fn new(
+        factor: f32,
+        map: Option<Arc<Texture>>,
+        channel: TextureChannel,
+        mode: AlphaMode,
+    ) -> Self {
+        Self {
+            factor,
+            map,
+            channel,
+            mode,
+        }
+    }
+
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let factor = params.get_float_or("opacity", 1.0);
+        let map = if params.contains_key("opacity_map") {
+            Some(rsc.clone_texture(params.get_str("opacity_map")?)?)
+        } else {
+            None
+        };
+        let channel = match params.get_str_or("opacity_channel", "r").as_str() {
+            "r" => TextureChannel::R,
+            "a" => TextureChannel::A,
+            chan => anyhow::bail!(format!(
+                "{} - unknown opacity channel '{}'",
+                params.name(),
+                chan
+            )),
+        };
+        let mode = match params.get_str_or("alpha_mode", "mask").as_str() {
+            "mask" => AlphaMode::Mask(params.get_float_or("alpha_cutoff", 0.5)),
+            "blend" => AlphaMode::Blend,
+            mode => anyhow::bail!(format!(
+                "{} - unknown alpha mode '{}'",
+                params.name(),
+                mode
+            )),
+        };
+
+        Ok(Self::new(factor, map, channel, mode))
+    }
+
+    fn opacity_at(&self, inter: &Intersection<'_>) -> f32 {
+        self.factor
+            * self
+                .map
+                .as_ref()
+                .map_or(1.0, |map| map.float_at(inter.into(), self.channel))
+    }
+
+    pub fn is_transparent(&self, ray: &Ray, inter: &Intersection<'_>) -> bool {
+        let opacity = self.opacity_at(inter);
+        match self.mode {
+            AlphaMode::Mask(cutoff) => opacity < cutoff,
+            AlphaMode::Blend => opacity < hash_ray_hit(ray, inter.t),
+        }
+    }
+}
+
+/// a deterministic random number in [0, 1) for stochastic transparency,
+/// ray traversal has no access to the rng
+fn hash_ray_hit(ray: &Ray, t: f32) -> f32 {
+    let values = [
+        ray.origin.x,
+        ray.origin.y,
+        ray.origin.z,
+        ray.direction.x,
+        ray.direction.y,
+        ray.direction.z,
+        t,
+    ];
+    let mut hash = 0x811c9dc5_u32;
+    for value in values.iter() {
+        hash = (hash ^ value.to_bits()).wrapping_mul(0x01000193);
+    }
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^= hash >> 16;
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
+
 pub struct Surface {
     material: Arc<Material>,
     normal_map: Option<Arc<Texture>>,
-    _displacement_map: Option<Arc<Texture>>, // TODO: disp map, not supported
+    displacement: Option<Displacement>,
+    opacity: Option<Opacity>,
     emissive: Color,
     emissive_map: Option<Arc<Texture>>,
     double_sided: bool,
@@ -22,10 +124,12 @@ pub struct Surface {
 }
 
 impl Surface {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         material: Arc<Material>,
         normal_map: Option<Arc<Texture>>,
-        displacement_map: Option<Arc<Texture>>,
+        displacement: Option<Displacement>,
+        opacity: Option<Opacity>,
         emissive: Color,
         emissive_map: Option<Arc<Texture>>,
         double_sided: bool,
@@ -34,7 +138,8 @@ impl Surface {
         Self {
             material,
             normal_map,
-            _displacement_map: displacement_map,
+            displacement,
+            opacity,
             emissive,
             emissive_map,
             double_sided,
@@ -114,6 +219,22 @@ impl Surface {
         self.double_sided
     }
 
+    pub fn displacement(&self) -> Option<&Displacement> {
+        self.displacement.as_ref()
+    }
+
+    pub fn has_opacity(&self) -> bool {
+        self.opacity.is_some()
+    }
+
+    pub fn is_transparent(&self, ray: &Ray, inter: &Intersection<'_>) -> bool {
+        if let Some(opacity) = &self.opacity {
+            opacity.is_transparent(ray, inter)
+        } else {
+            false
+        }
+    }
+
     pub fn load(rsc: &mut SceneResources, params: &mut InputParams) -> anyhow::Result<()> {
         params.set_name("surface".into());
         let name = params.get_str("name")?;
@@ -126,8 +247,14 @@ impl Surface {
         } else {
             None
         };
-        let displacement_map = if params.contains_key("displacement_map") {
-            Some(rsc.clone_texture(params.get_str("displacement_map")?)?)
+        let displacement = if params.contains_key("displacement_map") {
+            Some(Displacement::load(rsc, params)?)
+        } else {
+            None
+        };
+
+        let opacity = if params.contains_key("opacity") || params.contains_key("opacity_map") {
+            Some(Opacity::load(rsc, params)?)
         } else {
             None
         };
@@ -150,7 +277,8 @@ impl Surface {
         let res = Surface::new(
             material,
             normal_map,
-            displacement_map,
+            displacement,
+            opacity,
             emissive,
             emissive_map,
             double_sided,
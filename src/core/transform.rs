@@ -1,3 +1,5 @@
+use crate::core::ray::gamma;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Transform {
     trans: glam::Affine3A,
@@ -20,6 +22,19 @@ impl Transform {
         self.trans.transform_point3a(other)
     }
 
+    /// also returns the error bound of the transformed point, given the error bound of `other`
+    pub fn transform_point3a_with_error(
+        &self,
+        other: glam::Vec3A,
+        other_error: glam::Vec3A,
+    ) -> (glam::Vec3A, glam::Vec3A) {
+        let m = self.trans.matrix3;
+        let abs_m = glam::Mat3A::from_cols(m.x_axis.abs(), m.y_axis.abs(), m.z_axis.abs());
+        let error = abs_m * other_error * (1.0 + gamma(3))
+            + (abs_m * other.abs() + self.trans.translation.abs()) * gamma(3);
+        (self.trans.transform_point3a(other), error)
+    }
+
     pub fn transform_vector3a(&self, other: glam::Vec3A) -> glam::Vec3A {
         self.trans.transform_vector3a(other)
     }
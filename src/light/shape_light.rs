@@ -43,7 +43,9 @@ impl LightT for ShapeLight {
     }
 
     fn strength_dist_pdf(&self, position: glam::Vec3A, wi: glam::Vec3A) -> (Color, f32, f32) {
-        let ray = Ray::new(position, wi);
+        // `position` has no error bounds here, so the ray can't be spawned from the surface
+        let mut ray = Ray::new(position, wi);
+        ray.t_min = Ray::T_MIN_EPS;
         let mut inter = Intersection::default();
         if self.shape.intersect(&ray, &mut inter) {
             let emissive = inter.surface.unwrap().emissive(&inter);
@@ -5,25 +5,35 @@ use byte_slice_cast::AsSliceOf;
 use glam::Vec4Swizzles;
 
 use crate::{
-    camera,
-    core::{color::Color, scene::Scene, scene_resources::SceneResources, surface::Surface},
+    bxdf, camera,
+    core::{
+        color::Color,
+        scene::Scene,
+        scene_resources::SceneResources,
+        surface::{AlphaMode, Opacity, Surface},
+    },
     light, material, primitive, texture,
 };
 
 pub fn load_scene<P: AsRef<Path>>(path: P) -> anyhow::Result<Scene> {
-    let rsc = load_scene_resources(path)?;
+    let rsc = load_scene_resources(path, false)?;
     let scene = rsc.to_scene(None, None)?;
     Ok(scene)
 }
 
-pub fn load_scene_resources<P: AsRef<Path>>(path: P) -> anyhow::Result<SceneResources> {
-    let (gltf_doc, buffers, images) = gltf::import(path)?;
+pub fn load_scene_resources<P: AsRef<Path>>(
+    path: P,
+    compact_meshes: bool,
+) -> anyhow::Result<SceneResources> {
+    let (gltf_doc, buffers, images) = gltf::import(path.as_ref())?;
+    let material_extensions = load_material_extensions(path.as_ref())?;
 
     let mut rsc = SceneResources::default();
+    rsc.set_compact_meshes(compact_meshes);
 
     load_images(&mut rsc, images)?;
 
-    let material_name_map = load_materials(&mut rsc, &gltf_doc)?;
+    let material_name_map = load_materials(&mut rsc, &gltf_doc, &material_extensions)?;
 
     let mesh_name_map = load_primitives(&mut rsc, &gltf_doc, &buffers)?;
 
@@ -99,9 +109,61 @@ fn load_images(rsc: &mut SceneResources, images: Vec<gltf::image::Data>) -> anyh
     Ok(())
 }
 
+/// raw json of extensions of each material, as `gltf` doesn't parse some of them
+fn load_material_extensions(path: &Path) -> anyhow::Result<Vec<serde_json::Value>> {
+    let bytes = std::fs::read(path)?;
+    let json = if bytes.starts_with(b"glTF") {
+        gltf::Glb::from_slice(&bytes)?.json.into_owned()
+    } else {
+        bytes
+    };
+    let root: serde_json::Value = serde_json::from_slice(&json)?;
+    let extensions = root["materials"]
+        .as_array()
+        .map(|materials| {
+            materials
+                .iter()
+                .map(|material| material["extensions"].clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(extensions)
+}
+
+/// image of `texture` in a raw texture info json
+fn raw_texture_image(
+    rsc: &SceneResources,
+    gltf_doc: &gltf::Document,
+    texture: &serde_json::Value,
+) -> anyhow::Result<Option<Arc<texture::Texture>>> {
+    if let Some(index) = texture["index"].as_u64() {
+        let gltf_tex = gltf_doc
+            .textures()
+            .nth(index as usize)
+            .context(format!("There is no texture {}", index))?;
+        let image_index = gltf_tex.source().index();
+        Ok(Some(rsc.clone_texture(format!("image_{}", image_index))?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn raw_color_factor(value: &serde_json::Value) -> Option<Color> {
+    let factor = value.as_array()?;
+    if factor.len() < 3 {
+        return None;
+    }
+    Some(Color::new(
+        factor[0].as_f64()? as f32,
+        factor[1].as_f64()? as f32,
+        factor[2].as_f64()? as f32,
+    ))
+}
+
 fn load_materials(
     rsc: &mut SceneResources,
     gltf_doc: &gltf::Document,
+    material_extensions: &[serde_json::Value],
 ) -> anyhow::Result<HashMap<Option<usize>, String>> {
     let mut name_map = HashMap::with_capacity(gltf_doc.materials().len());
 
@@ -181,6 +243,7 @@ fn load_materials(
                 roughness_x,
                 roughness_y,
                 roughness_chan,
+                bxdf::MicrofacetDistribution::Ggx,
             )
             .into()
         } else {
@@ -226,15 +289,83 @@ fn load_materials(
             let roughness_x = Arc::new(roughness);
             let roughness_y = roughness_x.clone();
 
-            material::PbrMetallic::new(
+            let mut pbr_metallic = material::PbrMetallic::new(
                 Arc::new(base_color),
                 roughness_x,
                 roughness_y,
                 texture::TextureChannel::G,
                 Arc::new(metallic),
                 texture::TextureChannel::B,
-            )
-            .into()
+                bxdf::MicrofacetDistribution::Ggx,
+                false,
+            );
+
+            let extensions = material_extensions.get(mat_index);
+            if let Some(sheen) = extensions.and_then(|ext| ext.get("KHR_materials_sheen")) {
+                let color_fact =
+                    raw_color_factor(&sheen["sheenColorFactor"]).unwrap_or(Color::BLACK);
+                let color_fact_tex: texture::Texture = texture::ScalarTex::new(color_fact).into();
+                let color = if let Some(color_tex) =
+                    raw_texture_image(rsc, gltf_doc, &sheen["sheenColorTexture"])?
+                {
+                    texture::MulTex::new(
+                        Arc::new(color_fact_tex),
+                        Arc::new(texture::SrgbTex::new(color_tex).into()),
+                    )
+                    .into()
+                } else {
+                    color_fact_tex
+                };
+                let roughness_fact = sheen["sheenRoughnessFactor"].as_f64().unwrap_or(0.0) as f32;
+                let roughness = raw_texture_image(rsc, gltf_doc, &sheen["sheenRoughnessTexture"])?
+                    .map(|roughness_tex| (roughness_tex, texture::TextureChannel::A));
+                pbr_metallic = pbr_metallic.with_sheen(Arc::new(color), roughness_fact, roughness);
+            }
+            if let Some(iridescence) =
+                extensions.and_then(|ext| ext.get("KHR_materials_iridescence"))
+            {
+                let weight_fact = iridescence["iridescenceFactor"].as_f64().unwrap_or(0.0) as f32;
+                let weight_fact_tex: texture::Texture =
+                    texture::ScalarTex::new(Color::gray(weight_fact)).into();
+                let weight = if let Some(weight_tex) =
+                    raw_texture_image(rsc, gltf_doc, &iridescence["iridescenceTexture"])?
+                {
+                    texture::MulTex::new(Arc::new(weight_fact_tex), weight_tex).into()
+                } else {
+                    weight_fact_tex
+                };
+
+                let ior = iridescence["iridescenceIor"].as_f64().unwrap_or(1.3) as f32;
+                let thickness_min = iridescence["iridescenceThicknessMinimum"]
+                    .as_f64()
+                    .unwrap_or(100.0) as f32;
+                let thickness_max = iridescence["iridescenceThicknessMaximum"]
+                    .as_f64()
+                    .unwrap_or(400.0) as f32;
+                let thickness = if let Some(thickness_tex) =
+                    raw_texture_image(rsc, gltf_doc, &iridescence["iridescenceThicknessTexture"])?
+                {
+                    let range_tex =
+                        texture::ScalarTex::new(Color::gray(thickness_max - thickness_min)).into();
+                    let min_tex = texture::ScalarTex::new(Color::gray(thickness_min)).into();
+                    texture::AddTex::new(
+                        Arc::new(min_tex),
+                        Arc::new(texture::MulTex::new(Arc::new(range_tex), thickness_tex).into()),
+                    )
+                    .into()
+                } else {
+                    texture::ScalarTex::new(Color::gray(thickness_max)).into()
+                };
+
+                pbr_metallic = pbr_metallic.with_thin_film(material::ThinFilm::new(
+                    Arc::new(thickness),
+                    texture::TextureChannel::G,
+                    ior,
+                    Arc::new(weight),
+                ));
+            }
+
+            pbr_metallic.into()
         };
 
         let double_sided = gltf_mat.double_sided();
@@ -254,10 +385,48 @@ fn load_materials(
             None
         };
 
+        let (alpha_fact, alpha_tex) = if let Some(pbr_specular) = gltf_mat.pbr_specular_glossiness()
+        {
+            (
+                pbr_specular.diffuse_factor()[3],
+                pbr_specular.diffuse_texture(),
+            )
+        } else {
+            let pbr_metallic = gltf_mat.pbr_metallic_roughness();
+            (
+                pbr_metallic.base_color_factor()[3],
+                pbr_metallic.base_color_texture(),
+            )
+        };
+        let alpha_mode = match gltf_mat.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => None,
+            gltf::material::AlphaMode::Mask => {
+                Some(AlphaMode::Mask(gltf_mat.alpha_cutoff().unwrap_or(0.5)))
+            }
+            gltf::material::AlphaMode::Blend => Some(AlphaMode::Blend),
+        };
+        let opacity = if let Some(alpha_mode) = alpha_mode {
+            let alpha_map = if let Some(alpha_tex) = alpha_tex {
+                let image_index = alpha_tex.texture().index();
+                Some(rsc.clone_texture(format!("image_{}", image_index))?)
+            } else {
+                None
+            };
+            Some(Opacity::new(
+                alpha_fact,
+                alpha_map,
+                texture::TextureChannel::A,
+                alpha_mode,
+            ))
+        } else {
+            None
+        };
+
         let surf = Surface::new(
             Arc::new(mat),
             normal_map,
             None,
+            opacity,
             emissive,
             emissive_map,
             double_sided,
@@ -345,7 +514,11 @@ fn load_primitives(
 
             primitive::TriMesh::calc_tangents(&mut vertices, &indices);
 
-            rsc.add_primitive(prim_name, primitive::TriMesh::new(vertices, indices).into())?;
+            let mut mesh = primitive::TriMesh::new(vertices, indices);
+            if rsc.compact_meshes() {
+                mesh = mesh.into_compact();
+            }
+            rsc.add_primitive(prim_name, mesh.into())?;
         }
     }
 
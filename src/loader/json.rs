@@ -58,6 +58,14 @@ pub fn load_scene<P: AsRef<Path>>(path: P) -> anyhow::Result<Scene> {
     let json_reader = std::io::BufReader::new(json_file);
     let json_value: serde_json::Value = serde_json::from_reader(json_reader)?;
 
+    if let Some(compact_value) = json_value.get("compact_meshes") {
+        rsc.set_compact_meshes(
+            compact_value
+                .as_bool()
+                .context("scene - 'compact_meshes' should be bool")?,
+        );
+    }
+
     let camera_value = json_value
         .get("cameras")
         .context("scene - There is no 'cameras' field")?;
@@ -170,7 +178,7 @@ pub fn load_scene<P: AsRef<Path>>(path: P) -> anyhow::Result<Scene> {
             .as_str()
             .context("json - 'gltf' should be string")?;
         let gltf_path = path.with_file_name(gltf_file);
-        let gltf_rsc = super::gltf::load_scene_resources(gltf_path)?;
+        let gltf_rsc = super::gltf::load_scene_resources(gltf_path, rsc.compact_meshes())?;
         rsc.merge(gltf_rsc);
     }
 
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::{
+    bxdf::{self, Bxdf, Lambert, Woven},
+    core::{
+        color::Color, intersection::Intersection, loader::InputParams,
+        scene_resources::SceneResources,
+    },
+    texture::{ScalarTex, Texture, TextureChannel, TextureT},
+};
+
+use super::MaterialT;
+
+/// plain weave of threads, warp along u and weft along v of texcoords
+struct Weave {
+    specular: Arc<Texture>,
+    roughness: f32,
+    /// number of thread cells per unit of texcoords
+    scale: f32,
+    /// max tilt of the fiber out of the surface at ends of a visible thread segment, in radians
+    tilt: f32,
+}
+
+/// a Charlie sheen lobe over a diffuse base, or over woven threads if `thread_specular` is given
+pub struct Cloth {
+    albedo: Arc<Texture>,
+    sheen: Arc<Texture>,
+    sheen_roughness: Arc<Texture>,
+    weave: Option<Weave>,
+}
+
+impl Cloth {
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let albedo = rsc.clone_texture(params.get_str("albedo")?)?;
+
+        let texture_or =
+            |params: &mut InputParams, key: &str, fallback: f32| -> anyhow::Result<Arc<Texture>> {
+                if params.contains_key(key) {
+                    rsc.clone_texture(params.get_str(key)?)
+                } else {
+                    Ok(Arc::new(ScalarTex::new(Color::gray(fallback)).into()))
+                }
+            };
+        let sheen = texture_or(params, "sheen", 1.0)?;
+        let sheen_roughness = texture_or(params, "sheen_roughness", 0.5)?;
+
+        let weave = if params.contains_key("thread_specular") {
+            Some(Weave {
+                specular: rsc.clone_texture(params.get_str("thread_specular")?)?,
+                roughness: params.get_float_or("thread_roughness", 0.3),
+                scale: params.get_float_or("weave_scale", 100.0),
+                tilt: params.get_float_or("weave_tilt", 30.0).to_radians(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            albedo,
+            sheen,
+            sheen_roughness,
+            weave,
+        })
+    }
+}
+
+impl MaterialT for Cloth {
+    fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
+        let albedo = self.albedo.color_at(inter.into());
+        let sheen = self.sheen.color_at(inter.into());
+        let sheen_roughness = self
+            .sheen_roughness
+            .float_at(inter.into(), TextureChannel::R)
+            .clamp(0.0, 1.0);
+
+        let base = if let Some(weave) = &self.weave {
+            let uv = inter.texcoords * weave.scale;
+            let cell = uv.floor();
+            let offset = uv - cell - glam::Vec2::splat(0.5);
+            let warp_on_top = (cell.x + cell.y).rem_euclid(2.0) < 1.0;
+            let (dir, offset) = if warp_on_top {
+                (glam::Vec3A::X, offset.x)
+            } else {
+                (glam::Vec3A::Y, offset.y)
+            };
+            let tilt = 2.0 * offset * weave.tilt;
+            let tangent = tilt.cos() * dir + tilt.sin() * glam::Vec3A::Z;
+
+            Woven::new(
+                albedo,
+                weave.specular.color_at(inter.into()),
+                tangent,
+                weave.roughness,
+            )
+            .into()
+        } else {
+            Lambert::new(albedo).into()
+        };
+
+        bxdf::Sheen::new(sheen, sheen_roughness * sheen_roughness, base).into()
+    }
+}
@@ -1,18 +1,26 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{Bxdf, ConductorFresnel, GgxMicrofacet, MicrofacetConductor, SpecularConductor},
+    bxdf::{
+        Bxdf, ConductorCompensation, ConductorFresnel, Fresnel, FresnelT, MicrofacetConductor,
+        MicrofacetDistribution, SpecularConductor, ThinFilmFresnel,
+    },
     core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
     texture::{Texture, TextureChannel, TextureT},
 };
 
-use super::MaterialT;
+use super::{load_distribution, load_thin_film, MaterialT, ThinFilm};
 
 pub struct Conductor {
     ior: Arc<Texture>,
     ior_k: Arc<Texture>,
     roughness_x: Arc<Texture>,
     roughness_y: Arc<Texture>,
+    distribution: MicrofacetDistribution,
+    energy_compensation: bool,
+    /// compensation shared by all shading points, if it does not vary over the surface
+    compensation: Option<ConductorCompensation>,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Conductor {
@@ -21,13 +29,50 @@ impl Conductor {
         ior_k: Arc<Texture>,
         roughness_x: Arc<Texture>,
         roughness_y: Arc<Texture>,
+        distribution: MicrofacetDistribution,
+        energy_compensation: bool,
     ) -> Self {
-        Self {
+        let mut conductor = Self {
             ior,
             ior_k,
             roughness_x,
             roughness_y,
+            distribution,
+            energy_compensation,
+            compensation: None,
+            thin_film: None,
+        };
+        conductor.compensation = conductor.constant_compensation();
+        conductor
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self.compensation = None;
+        self
+    }
+
+    /// computes the compensation ahead if all textures it depends on are constant,
+    /// so that average fresnel is not integrated for each shading point
+    fn constant_compensation(&self) -> Option<ConductorCompensation> {
+        let is_constant =
+            |texture: &Arc<Texture>| matches!(texture.as_ref(), Texture::ScalarTex(_));
+        if !self.energy_compensation
+            || !matches!(self.distribution, MicrofacetDistribution::Ggx)
+            || ![&self.ior, &self.ior_k, &self.roughness_x, &self.roughness_y]
+                .iter()
+                .all(|texture| is_constant(texture))
+        {
+            return None;
         }
+
+        let roughness_x = self.roughness_x.average_float(TextureChannel::R).powi(2);
+        let roughness_y = self.roughness_y.average_float(TextureChannel::R).powi(2);
+        let fresnel = ConductorFresnel::new(self.ior.average_color(), self.ior_k.average_color());
+        Some(ConductorCompensation::new(
+            (roughness_x * roughness_y).sqrt(),
+            fresnel.average(),
+        ))
     }
 
     pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
@@ -43,7 +88,23 @@ impl Conductor {
             (roughness_x, roughness_y)
         };
 
-        Ok(Self::new(ior, ior_k, roughness_x, roughness_y))
+        let distribution = load_distribution(params)?;
+        let energy_compensation = params.get_bool_or("energy_compensation", false);
+        let thin_film = load_thin_film(rsc, params, 1.0)?;
+
+        let conductor = Self::new(
+            ior,
+            ior_k,
+            roughness_x,
+            roughness_y,
+            distribution,
+            energy_compensation,
+        );
+        Ok(if let Some(thin_film) = thin_film {
+            conductor.with_thin_film(thin_film)
+        } else {
+            conductor
+        })
     }
 }
 
@@ -60,14 +121,28 @@ impl MaterialT for Conductor {
             .float_at(inter.into(), TextureChannel::R)
             .powi(2);
 
+        let fresnel: Fresnel = if let Some(thin_film) = &self.thin_film {
+            thin_film.fresnel_at(inter, |film_ior, thickness| {
+                ThinFilmFresnel::conductor(ior, ior_k, film_ior, thickness)
+            })
+        } else {
+            ConductorFresnel::new(ior, ior_k).into()
+        };
+
         if roughness_x < 0.0001 || roughness_y < 0.0001 {
-            SpecularConductor::new(ConductorFresnel::new(ior, ior_k).into()).into()
+            SpecularConductor::new(fresnel).into()
         } else {
-            MicrofacetConductor::new(
-                GgxMicrofacet::new(roughness_x, roughness_y).into(),
-                ConductorFresnel::new(ior, ior_k).into(),
-            )
-            .into()
+            let bxdf = MicrofacetConductor::new(
+                self.distribution.microfacet(roughness_x, roughness_y),
+                fresnel,
+            );
+            if let Some(compensation) = self.compensation {
+                bxdf.with_compensation(compensation).into()
+            } else if self.energy_compensation {
+                bxdf.with_energy_compensation().into()
+            } else {
+                bxdf.into()
+            }
         }
     }
 }
@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{Bxdf, DielectricFresnel, GgxMicrofacet, MicrofacetDielectric, SpecularDielectric},
+    bxdf::{
+        Bxdf, DielectricFresnel, Fresnel, MicrofacetDielectric, MicrofacetDistribution,
+        SpecularDielectric, ThinFilmFresnel,
+    },
     core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
     texture::{Texture, TextureChannel, TextureT},
 };
 
-use super::MaterialT;
+use super::{load_distribution, load_thin_film, MaterialT, ThinFilm};
 
 pub struct Dielectric {
     ior: f32,
@@ -14,9 +17,13 @@ pub struct Dielectric {
     transmittance: Arc<Texture>,
     roughness_x: Arc<Texture>,
     roughness_y: Arc<Texture>,
+    distribution: MicrofacetDistribution,
+    energy_compensation: bool,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         int_ior: f32,
         ext_ior: f32,
@@ -24,6 +31,8 @@ impl Dielectric {
         transmittance: Arc<Texture>,
         roughness_x: Arc<Texture>,
         roughness_y: Arc<Texture>,
+        distribution: MicrofacetDistribution,
+        energy_compensation: bool,
     ) -> Self {
         let ior = int_ior / ext_ior;
         Self {
@@ -32,9 +41,17 @@ impl Dielectric {
             transmittance,
             roughness_x,
             roughness_y,
+            distribution,
+            energy_compensation,
+            thin_film: None,
         }
     }
 
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
     pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let int_ior = params.get_float("int_ior")?;
         let ext_ior = params.get_float_or("ext_ior", 1.0);
@@ -51,14 +68,25 @@ impl Dielectric {
             (roughness_x, roughness_y)
         };
 
-        Ok(Self::new(
+        let distribution = load_distribution(params)?;
+        let energy_compensation = params.get_bool_or("energy_compensation", false);
+        let thin_film = load_thin_film(rsc, params, ext_ior)?;
+
+        let dielectric = Self::new(
             int_ior,
             ext_ior,
             reflectance,
             transmittance,
             roughness_x,
             roughness_y,
-        ))
+            distribution,
+            energy_compensation,
+        );
+        Ok(if let Some(thin_film) = thin_film {
+            dielectric.with_thin_film(thin_film)
+        } else {
+            dielectric
+        })
     }
 }
 
@@ -76,14 +104,26 @@ impl MaterialT for Dielectric {
             .float_at(inter.into(), TextureChannel::R)
             .powi(2);
 
+        let fresnel: Fresnel = if let Some(thin_film) = &self.thin_film {
+            thin_film.fresnel_at(inter, |film_ior, thickness| {
+                ThinFilmFresnel::dielectric(self.ior, film_ior, thickness)
+            })
+        } else {
+            DielectricFresnel::new(self.ior).into()
+        };
+
         if roughness_x < 0.0001 || roughness_y < 0.0001 {
-            SpecularDielectric::new(DielectricFresnel::new(self.ior).into()).into()
+            SpecularDielectric::new(fresnel).into()
         } else {
-            MicrofacetDielectric::new(
-                GgxMicrofacet::new(roughness_x, roughness_y).into(),
-                DielectricFresnel::new(self.ior).into(),
-            )
-            .into()
+            let bxdf = MicrofacetDielectric::new(
+                self.distribution.microfacet(roughness_x, roughness_y),
+                fresnel,
+            );
+            if self.energy_compensation {
+                bxdf.with_energy_compensation().into()
+            } else {
+                bxdf.into()
+            }
         }
     }
 }
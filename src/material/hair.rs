@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::{
+    bxdf::{self, Bxdf, HairParams},
+    core::{
+        color::Color, intersection::Intersection, loader::InputParams,
+        scene_resources::SceneResources,
+    },
+    texture::{ScalarTex, Texture, TextureChannel, TextureT},
+};
+
+use super::MaterialT;
+
+enum HairAbsorption {
+    SigmaA(Arc<Texture>),
+    Color(Arc<Texture>),
+    Melanin {
+        eumelanin: Arc<Texture>,
+        pheomelanin: Arc<Texture>,
+    },
+}
+
+/// hair fibers modeled as camera-facing ribbons (strips of quads along each strand),
+/// u of texcoords and the tangent go along the fiber, v goes across the ribbon from 0 to 1
+/// and is mapped to the offset h in [-1, 1] across the fiber, v = 0.5 is its center
+pub struct Hair {
+    ior: f32,
+    absorption: HairAbsorption,
+    beta_m: Arc<Texture>,
+    beta_n: Arc<Texture>,
+    alpha: f32,
+}
+
+impl Hair {
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let int_ior = params.get_float_or("int_ior", 1.55);
+        let ext_ior = params.get_float_or("ext_ior", 1.0);
+        let alpha = params.get_float_or("alpha", 2.0);
+
+        let texture_or =
+            |params: &mut InputParams, key: &str, fallback: f32| -> anyhow::Result<Arc<Texture>> {
+                if params.contains_key(key) {
+                    rsc.clone_texture(params.get_str(key)?)
+                } else {
+                    Ok(Arc::new(ScalarTex::new(Color::gray(fallback)).into()))
+                }
+            };
+
+        let beta_m = texture_or(params, "beta_m", 0.3)?;
+        let beta_n = texture_or(params, "beta_n", 0.3)?;
+
+        let absorption = if params.contains_key("sigma_a") {
+            HairAbsorption::SigmaA(rsc.clone_texture(params.get_str("sigma_a")?)?)
+        } else if params.contains_key("color") {
+            HairAbsorption::Color(rsc.clone_texture(params.get_str("color")?)?)
+        } else {
+            HairAbsorption::Melanin {
+                eumelanin: texture_or(params, "eumelanin", 1.3)?,
+                pheomelanin: texture_or(params, "pheomelanin", 0.0)?,
+            }
+        };
+
+        Ok(Self {
+            ior: int_ior / ext_ior,
+            absorption,
+            beta_m,
+            beta_n,
+            alpha,
+        })
+    }
+}
+
+impl MaterialT for Hair {
+    fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
+        let float_at = |texture: &Arc<Texture>| texture.float_at(inter.into(), TextureChannel::R);
+
+        let beta_m = float_at(&self.beta_m).clamp(0.0, 1.0);
+        let beta_n = float_at(&self.beta_n).clamp(0.0, 1.0);
+        let sigma_a = match &self.absorption {
+            HairAbsorption::SigmaA(sigma_a) => sigma_a.color_at(inter.into()),
+            HairAbsorption::Color(color) => {
+                bxdf::Hair::sigma_a_from_color(color.color_at(inter.into()), beta_n)
+            }
+            HairAbsorption::Melanin {
+                eumelanin,
+                pheomelanin,
+            } => bxdf::Hair::sigma_a_from_melanin(
+                float_at(eumelanin).max(0.0),
+                float_at(pheomelanin).max(0.0),
+            ),
+        };
+
+        bxdf::Hair::new(&HairParams {
+            h: inter.texcoords.y * 2.0 - 1.0,
+            ior: self.ior,
+            sigma_a,
+            beta_m,
+            beta_n,
+            alpha: self.alpha,
+        })
+        .into()
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::{
+    bxdf::{
+        self, Bxdf, DielectricFresnel, GgxMicrofacet, MicrofacetDielectric, SpecularDielectric,
+    },
+    core::{
+        color::Color, intersection::Intersection, loader::InputParams,
+        scene_resources::SceneResources,
+    },
+    texture::{Texture, TextureChannel, TextureT},
+};
+
+use super::{Material, MaterialT};
+
+/// a dielectric coating over another material,
+/// `color` is the transmittance of the coating at normal incidence for unit `thickness`
+pub struct Layered {
+    base: Arc<Material>,
+    ior: f32,
+    roughness: Option<Arc<Texture>>,
+    color: Option<Arc<Texture>>,
+    thickness: f32,
+    max_depth: u32,
+    samples: u32,
+}
+
+impl Layered {
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let base = rsc.clone_material(params.get_str("base")?)?;
+
+        let int_ior = params.get_float_or("int_ior", 1.5);
+        let ext_ior = params.get_float_or("ext_ior", 1.0);
+
+        let roughness = if params.contains_key("roughness") {
+            Some(rsc.clone_texture(params.get_str("roughness")?)?)
+        } else {
+            None
+        };
+        let color = if params.contains_key("color") {
+            Some(rsc.clone_texture(params.get_str("color")?)?)
+        } else {
+            None
+        };
+        let thickness = params.get_float_or("thickness", 1.0);
+
+        let max_depth = params.get_int_or("max_depth", 10);
+        let samples = params.get_int_or("samples", 1);
+        if max_depth <= 0 || samples <= 0 {
+            anyhow::bail!(format!(
+                "{} - max_depth and samples should be positive",
+                params.name()
+            ));
+        }
+
+        Ok(Self {
+            base,
+            ior: int_ior / ext_ior,
+            roughness,
+            color,
+            thickness,
+            max_depth: max_depth as u32,
+            samples: samples as u32,
+        })
+    }
+}
+
+impl MaterialT for Layered {
+    fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
+        let roughness = self.roughness.as_ref().map_or(0.0, |roughness| {
+            roughness.float_at(inter.into(), TextureChannel::R).powi(2)
+        });
+        let top = if roughness < 0.0001 {
+            SpecularDielectric::new(DielectricFresnel::new(self.ior).into()).into()
+        } else {
+            MicrofacetDielectric::new(
+                GgxMicrofacet::new(roughness, roughness).into(),
+                DielectricFresnel::new(self.ior).into(),
+            )
+            .into()
+        };
+
+        let absorption = self.color.as_ref().map_or(Color::BLACK, |color| {
+            let color = color.color_at(inter.into());
+            let optical_depth = |value: f32| -value.max(0.0001).ln() * self.thickness;
+            Color::new(
+                optical_depth(color.r),
+                optical_depth(color.g),
+                optical_depth(color.b),
+            )
+        });
+
+        bxdf::Layered::new(
+            top,
+            self.base.bxdf_context(inter),
+            self.ior,
+            absorption,
+            self.max_depth,
+            self.samples,
+        )
+        .into()
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+
+use crate::{
+    bxdf::{self, Bxdf, MeasuredData},
+    core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
+};
+
+use super::MaterialT;
+
+/// a measured brdf of a MERL `.binary` file or a RGL `.bsdf` file
+pub struct Measured {
+    data: Arc<MeasuredData>,
+}
+
+impl Measured {
+    pub fn new(data: Arc<MeasuredData>) -> Self {
+        Self { data }
+    }
+
+    pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let path = params.get_file_path("file")?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let data = match extension.as_str() {
+            "binary" => MeasuredData::load_merl(&path),
+            "bsdf" => MeasuredData::load_rgl(&path),
+            _ => anyhow::bail!(format!(
+                "{} - unknown measured brdf format of '{}'",
+                params.name(),
+                path.display()
+            )),
+        }
+        .context(format!(
+            "{} - can't load measured brdf '{}'",
+            params.name(),
+            path.display()
+        ))?;
+        Ok(Self::new(Arc::new(data)))
+    }
+}
+
+impl MaterialT for Measured {
+    fn bxdf_context(&self, _inter: &Intersection<'_>) -> Bxdf {
+        bxdf::Measured::new(self.data.clone()).into()
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use crate::{
+    bxdf::{self, Bxdf},
+    core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
+    texture::{Texture, TextureChannel, TextureT},
+};
+
+use super::{Material, MaterialT};
+
+/// blend of two or more materials, `weights[i]` is the mask of `materials[i + 1]`
+/// over the blend of the materials before it
+pub struct Mix {
+    materials: Vec<Arc<Material>>,
+    weights: Vec<Arc<Texture>>,
+}
+
+impl Mix {
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let materials = params
+            .get_str_array("materials")?
+            .into_iter()
+            .map(|name| rsc.clone_material(name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if materials.len() < 2 {
+            anyhow::bail!(format!(
+                "{} - at least 2 materials are needed",
+                params.name()
+            ));
+        }
+
+        let weights = params
+            .get_str_array("weights")?
+            .into_iter()
+            .map(|name| rsc.clone_texture(name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if weights.len() + 1 != materials.len() {
+            anyhow::bail!(format!(
+                "{} - there should be {} weights for {} materials",
+                params.name(),
+                materials.len() - 1,
+                materials.len()
+            ));
+        }
+
+        Ok(Self { materials, weights })
+    }
+}
+
+impl MaterialT for Mix {
+    fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
+        let mut amounts = vec![1.0];
+        for weight in &self.weights {
+            let weight = weight
+                .float_at(inter.into(), TextureChannel::R)
+                .clamp(0.0, 1.0);
+            amounts
+                .iter_mut()
+                .for_each(|amount| *amount *= 1.0 - weight);
+            amounts.push(weight);
+        }
+
+        let mut components: Vec<_> = amounts
+            .into_iter()
+            .zip(&self.materials)
+            .filter(|(amount, _)| *amount > 0.0)
+            .map(|(amount, material)| (amount, material.bxdf_context(inter)))
+            .collect();
+        if components.len() == 1 {
+            components.pop().unwrap().1
+        } else {
+            bxdf::Mix::new(components).into()
+        }
+    }
+}
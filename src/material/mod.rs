@@ -1,28 +1,46 @@
+mod cloth;
 mod conductor;
 mod dielectric;
+mod hair;
 mod lambert;
+mod layered;
+mod measured;
+mod mix;
 mod pbr_metallic;
 mod pbr_specular;
 mod plastic;
 mod pndf_conductor;
 mod pndf_plastic;
+mod principled;
 mod pseudo;
 mod subsurface;
 
+pub use cloth::*;
 pub use conductor::*;
 pub use dielectric::*;
+pub use hair::*;
 pub use lambert::*;
+pub use layered::*;
+pub use measured::*;
+pub use mix::*;
 pub use pbr_metallic::*;
 pub use pbr_specular::*;
 pub use plastic::*;
 pub use pndf_conductor::*;
 pub use pndf_plastic::*;
+pub use principled::*;
 pub use pseudo::*;
 pub use subsurface::*;
 
+use std::sync::Arc;
+
 use crate::{
-    bxdf::Bxdf,
-    core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
+    bxdf::{Bxdf, Fresnel, MicrofacetDistribution, ThinFilmFresnel},
+    core::{
+        color::Color, intersection::Intersection, loader::InputParams,
+        scene_resources::SceneResources,
+    },
+    texture::{ScalarTex, Texture, TextureChannel, TextureT},
 };
 
 #[enum_dispatch::enum_dispatch(Material)]
@@ -32,14 +50,20 @@ pub trait MaterialT: Send + Sync {
 
 #[enum_dispatch::enum_dispatch]
 pub enum Material {
+    Cloth,
     Conductor,
     Dielectric,
     Plastic,
+    Hair,
     Lambert,
+    Layered,
+    Measured,
+    Mix,
     PbrMetallic,
     PbrSpecular,
     PndfConductor,
     PndfPlastic,
+    Principled,
     PseudoMaterial,
     Subsurface,
 }
@@ -54,14 +78,20 @@ pub fn create_material_from_params(
     params.set_name(format!("material-{}-{}", ty, name).into());
 
     let res = match ty.as_str() {
+        "cloth" => Cloth::load(rsc, params)?.into(),
         "conductor" => Conductor::load(rsc, params)?.into(),
         "dielectric" => Dielectric::load(rsc, params)?.into(),
         "plastic" => Plastic::load(rsc, params)?.into(),
+        "hair" => Hair::load(rsc, params)?.into(),
         "lambert" => Lambert::load(rsc, params)?.into(),
+        "layered" => Layered::load(rsc, params)?.into(),
+        "measured" => Measured::load(rsc, params)?.into(),
+        "mix" => Mix::load(rsc, params)?.into(),
         "pbr_metallic" => PbrMetallic::load(rsc, params)?.into(),
         "pbr_specular" => PbrSpecular::load(rsc, params)?.into(),
         "pndf_conductor" => PndfConductor::load(rsc, params)?.into(),
         "pndf_plastic" => PndfPlastic::load(rsc, params)?.into(),
+        "principled" => Principled::load(rsc, params)?.into(),
         "pseudo" => PseudoMaterial::load(rsc, params)?.into(),
         "subsurface" => Subsurface::load(rsc, params)?.into(),
         _ => anyhow::bail!(format!("{}: unknown type '{}'", params.name(), ty)),
@@ -73,3 +103,86 @@ pub fn create_material_from_params(
 
     Ok(())
 }
+
+/// microfacet distribution of the `distribution` key, ggx if absent
+fn load_distribution(params: &mut InputParams) -> anyhow::Result<MicrofacetDistribution> {
+    let ty = params.get_str_or("distribution", "ggx");
+    let distribution = match ty.as_str() {
+        "ggx" => MicrofacetDistribution::Ggx,
+        "beckmann" => MicrofacetDistribution::Beckmann,
+        "gtr1" => MicrofacetDistribution::Gtr(1.0),
+        "gtr" => {
+            let gamma = params.get_float("gtr_gamma")?;
+            if gamma <= 0.0 {
+                anyhow::bail!(format!("{} - gtr_gamma should be positive", params.name()));
+            }
+            MicrofacetDistribution::Gtr(gamma)
+        }
+        _ => anyhow::bail!(format!("{} - unknown distribution '{}'", params.name(), ty)),
+    };
+    Ok(distribution)
+}
+
+/// a thin film over the surface of a material, which makes its fresnel iridescent
+pub struct ThinFilm {
+    /// thickness of the film in nm
+    thickness: Arc<Texture>,
+    thickness_channel: TextureChannel,
+    /// ior of the film relative to the outside medium
+    ior: f32,
+    weight: Arc<Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(
+        thickness: Arc<Texture>,
+        thickness_channel: TextureChannel,
+        ior: f32,
+        weight: Arc<Texture>,
+    ) -> Self {
+        Self {
+            thickness,
+            thickness_channel,
+            ior,
+            weight,
+        }
+    }
+
+    /// `fresnel` is called with ior and thickness of the film at `inter`
+    fn fresnel_at<F>(&self, inter: &Intersection<'_>, fresnel: F) -> Fresnel
+    where
+        F: FnOnce(f32, f32) -> ThinFilmFresnel,
+    {
+        let thickness = self
+            .thickness
+            .float_at(inter.into(), self.thickness_channel)
+            .max(0.0);
+        let weight = self.weight.float_at(inter.into(), TextureChannel::R);
+        fresnel(self.ior, thickness).with_weight(weight).into()
+    }
+}
+
+/// thin film of the `thin_film_thickness` key, none if absent
+fn load_thin_film(
+    rsc: &SceneResources,
+    params: &mut InputParams,
+    ext_ior: f32,
+) -> anyhow::Result<Option<ThinFilm>> {
+    if !params.contains_key("thin_film_thickness") {
+        return Ok(None);
+    }
+
+    let thickness = rsc.clone_texture(params.get_str("thin_film_thickness")?)?;
+    let ior = params.get_float_or("thin_film_ior", 1.3);
+    let weight = if params.contains_key("thin_film_weight") {
+        rsc.clone_texture(params.get_str("thin_film_weight")?)?
+    } else {
+        Arc::new(ScalarTex::new(Color::WHITE).into())
+    };
+    Ok(Some(ThinFilm::new(
+        thickness,
+        TextureChannel::R,
+        ior / ext_ior,
+        weight,
+    )))
+}
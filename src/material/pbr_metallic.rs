@@ -1,7 +1,10 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{Bxdf, GgxMicrofacet, Lambert, MicrofacetPlastic, SchlickFresnel, SpecularPlastic},
+    bxdf::{
+        self, Bxdf, Fresnel, Lambert, MicrofacetDistribution, MicrofacetPlastic, SchlickFresnel,
+        SpecularPlastic, ThinFilmFresnel,
+    },
     core::{
         color::Color, intersection::Intersection, loader::InputParams,
         scene_resources::SceneResources,
@@ -9,7 +12,7 @@ use crate::{
     texture::{Texture, TextureChannel, TextureT},
 };
 
-use super::MaterialT;
+use super::{load_distribution, load_thin_film, MaterialT, ThinFilm};
 
 pub struct PbrMetallic {
     base_color: Arc<Texture>,
@@ -18,9 +21,21 @@ pub struct PbrMetallic {
     roughness_chan: TextureChannel,
     metallic: Arc<Texture>,
     metallic_chan: TextureChannel,
+    distribution: MicrofacetDistribution,
+    energy_compensation: bool,
+    sheen: Option<PbrSheen>,
+    thin_film: Option<ThinFilm>,
+}
+
+/// Charlie sheen over the base, as KHR_materials_sheen
+struct PbrSheen {
+    color: Arc<Texture>,
+    roughness_factor: f32,
+    roughness: Option<(Arc<Texture>, TextureChannel)>,
 }
 
 impl PbrMetallic {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         base_color: Arc<Texture>,
         roughness_x: Arc<Texture>,
@@ -28,6 +43,8 @@ impl PbrMetallic {
         roughness_chan: TextureChannel,
         metallic: Arc<Texture>,
         metallic_chan: TextureChannel,
+        distribution: MicrofacetDistribution,
+        energy_compensation: bool,
     ) -> Self {
         Self {
             base_color,
@@ -36,9 +53,34 @@ impl PbrMetallic {
             roughness_chan,
             metallic,
             metallic_chan,
+            distribution,
+            energy_compensation,
+            sheen: None,
+            thin_film: None,
         }
     }
 
+    /// sheen roughness is `roughness_factor` multiplied by `roughness` if there is
+    pub fn with_sheen(
+        mut self,
+        color: Arc<Texture>,
+        roughness_factor: f32,
+        roughness: Option<(Arc<Texture>, TextureChannel)>,
+    ) -> Self {
+        self.sheen = Some(PbrSheen {
+            color,
+            roughness_factor,
+            roughness,
+        });
+        self
+    }
+
+    /// thin film over the specular, as KHR_materials_iridescence
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
     pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let base_color = rsc.clone_texture(params.get_str("base_color")?)?;
 
@@ -53,14 +95,35 @@ impl PbrMetallic {
 
         let metallic = rsc.clone_texture(params.get_str("metallic")?)?;
 
-        Ok(Self::new(
+        let distribution = load_distribution(params)?;
+        let energy_compensation = params.get_bool_or("energy_compensation", false);
+
+        let mut material = Self::new(
             base_color,
             roughness_x,
             roughness_y,
             TextureChannel::R,
             metallic,
             TextureChannel::R,
-        ))
+            distribution,
+            energy_compensation,
+        );
+
+        if params.contains_key("sheen_color") {
+            let sheen_color = rsc.clone_texture(params.get_str("sheen_color")?)?;
+            material = if params.contains_key("sheen_roughness") {
+                let sheen_roughness = rsc.clone_texture(params.get_str("sheen_roughness")?)?;
+                material.with_sheen(sheen_color, 1.0, Some((sheen_roughness, TextureChannel::R)))
+            } else {
+                material.with_sheen(sheen_color, 0.5, None)
+            };
+        }
+
+        if let Some(thin_film) = load_thin_film(rsc, params, 1.0)? {
+            material = material.with_thin_film(thin_film);
+        }
+
+        Ok(material)
     }
 }
 
@@ -80,19 +143,38 @@ impl MaterialT for PbrMetallic {
         let specular = metallic * base_color + (1.0 - metallic) * Color::gray(0.04);
         let diffuse = base_color * (1.0 - metallic);
 
-        if roughness_x < 0.0001 || roughness_y < 0.0001 {
-            SpecularPlastic::new(
-                SchlickFresnel::new(specular).into(),
-                Lambert::new(diffuse).into(),
-            )
-            .into()
+        let fresnel: Fresnel = if let Some(thin_film) = &self.thin_film {
+            thin_film.fresnel_at(inter, |film_ior, thickness| {
+                ThinFilmFresnel::schlick(specular, film_ior, thickness)
+            })
+        } else {
+            SchlickFresnel::new(specular).into()
+        };
+
+        let base = if roughness_x < 0.0001 || roughness_y < 0.0001 {
+            SpecularPlastic::new(fresnel, Lambert::new(diffuse).into()).into()
         } else {
-            MicrofacetPlastic::new(
-                GgxMicrofacet::new(roughness_x, roughness_y).into(),
-                SchlickFresnel::new(specular).into(),
+            let bxdf = MicrofacetPlastic::new(
+                self.distribution.microfacet(roughness_x, roughness_y),
+                fresnel,
                 Lambert::new(diffuse).into(),
-            )
-            .into()
+            );
+            if self.energy_compensation {
+                bxdf.with_energy_compensation().into()
+            } else {
+                bxdf.into()
+            }
+        };
+
+        if let Some(sheen) = &self.sheen {
+            let color = sheen.color.color_at(inter.into());
+            let roughness = sheen.roughness.as_ref().map_or(1.0, |(roughness, chan)| {
+                roughness.float_at(inter.into(), *chan)
+            });
+            let roughness = (roughness * sheen.roughness_factor).clamp(0.0, 1.0);
+            bxdf::Sheen::new(color, roughness * roughness, base).into()
+        } else {
+            base
         }
     }
 }
@@ -1,12 +1,14 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{Bxdf, GgxMicrofacet, Lambert, MicrofacetPlastic, SchlickFresnel, SpecularPlastic},
+    bxdf::{
+        Bxdf, Lambert, MicrofacetDistribution, MicrofacetPlastic, SchlickFresnel, SpecularPlastic,
+    },
     core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
     texture::{Texture, TextureChannel, TextureT},
 };
 
-use super::MaterialT;
+use super::{load_distribution, MaterialT};
 
 pub struct PbrSpecular {
     diffuse: Arc<Texture>,
@@ -14,6 +16,7 @@ pub struct PbrSpecular {
     roughness_x: Arc<Texture>,
     roughness_y: Arc<Texture>,
     roughness_chan: TextureChannel,
+    distribution: MicrofacetDistribution,
 }
 
 impl PbrSpecular {
@@ -23,6 +26,7 @@ impl PbrSpecular {
         roughness_x: Arc<Texture>,
         roughness_y: Arc<Texture>,
         roughness_chan: TextureChannel,
+        distribution: MicrofacetDistribution,
     ) -> Self {
         Self {
             diffuse,
@@ -30,6 +34,7 @@ impl PbrSpecular {
             roughness_x,
             roughness_y,
             roughness_chan,
+            distribution,
         }
     }
 
@@ -47,12 +52,15 @@ impl PbrSpecular {
             (roughness_x, roughness_y)
         };
 
+        let distribution = load_distribution(params)?;
+
         Ok(Self::new(
             diffuse,
             specular,
             roughness_x,
             roughness_y,
             TextureChannel::R,
+            distribution,
         ))
     }
 }
@@ -78,7 +86,7 @@ impl MaterialT for PbrSpecular {
             .into()
         } else {
             MicrofacetPlastic::new(
-                GgxMicrofacet::new(roughness_x, roughness_y).into(),
+                self.distribution.microfacet(roughness_x, roughness_y),
                 SchlickFresnel::new(specular).into(),
                 Lambert::new(diffuse).into(),
             )
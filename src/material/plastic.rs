@@ -1,18 +1,24 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{Bxdf, DielectricFresnel, Diffuse, GgxMicrofacet, MicrofacetPlastic, SpecularPlastic},
+    bxdf::{
+        Bxdf, DielectricFresnel, Diffuse, Fresnel, MicrofacetDistribution, MicrofacetPlastic,
+        SpecularPlastic, ThinFilmFresnel,
+    },
     core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
     texture::{Texture, TextureChannel, TextureT},
 };
 
-use super::MaterialT;
+use super::{load_distribution, load_thin_film, MaterialT, ThinFilm};
 
 pub struct Plastic {
     ior: f32,
     albedo: Arc<Texture>,
     roughness_x: Arc<Texture>,
     roughness_y: Arc<Texture>,
+    distribution: MicrofacetDistribution,
+    energy_compensation: bool,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Plastic {
@@ -22,6 +28,8 @@ impl Plastic {
         albedo: Arc<Texture>,
         roughness_x: Arc<Texture>,
         roughness_y: Arc<Texture>,
+        distribution: MicrofacetDistribution,
+        energy_compensation: bool,
     ) -> Self {
         let ior = int_ior / ext_ior;
         Self {
@@ -29,9 +37,17 @@ impl Plastic {
             albedo,
             roughness_x,
             roughness_y,
+            distribution,
+            energy_compensation,
+            thin_film: None,
         }
     }
 
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
     pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let int_ior = params.get_float("int_ior")?;
         let ext_ior = params.get_float_or("ext_ior", 1.0);
@@ -47,13 +63,24 @@ impl Plastic {
             (roughness_x, roughness_y)
         };
 
-        Ok(Self::new(
+        let distribution = load_distribution(params)?;
+        let energy_compensation = params.get_bool_or("energy_compensation", false);
+        let thin_film = load_thin_film(rsc, params, ext_ior)?;
+
+        let plastic = Self::new(
             int_ior,
             ext_ior,
             albedo,
             roughness_x,
             roughness_y,
-        ))
+            distribution,
+            energy_compensation,
+        );
+        Ok(if let Some(thin_film) = thin_film {
+            plastic.with_thin_film(thin_film)
+        } else {
+            plastic
+        })
     }
 }
 
@@ -64,19 +91,27 @@ impl MaterialT for Plastic {
         let roughness_x = self.roughness_x.float_at(inter.into(), TextureChannel::R);
         let roughness_y = self.roughness_y.float_at(inter.into(), TextureChannel::R);
 
+        let fresnel: Fresnel = if let Some(thin_film) = &self.thin_film {
+            thin_film.fresnel_at(inter, |film_ior, thickness| {
+                ThinFilmFresnel::dielectric(self.ior, film_ior, thickness)
+            })
+        } else {
+            DielectricFresnel::new(self.ior).into()
+        };
+
         if roughness_x < 0.0001 || roughness_y < 0.0001 {
-            SpecularPlastic::new(
-                DielectricFresnel::new(self.ior).into(),
-                Diffuse::new(albedo, self.ior).into(),
-            )
-            .into()
+            SpecularPlastic::new(fresnel, Diffuse::new(albedo, self.ior).into()).into()
         } else {
-            MicrofacetPlastic::new(
-                GgxMicrofacet::new(roughness_x, roughness_y).into(),
-                DielectricFresnel::new(self.ior).into(),
+            let bxdf = MicrofacetPlastic::new(
+                self.distribution.microfacet(roughness_x, roughness_y),
+                fresnel,
                 Diffuse::new(albedo, self.ior).into(),
-            )
-            .into()
+            );
+            if self.energy_compensation {
+                bxdf.with_energy_compensation().into()
+            } else {
+                bxdf.into()
+            }
         }
     }
 }
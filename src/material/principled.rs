@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use crate::{
+    bxdf::{self, Bxdf, PrincipledParams},
+    core::{
+        color::Color, intersection::Intersection, loader::InputParams,
+        scene_resources::SceneResources,
+    },
+    texture::{ScalarTex, Texture, TextureChannel, TextureT},
+};
+
+use super::MaterialT;
+
+pub struct Principled {
+    base_color: Arc<Texture>,
+    metallic: Arc<Texture>,
+    roughness: Arc<Texture>,
+    anisotropic: Arc<Texture>,
+    specular: Arc<Texture>,
+    specular_tint: Arc<Texture>,
+    sheen: Arc<Texture>,
+    sheen_tint: Arc<Texture>,
+    sheen_roughness: Arc<Texture>,
+    clearcoat: Arc<Texture>,
+    clearcoat_roughness: Arc<Texture>,
+    transmission: Arc<Texture>,
+    subsurface: Arc<Texture>,
+    ior: f32,
+}
+
+impl Principled {
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let int_ior = params.get_float_or("int_ior", 1.5);
+        let ext_ior = params.get_float_or("ext_ior", 1.0);
+
+        let mut texture_or = |key: &str, fallback: f32| -> anyhow::Result<Arc<Texture>> {
+            if params.contains_key(key) {
+                rsc.clone_texture(params.get_str(key)?)
+            } else {
+                Ok(Arc::new(ScalarTex::new(Color::gray(fallback)).into()))
+            }
+        };
+
+        Ok(Self {
+            base_color: texture_or("base_color", 0.8)?,
+            metallic: texture_or("metallic", 0.0)?,
+            roughness: texture_or("roughness", 0.5)?,
+            anisotropic: texture_or("anisotropic", 0.0)?,
+            specular: texture_or("specular", 0.5)?,
+            specular_tint: texture_or("specular_tint", 0.0)?,
+            sheen: texture_or("sheen", 0.0)?,
+            sheen_tint: texture_or("sheen_tint", 0.5)?,
+            sheen_roughness: texture_or("sheen_roughness", 0.5)?,
+            clearcoat: texture_or("clearcoat", 0.0)?,
+            clearcoat_roughness: texture_or("clearcoat_roughness", 0.03)?,
+            transmission: texture_or("transmission", 0.0)?,
+            subsurface: texture_or("subsurface", 0.0)?,
+            ior: int_ior / ext_ior,
+        })
+    }
+}
+
+impl MaterialT for Principled {
+    fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
+        let float_at = |texture: &Arc<Texture>| {
+            texture
+                .float_at(inter.into(), TextureChannel::R)
+                .clamp(0.0, 1.0)
+        };
+
+        bxdf::Principled::new(&PrincipledParams {
+            base_color: self.base_color.color_at(inter.into()),
+            metallic: float_at(&self.metallic),
+            roughness: float_at(&self.roughness),
+            anisotropic: float_at(&self.anisotropic),
+            specular: float_at(&self.specular),
+            specular_tint: float_at(&self.specular_tint),
+            sheen: float_at(&self.sheen),
+            sheen_tint: float_at(&self.sheen_tint),
+            sheen_roughness: float_at(&self.sheen_roughness),
+            clearcoat: float_at(&self.clearcoat),
+            clearcoat_roughness: float_at(&self.clearcoat_roughness),
+            transmission: float_at(&self.transmission),
+            ior: self.ior,
+            subsurface: float_at(&self.subsurface),
+        })
+        .into()
+    }
+}
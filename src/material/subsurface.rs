@@ -1,19 +1,30 @@
 use std::sync::Arc;
 
 use crate::{
-    bxdf::{self, Bxdf, DielectricFresnel, GgxMicrofacet, MicrofacetPlastic, SpecularPlastic},
+    bxdf::{
+        self, Bxdf, DielectricFresnel, GgxMicrofacet, MicrofacetPlastic, RandomWalk,
+        SpecularPlastic, Substrate,
+    },
     core::{intersection::Intersection, loader::InputParams, scene_resources::SceneResources},
     texture::{Texture, TextureChannel, TextureT},
 };
 
 use super::MaterialT;
 
+enum SubsurfaceMode {
+    /// importance sampled diffusion profile, probing the scene around the shading point
+    Diffusion,
+    /// volumetric random walk inside the mesh, `ld` is the mean free path of each channel
+    RandomWalk { asymmetric: f32, max_bounces: u32 },
+}
+
 pub struct Subsurface {
     ior: f32,
     albedo: Arc<Texture>,
     ld: Arc<Texture>,
     roughness_x: Arc<Texture>,
     roughness_y: Arc<Texture>,
+    mode: SubsurfaceMode,
 }
 
 impl Subsurface {
@@ -32,9 +43,19 @@ impl Subsurface {
             ld,
             roughness_x,
             roughness_y,
+            mode: SubsurfaceMode::Diffusion,
         }
     }
 
+    /// use random walk instead of diffusion profile, `asymmetric` is of the Henyey-Greenstein phase
+    pub fn with_random_walk(mut self, asymmetric: f32, max_bounces: u32) -> Self {
+        self.mode = SubsurfaceMode::RandomWalk {
+            asymmetric,
+            max_bounces,
+        };
+        self
+    }
+
     pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let int_ior = params.get_float("int_ior")?;
         let ext_ior = params.get_float_or("ext_ior", 1.0);
@@ -51,21 +72,52 @@ impl Subsurface {
             (roughness_x, roughness_y)
         };
 
-        Ok(Subsurface::new(
-            int_ior,
-            ext_ior,
-            albedo,
-            ld,
-            roughness_x,
-            roughness_y,
-        ))
+        let material = Subsurface::new(int_ior, ext_ior, albedo, ld, roughness_x, roughness_y);
+        let mode = params.get_str_or("mode", "diffusion");
+        match mode.as_str() {
+            "diffusion" => Ok(material),
+            "random_walk" => {
+                let asymmetric = params.get_float_or("asymmetric", 0.0);
+                let max_bounces = params.get_int_or("max_bounces", 256);
+                if asymmetric.abs() >= 1.0 {
+                    anyhow::bail!(format!(
+                        "{} - asymmetric should be in (-1, 1)",
+                        params.name()
+                    ));
+                }
+                if max_bounces <= 0 {
+                    anyhow::bail!(format!(
+                        "{} - max_bounces should be positive",
+                        params.name()
+                    ));
+                }
+                Ok(material.with_random_walk(asymmetric, max_bounces as u32))
+            }
+            _ => anyhow::bail!(format!(
+                "{} - unknown subsurface mode '{}'",
+                params.name(),
+                mode
+            )),
+        }
     }
 }
 
 impl MaterialT for Subsurface {
     fn bxdf_context(&self, inter: &Intersection<'_>) -> Bxdf {
         let albedo = self.albedo.color_at(inter.into());
-        let ld = self.ld.float_at(inter.into(), TextureChannel::R);
+        let substrate: Substrate = match self.mode {
+            SubsurfaceMode::Diffusion => {
+                let ld = self.ld.float_at(inter.into(), TextureChannel::R);
+                bxdf::Subsurface::new(albedo, self.ior, ld).into()
+            }
+            SubsurfaceMode::RandomWalk {
+                asymmetric,
+                max_bounces,
+            } => {
+                let mfp = self.ld.color_at(inter.into());
+                RandomWalk::new(albedo, mfp, self.ior, asymmetric, max_bounces).into()
+            }
+        };
 
         let roughness_x = self
             .roughness_x
@@ -77,16 +129,12 @@ impl MaterialT for Subsurface {
             .powi(2);
 
         if roughness_x < 0.0001 || roughness_y < 0.0001 {
-            SpecularPlastic::new(
-                DielectricFresnel::new(self.ior).into(),
-                bxdf::Subsurface::new(albedo, self.ior, ld).into(),
-            )
-            .into()
+            SpecularPlastic::new(DielectricFresnel::new(self.ior).into(), substrate).into()
         } else {
             MicrofacetPlastic::new(
                 GgxMicrofacet::new(roughness_x, roughness_y).into(),
                 DielectricFresnel::new(self.ior).into(),
-                bxdf::Subsurface::new(albedo, self.ior, ld).into(),
+                substrate,
             )
             .into()
         }
@@ -1,10 +1,21 @@
 use crate::core::{
-    bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
-    scene_resources::SceneResources, transform::Transform,
+    alias_table::AliasTable,
+    bbox::Bbox,
+    cache::{CacheReader, CacheWriter, Cacheable},
+    intersection::Intersection,
+    loader::InputParams,
+    ray::{self, Ray},
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
 };
 
 use super::{BasicPrimitiveRef, PrimitiveT};
 
+// area sampling, the parameter space is divided into a grid of cells
+// and a cell is chosen in proportion to its estimated area
+const SAMPLE_GRID_SIZE: usize = 4;
+
 // Newton's iteration
 #[cfg(feature = "bezier_ni")]
 const NEWTON_ITERATION_MAX_TIMES: u32 = 16;
@@ -19,10 +30,14 @@ const CLIPPING_EPS: f32 = 0.00001;
 pub struct CubicBezier {
     control_points: [[glam::Vec3A; 4]; 4],
     bbox: Bbox,
+    /// texcoords at corner (0, 0), (0, 1), (1, 1), (1, 0), (u, v) is used if not specified
+    texcoords: Option<[glam::Vec2; 4]>,
+    area: f32,
+    cell_table: AliasTable,
 }
 
 impl CubicBezier {
-    pub fn new(control_points: [[glam::Vec3A; 4]; 4]) -> Self {
+    pub fn new(control_points: [[glam::Vec3A; 4]; 4], texcoords: Option<[glam::Vec2; 4]>) -> Self {
         let (p_min, p_max) = control_points
             .iter()
             .flatten()
@@ -31,30 +46,120 @@ impl CubicBezier {
             });
         let bbox = Bbox::new(p_min, p_max);
 
-        Self {
+        let mut patch = Self {
             control_points,
             bbox,
+            texcoords,
+            area: 0.0,
+            cell_table: AliasTable::new(vec![]),
+        };
+        let cell_areas = patch.cell_areas(Transform::IDENTITY);
+        patch.area = cell_areas.iter().sum();
+        let props = if patch.area > 0.0 {
+            cell_areas.iter().map(|area| area / patch.area).collect()
+        } else {
+            vec![1.0 / cell_areas.len() as f32; cell_areas.len()]
+        };
+        patch.cell_table = AliasTable::new(props);
+
+        patch
+    }
+
+    pub fn area(&self) -> f32 {
+        self.area
+    }
+
+    pub fn texcoords_at(&self, u: f32, v: f32) -> glam::Vec2 {
+        if let Some([t00, t01, t11, t10]) = self.texcoords {
+            (t00 * (1.0 - v) + t01 * v) * (1.0 - u) + (t10 * (1.0 - v) + t11 * v) * u
+        } else {
+            glam::Vec2::new(u, v)
+        }
+    }
+
+    /// returns texcoords, tangent and bitangent, the latter two are derivatives of position
+    /// relative to texcoords, so that normal map works with arbitrary uv layout
+    fn shading_frame_at(&self, u: f32, v: f32) -> (glam::Vec2, glam::Vec3A, glam::Vec3A) {
+        let dpdu = self.tangent_at(u, v);
+        let dpdv = self.bitangent_at(u, v);
+        if let Some([t00, t01, t11, t10]) = self.texcoords {
+            let dtdu = (t10 - t00) * (1.0 - v) + (t11 - t01) * v;
+            let dtdv = (t01 - t00) * (1.0 - u) + (t11 - t10) * u;
+            let jacobian = glam::Mat2::from_cols(dtdu, dtdv);
+            if jacobian.determinant().abs() > 0.000001 {
+                let jacobian_inv = jacobian.inverse();
+                let tangent = dpdu * jacobian_inv.col(0).x + dpdv * jacobian_inv.col(0).y;
+                let bitangent = dpdu * jacobian_inv.col(1).x + dpdv * jacobian_inv.col(1).y;
+                return (self.texcoords_at(u, v), tangent, bitangent);
+            }
         }
+        (self.texcoords_at(u, v), dpdu, dpdv)
     }
 
-    fn point_at(&self, u: f32, v: f32) -> glam::Vec3A {
+    /// estimated area of each cell in sampling grid, using the jacobian at cell center
+    fn cell_areas(&self, trans: Transform) -> Vec<f32> {
+        let cell_size = 1.0 / SAMPLE_GRID_SIZE as f32;
+        let mut areas = Vec::with_capacity(SAMPLE_GRID_SIZE * SAMPLE_GRID_SIZE);
+        for j in 0..SAMPLE_GRID_SIZE {
+            for i in 0..SAMPLE_GRID_SIZE {
+                let u = (i as f32 + 0.5) * cell_size;
+                let v = (j as f32 + 0.5) * cell_size;
+                let tangent = trans.transform_vector3a(self.tangent_at(u, v));
+                let bitangent = trans.transform_vector3a(self.bitangent_at(u, v));
+                areas.push(tangent.cross(bitangent).length() * cell_size * cell_size);
+            }
+        }
+        areas
+    }
+
+    /// pdf relative to area of point at (u, v)
+    fn area_pdf(&self, u: f32, v: f32) -> f32 {
+        let i = ((u * SAMPLE_GRID_SIZE as f32) as usize).min(SAMPLE_GRID_SIZE - 1);
+        let j = ((v * SAMPLE_GRID_SIZE as f32) as usize).min(SAMPLE_GRID_SIZE - 1);
+        let cell_pdf = self.cell_table.probability(j * SAMPLE_GRID_SIZE + i);
+        let jacobian = self
+            .tangent_at(u, v)
+            .cross(self.bitangent_at(u, v))
+            .length();
+        cell_pdf * (SAMPLE_GRID_SIZE * SAMPLE_GRID_SIZE) as f32 / jacobian.max(0.000001)
+    }
+
+    pub fn point_at(&self, u: f32, v: f32) -> glam::Vec3A {
         let bezier_u = cubic_bezier_at(u);
         let bezier_v = cubic_bezier_at(v);
         cubic_bezier_sum(&self.control_points, &bezier_u, &bezier_v)
     }
 
-    fn tangent_at(&self, u: f32, v: f32) -> glam::Vec3A {
+    pub fn tangent_at(&self, u: f32, v: f32) -> glam::Vec3A {
         let bezier_du = cubic_bezier_du_at(u);
         let bezier_v = cubic_bezier_at(v);
         cubic_bezier_sum(&self.control_points, &bezier_du, &bezier_v)
     }
 
-    fn bitangent_at(&self, u: f32, v: f32) -> glam::Vec3A {
+    pub fn bitangent_at(&self, u: f32, v: f32) -> glam::Vec3A {
         let bezier_u = cubic_bezier_at(u);
         let bezier_dv = cubic_bezier_du_at(v);
         cubic_bezier_sum(&self.control_points, &bezier_u, &bezier_dv)
     }
 
+    /// error bound of a point on the patch evaluated by `point_at`
+    fn point_error(&self) -> glam::Vec3A {
+        self.bbox.p_min.abs().max(self.bbox.p_max.abs()) * ray::gamma(20)
+    }
+
+    /// error bound of a hit point, dominated by the tolerance of Newton's iteration
+    #[cfg(feature = "bezier_ni")]
+    fn hit_error(&self) -> glam::Vec3A {
+        self.point_error() + glam::Vec3A::splat(NEWTON_ITERATION_EPS.sqrt())
+    }
+
+    /// error bound of a hit point, dominated by the parametric tolerance of Bezier clipping
+    #[cfg(not(feature = "bezier_ni"))]
+    fn hit_error(&self) -> glam::Vec3A {
+        // derivatives of a cubic are bounded by 3 times the extent of control points
+        self.point_error() + (self.bbox.p_max - self.bbox.p_min) * (3.0 * CLIPPING_EPS)
+    }
+
     /// returns (u, v, t) of intersected point if exists, using Newton's iteration
     #[cfg(feature = "bezier_ni")]
     fn intersect_ray(&self, ray: &Ray) -> Option<(f32, f32, f32)> {
@@ -144,7 +249,7 @@ impl CubicBezier {
             }
         }
 
-        Ok(Self::new(control_points))
+        Ok(Self::new(control_points, None))
     }
 }
 
@@ -160,11 +265,16 @@ impl PrimitiveT for CubicBezier {
     fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
         if let Some((u, v, t)) = self.intersect_ray(ray) {
             if t > ray.t_min && t < inter.t {
+                let (texcoords, tangent, bitangent) = self.shading_frame_at(u, v);
                 inter.t = t;
-                inter.texcoords = glam::Vec2::new(u, v);
-                inter.tangent = self.tangent_at(u, v);
-                inter.bitangent = self.bitangent_at(u, v);
-                inter.normal = (inter.tangent.cross(inter.bitangent)).normalize();
+                inter.position = self.point_at(u, v);
+                inter.p_error = self.hit_error();
+                inter.texcoords = texcoords;
+                inter.prim_uv = glam::Vec2::new(u, v);
+                inter.tangent = tangent;
+                inter.bitangent = bitangent;
+                inter.normal = (self.tangent_at(u, v).cross(self.bitangent_at(u, v))).normalize();
+                inter.geo_normal = inter.normal;
                 inter.primitive = Some(BasicPrimitiveRef::CubicBezier(self));
                 return true;
             }
@@ -176,16 +286,36 @@ impl PrimitiveT for CubicBezier {
         self.bbox
     }
 
-    fn sample<'a>(&'a self, _rng: &mut Rng) -> (Intersection<'a>, f32) {
-        unimplemented!("<CubicBezier as Primitive>::sample() not supported yet")
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let (cell, _) = self.cell_table.sample(rng.uniform_1d());
+        let rand = rng.uniform_2d();
+        let u = ((cell % SAMPLE_GRID_SIZE) as f32 + rand.0) / SAMPLE_GRID_SIZE as f32;
+        let v = ((cell / SAMPLE_GRID_SIZE) as f32 + rand.1) / SAMPLE_GRID_SIZE as f32;
+
+        let (texcoords, tangent, bitangent) = self.shading_frame_at(u, v);
+        let normal = (self.tangent_at(u, v).cross(self.bitangent_at(u, v))).normalize();
+        let inter = Intersection {
+            position: self.point_at(u, v),
+            p_error: self.point_error(),
+            geo_normal: normal,
+            normal,
+            tangent,
+            bitangent,
+            texcoords,
+            prim_uv: glam::Vec2::new(u, v),
+            primitive: Some(BasicPrimitiveRef::CubicBezier(self)),
+            ..Default::default()
+        };
+
+        (inter, self.area_pdf(u, v))
     }
 
-    fn pdf(&self, _inter: &Intersection<'_>) -> f32 {
-        unimplemented!("<CubicBezier as Primitive>::pdf() not supported yet")
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        self.area_pdf(inter.prim_uv.x, inter.prim_uv.y)
     }
 
-    fn surface_area(&self, _trans: Transform) -> f32 {
-        unimplemented!("<CubicBezier as Primitive>::surface_area() not supported yet")
+    fn surface_area(&self, trans: Transform) -> f32 {
+        self.cell_areas(trans).iter().sum()
     }
 }
 
@@ -483,3 +613,35 @@ fn clip_bezier_at_midpoint(points: [glam::Vec2; 4]) -> ([glam::Vec2; 4], [glam::
         ],
     )
 }
+
+impl Cacheable for CubicBezier {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        self.control_points
+            .iter()
+            .flatten()
+            .for_each(|p| writer.write_vec3a(*p));
+        if let Some(texcoords) = self.texcoords {
+            writer.write_u32(1);
+            texcoords.iter().for_each(|uv| writer.write_vec2(*uv));
+        } else {
+            writer.write_u32(0);
+        }
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        let mut control_points = [[glam::Vec3A::ZERO; 4]; 4];
+        for p in control_points.iter_mut().flatten() {
+            *p = reader.read_vec3a()?;
+        }
+        let texcoords = if reader.read_u32()? != 0 {
+            let mut texcoords = [glam::Vec2::ZERO; 4];
+            for uv in &mut texcoords {
+                *uv = reader.read_vec2()?;
+            }
+            Some(texcoords)
+        } else {
+            None
+        };
+        Ok(Self::new(control_points, texcoords))
+    }
+}
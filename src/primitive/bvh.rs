@@ -1,221 +1,269 @@
-use std::{collections::HashSet, sync::Arc};
-
 use crate::core::{
-    bbox::Bbox, intersection::Intersection, ray::Ray, rng::Rng, transform::Transform,
+    bbox::Bbox,
+    cache::{CacheReader, CacheWriter, Cacheable},
+    intersection::Intersection,
+    ray::Ray,
+    rng::Rng,
+    transform::Transform,
 };
 
 use super::PrimitiveT;
 
 pub struct BvhAccel<P: PrimitiveT> {
-    bvh_root: Option<Box<BvhNode>>,
-    primitives: Vec<Arc<P>>,
+    bvh: Bvh,
+    primitives: Vec<P>,
+}
+
+/// flattened bvh, leaves refer to ranges of primitives sorted in build order
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+}
+
+/// 32-byte node, the left child of an interior node is always next to it
+#[repr(C)]
+pub(super) struct BvhNode {
+    pub(super) p_min: glam::Vec3,
+    pub(super) p_max: glam::Vec3,
+    /// first primitive of leaf node or right child of interior node
+    pub(super) offset: u32,
+    /// number of primitives, 0 for interior node
+    pub(super) count: u16,
+    /// split axis of interior node
+    pub(super) axis: u8,
 }
 
-struct BvhNode {
-    lc: Option<Box<BvhNode>>,
-    rc: Option<Box<BvhNode>>,
+const _: () = assert!(std::mem::size_of::<BvhNode>() == 32);
+
+struct BuildNode {
+    lc: Option<Box<BuildNode>>,
+    rc: Option<Box<BuildNode>>,
     bbox: Bbox,
     start: usize,
     end: usize,
+    axis: u8,
 }
 
-impl<P: PrimitiveT> BvhAccel<P> {
-    pub fn new(mut primitives: Vec<Arc<P>>, max_leaf_size: usize, bucket_number: usize) -> Self {
-        if primitives.is_empty() {
-            return Self {
-                bvh_root: None,
-                primitives,
-            };
-        };
+struct Builder<'a> {
+    bboxes: &'a [Bbox],
+    centroids: Vec<glam::Vec3A>,
+    max_leaf_size: usize,
+    bucket_number: usize,
+}
 
-        let mut bbox = primitives[0].bbox();
-        primitives.iter().skip(1).for_each(|prim| {
-            bbox = bbox.merge(prim.bbox());
-        });
-        let mut bvh_root = Box::new(BvhNode::new(0, primitives.len(), bbox));
+/// primitives of the node with bucket index less than `bucket` along `axis` go left
+struct Split {
+    axis: usize,
+    bucket: usize,
+    centroid_min: f32,
+    bucket_scale: f32,
+    left_bbox: Bbox,
+    right_bbox: Bbox,
+}
 
-        let mut stack = vec![&mut bvh_root];
-        while let Some(u) = stack.pop() {
-            if u.size() <= max_leaf_size {
-                continue;
-            }
+/// subtrees with fewer primitives are always built on the current thread
+const PARALLEL_BUILD_THRESHOLD: usize = 4096;
 
-            let bbox = u.bbox;
-            let len_per_bucket = (bbox.p_max - bbox.p_min) / bucket_number as f32;
+impl<P: PrimitiveT> BvhAccel<P> {
+    pub fn new(primitives: Vec<P>, max_leaf_size: usize, bucket_number: usize) -> Self {
+        let bboxes = primitives
+            .iter()
+            .map(|prim| prim.bbox())
+            .collect::<Vec<_>>();
+        let (bvh, order) = Bvh::new(&bboxes, max_leaf_size, bucket_number);
 
-            let mut boxes_x = vec![Bbox::empty(); bucket_number];
-            let mut boxes_y = vec![Bbox::empty(); bucket_number];
-            let mut boxes_z = vec![Bbox::empty(); bucket_number];
+        let mut primitives = primitives.into_iter().map(Some).collect::<Vec<_>>();
+        let primitives = order
+            .into_iter()
+            .map(|i| primitives[i].take().unwrap())
+            .collect();
 
-            let mut prim_indices_x = vec![vec![]; bucket_number];
-            let mut prim_indices_y = vec![vec![]; bucket_number];
-            let mut prim_indices_z = vec![vec![]; bucket_number];
+        Self { bvh, primitives }
+    }
 
-            for i in u.start..u.end {
-                let bbox = primitives[i].bbox();
-                let centroid = bbox.centroid();
+    pub fn primitives(&self) -> &[P] {
+        &self.primitives
+    }
 
-                let x = (centroid.x - bbox.p_min.x) / len_per_bucket.x;
-                if x >= 0.0 && x < bucket_number as f32 {
-                    let x = x as usize;
-                    boxes_x[x] = boxes_x[x].merge(bbox);
-                    prim_indices_x[x].push(i);
-                }
+    pub(super) fn into_parts(self) -> (Bvh, Vec<P>) {
+        (self.bvh, self.primitives)
+    }
+}
 
-                let y = (centroid.y - bbox.p_min.y) / len_per_bucket.y;
-                if y >= 0.0 && y < bucket_number as f32 {
-                    let y = y as usize;
-                    boxes_y[y] = boxes_y[y].merge(bbox);
-                    prim_indices_y[y].push(i);
-                }
+impl Bvh {
+    /// binned sah build, subtrees are built in parallel,
+    /// returns the bvh and the order of primitives that leaves refer to
+    pub fn new(bboxes: &[Bbox], max_leaf_size: usize, bucket_number: usize) -> (Self, Vec<usize>) {
+        let mut order = (0..bboxes.len()).collect::<Vec<_>>();
+        if bboxes.is_empty() {
+            return (Self { nodes: vec![] }, order);
+        };
 
-                let z = (centroid.z - bbox.p_min.z) / len_per_bucket.z;
-                if z >= 0.0 && z < bucket_number as f32 {
-                    let z = z as usize;
-                    boxes_z[z] = boxes_z[z].merge(bbox);
-                    prim_indices_z[z].push(i);
-                }
-            }
+        let builder = Builder {
+            bboxes,
+            centroids: bboxes.iter().map(|bbox| bbox.centroid()).collect(),
+            max_leaf_size: max_leaf_size.max(1),
+            bucket_number: bucket_number.max(2),
+        };
+        let bbox = Builder::range_bbox(bboxes, &order);
+        let spawn_depth = (num_cpus::get() * 2).next_power_of_two().trailing_zeros();
+        let root = builder.build(&mut order, 0, bbox, spawn_depth);
 
-            let (best_cost_x, best_split_x) = if len_per_bucket.x > 0.0001 {
-                Self::find_best_split(&boxes_x, &prim_indices_x, u.size(), bucket_number)
-            } else {
-                (f32::MAX, bucket_number / 2)
-            };
-            let (best_cost_y, best_split_y) = if len_per_bucket.y > 0.0001 {
-                Self::find_best_split(&boxes_y, &prim_indices_y, u.size(), bucket_number)
-            } else {
-                (f32::MAX, bucket_number / 2)
-            };
-            let (best_cost_z, best_split_z) = if len_per_bucket.z > 0.0001 {
-                Self::find_best_split(&boxes_z, &prim_indices_z, u.size(), bucket_number)
-            } else {
-                (f32::MAX, bucket_number / 2)
-            };
+        let mut nodes = vec![];
+        Self::flatten(&root, &mut nodes);
+        let bvh = Self { nodes };
+        bvh.log_stats("bvh", bboxes.len());
 
-            let (lc, rc) = if best_cost_x <= best_cost_y && best_cost_x <= best_cost_z {
-                Self::split_at(
-                    best_split_x,
-                    bucket_number,
-                    &boxes_x,
-                    &mut prim_indices_x,
-                    &mut primitives,
-                    u.start,
-                    u.end,
-                )
-            } else if best_cost_y <= best_cost_x && best_cost_y <= best_cost_z {
-                Self::split_at(
-                    best_split_y,
-                    bucket_number,
-                    &boxes_y,
-                    &mut prim_indices_y,
-                    &mut primitives,
-                    u.start,
-                    u.end,
-                )
-            } else {
-                Self::split_at(
-                    best_split_z,
-                    bucket_number,
-                    &boxes_z,
-                    &mut prim_indices_z,
-                    &mut primitives,
-                    u.start,
-                    u.end,
-                )
-            };
-            if lc.size() == 0 || rc.size() == 0 {
-                continue;
-            }
-            u.lc = Some(lc);
-            u.rc = Some(rc);
-
-            stack.push(u.lc.as_mut().unwrap());
-            stack.push(u.rc.as_mut().unwrap());
-        }
+        (bvh, order)
+    }
 
-        Self {
-            bvh_root: Some(bvh_root),
-            primitives,
+    fn flatten(node: &BuildNode, nodes: &mut Vec<BvhNode>) -> usize {
+        let index = nodes.len();
+        nodes.push(BvhNode {
+            p_min: node.bbox.p_min.into(),
+            p_max: node.bbox.p_max.into(),
+            offset: node.start as u32,
+            count: node.size() as u16,
+            axis: node.axis,
+        });
+        if let (Some(lc), Some(rc)) = (&node.lc, &node.rc) {
+            Self::flatten(lc, nodes);
+            let rc_index = Self::flatten(rc, nodes);
+            nodes[index].offset = rc_index as u32;
+            nodes[index].count = 0;
         }
+        index
     }
 
-    fn find_best_split(
-        boxes: &Vec<Bbox>,
-        prim_indices: &Vec<Vec<usize>>,
-        prim_total_count: usize,
-        bucket_number: usize,
-    ) -> (f32, usize) {
-        let mut best_cost = f32::MAX;
-        let mut best_split = 0;
-        let mut boxes_l = boxes.clone();
-        let mut boxes_r = boxes.clone();
-        let mut prim_count = vec![0; bucket_number];
-        prim_count[0] = prim_indices[0].len();
-        for i in 1..bucket_number {
-            boxes_l[i] = boxes_l[i].merge(boxes_l[i - 1]);
-            prim_count[i] = prim_count[i - 1] + prim_indices[i].len();
+    /// logs node count, depth and sah cost (relative to root area, with unit traversal
+    /// and intersection costs)
+    pub(super) fn log_stats(&self, kind: &str, primitive_count: usize) {
+        if self.nodes.is_empty() {
+            return;
         }
-        for i in (0..bucket_number - 1).rev() {
-            boxes_r[i] = boxes_r[i].merge(boxes_r[i + 1]);
-        }
-        for i in 1..bucket_number {
-            let left_surface_area = boxes_l[i - 1].surface_area();
-            let left_count = prim_count[i - 1] as f32;
-            let right_surface_area = boxes_r[i].surface_area();
-            let right_count = prim_total_count as f32 - left_count;
-            let cost = left_surface_area * left_count + right_surface_area * right_count;
-            if cost < best_cost {
-                best_cost = cost;
-                best_split = i;
+
+        let root_area = self.nodes[0].bbox().surface_area().max(f32::MIN_POSITIVE);
+        let mut leaf_count = 0;
+        let mut reference_count = 0;
+        let mut max_depth = 0;
+        let mut cost = 0.0;
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let area = node.bbox().surface_area() / root_area;
+            max_depth = max_depth.max(depth);
+            if node.count > 0 {
+                leaf_count += 1;
+                reference_count += node.count as usize;
+                cost += area * node.count as f32;
+            } else {
+                cost += area;
+                stack.push((index + 1, depth + 1));
+                stack.push((node.offset as usize, depth + 1));
             }
         }
-        (best_cost, best_split)
-    }
-
-    fn split_at(
-        split: usize,
-        bucket_number: usize,
-        boxes: &Vec<Bbox>,
-        prim_indices: &mut Vec<Vec<usize>>,
-        primitives: &mut Vec<Arc<P>>,
-        start: usize,
-        end: usize,
-    ) -> (Box<BvhNode>, Box<BvhNode>) {
-        let mut left_bbox = Bbox::empty();
-        let mut left_indices = vec![];
-        for i in 0..split {
-            left_bbox = left_bbox.merge(boxes[i]);
-            left_indices.append(&mut prim_indices[i]);
+
+        log::info!(
+            "{} - {} primitives, {} references, {} nodes ({} leaves), depth {}, sah cost {:.2}",
+            kind,
+            primitive_count,
+            reference_count,
+            self.nodes.len(),
+            leaf_count,
+            max_depth,
+            cost
+        );
+    }
+
+    /// calls `hit` for primitives in leaves the ray reaches before `t_max`, front to back,
+    /// `hit` returns the new `t_max` if the primitive is hit
+    pub fn intersect(&self, ray: &Ray, t_max: f32, hit: impl FnMut(usize) -> Option<f32>) -> bool {
+        self.traverse(ray, t_max, false, hit)
+    }
+
+    /// returns true once `hit` returns true for any primitive
+    pub fn intersect_test(
+        &self,
+        ray: &Ray,
+        t_max: f32,
+        mut hit: impl FnMut(usize) -> bool,
+    ) -> bool {
+        self.traverse(
+            ray,
+            t_max,
+            true,
+            |i| if hit(i) { Some(t_max) } else { None },
+        )
+    }
+
+    /// `nodes` must be in depth-first order with the left child next to its parent
+    pub(super) fn from_nodes(nodes: Vec<BvhNode>) -> Self {
+        Self { nodes }
+    }
+
+    pub(super) fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        if let Some(root) = self.nodes.first() {
+            root.bbox()
+        } else {
+            Bbox::empty()
         }
-        let left_indices: HashSet<usize> = left_indices.into_iter().collect();
+    }
 
-        let mut right_bbox = Bbox::empty();
-        let mut right_indices = vec![];
-        for i in split..bucket_number {
-            right_bbox = right_bbox.merge(boxes[i]);
-            right_indices.append(&mut prim_indices[i]);
+    fn traverse(
+        &self,
+        ray: &Ray,
+        mut t_max: f32,
+        any_hit: bool,
+        mut hit: impl FnMut(usize) -> Option<f32>,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
         }
-        let right_indices: HashSet<usize> = right_indices.into_iter().collect();
-
-        let mut rp = end - 1;
-        let mid = start + left_indices.len();
-        for lp in start..mid {
-            if !left_indices.contains(&lp) {
-                while right_indices.contains(&rp) {
-                    rp -= 1;
+
+        let origin = glam::Vec3::from(ray.origin);
+        let inv_dir = glam::Vec3::from(ray.direction).recip();
+        let dir_is_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+        let mut result = false;
+        let mut stack = Vec::with_capacity(64);
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.intersect_test(origin, inv_dir, ray.t_min, t_max) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for i in start..start + node.count as usize {
+                        if let Some(t) = hit(i) {
+                            if any_hit {
+                                return true;
+                            }
+                            t_max = t;
+                            result = true;
+                        }
+                    }
+                } else if dir_is_neg[node.axis as usize] {
+                    stack.push(current + 1);
+                    current = node.offset as usize;
+                    continue;
+                } else {
+                    stack.push(node.offset as usize);
+                    current += 1;
+                    continue;
                 }
-                primitives.swap(lp, rp);
-                rp -= 1;
+            }
+            if let Some(next) = stack.pop() {
+                current = next;
+            } else {
+                return result;
             }
         }
-        let lc = Box::new(BvhNode::new(start, mid, left_bbox));
-        let rc = Box::new(BvhNode::new(mid, end, right_bbox));
-        (lc, rc)
     }
 }
 
-impl BvhNode {
+impl BuildNode {
     fn new(start: usize, end: usize, bbox: Bbox) -> Self {
         Self {
             lc: None,
@@ -223,71 +271,199 @@ impl BvhNode {
             bbox,
             start,
             end,
+            axis: 0,
         }
     }
 
     fn size(&self) -> usize {
         self.end - self.start
     }
-    fn is_leaf(&self) -> bool {
-        self.lc.is_none()
-    }
 }
 
-impl<P: PrimitiveT> PrimitiveT for BvhAccel<P> {
-    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        if self.bvh_root.is_none() {
-            return false;
+impl<'a> Builder<'a> {
+    /// builds the subtree of primitives in `order`, which starts at `start` in the whole order,
+    /// the two children are built on different threads while `spawn_depth` is positive
+    fn build(&self, order: &mut [usize], start: usize, bbox: Bbox, spawn_depth: u32) -> BuildNode {
+        let mut node = BuildNode::new(start, start + order.len(), bbox);
+        if order.len() <= self.max_leaf_size {
+            return node;
         }
 
-        let mut stack = vec![self.bvh_root.as_ref().unwrap()];
-        while let Some(u) = stack.pop() {
-            if !u.bbox.intersect_test(ray, t_max) {
+        let (mid, left_bbox, right_bbox) = if let Some(split) = self.find_split(order) {
+            node.axis = split.axis as u8;
+            let mid = self.partition(order, &split);
+            (mid, split.left_bbox, split.right_bbox)
+        } else if order.len() <= u16::MAX as usize {
+            // all centroids coincide
+            return node;
+        } else {
+            // leaf size is limited by the primitive count of flattened node
+            let mid = order.len() / 2;
+            let left_bbox = Self::range_bbox(self.bboxes, &order[..mid]);
+            let right_bbox = Self::range_bbox(self.bboxes, &order[mid..]);
+            (mid, left_bbox, right_bbox)
+        };
+
+        let parallel = spawn_depth > 0 && order.len() > PARALLEL_BUILD_THRESHOLD;
+        let spawn_depth = spawn_depth.saturating_sub(1);
+        let (left, right) = order.split_at_mut(mid);
+        let (lc, rc) = if parallel {
+            crossbeam::scope(|scope| {
+                let lc = scope.spawn(|_| self.build(left, start, left_bbox, spawn_depth));
+                let rc = self.build(right, start + mid, right_bbox, spawn_depth);
+                (lc.join().unwrap(), rc)
+            })
+            .unwrap()
+        } else {
+            let lc = self.build(left, start, left_bbox, spawn_depth);
+            let rc = self.build(right, start + mid, right_bbox, spawn_depth);
+            (lc, rc)
+        };
+        node.lc = Some(Box::new(lc));
+        node.rc = Some(Box::new(rc));
+        node
+    }
+
+    /// bins primitives by centroid along all 3 axes in one pass and returns the cheapest split
+    fn find_split(&self, order: &[usize]) -> Option<Split> {
+        let centroid_bbox = order.iter().fold(Bbox::empty(), |bbox, &i| {
+            bbox.merge(Bbox::new(self.centroids[i], self.centroids[i]))
+        });
+        let extent = centroid_bbox.p_max - centroid_bbox.p_min;
+        let bucket_scale = glam::Vec3A::select(
+            extent.cmpgt(glam::Vec3A::ZERO),
+            self.bucket_number as f32 / extent,
+            glam::Vec3A::ZERO,
+        );
+
+        let mut buckets = vec![[(Bbox::empty(), 0); 3]; self.bucket_number];
+        for &i in order {
+            for (axis, bucket) in self.buckets_of(i, centroid_bbox.p_min, bucket_scale) {
+                let (bbox, count) = &mut buckets[bucket][axis];
+                *bbox = bbox.merge(self.bboxes[i]);
+                *count += 1;
+            }
+        }
+
+        let mut best_cost = f32::MAX;
+        let mut best = None;
+        for axis in 0..3 {
+            if extent[axis] <= 0.0 {
                 continue;
             }
-            if u.is_leaf() {
-                for i in u.start..u.end {
-                    if self.primitives[i].intersect_test(ray, t_max) {
-                        return true;
-                    }
+
+            let mut right_boxes = vec![(Bbox::empty(), 0); self.bucket_number];
+            let mut right = (Bbox::empty(), 0);
+            for bucket in (1..self.bucket_number).rev() {
+                let (bbox, count) = buckets[bucket][axis];
+                right = (right.0.merge(bbox), right.1 + count);
+                right_boxes[bucket] = right;
+            }
+
+            let mut left = (Bbox::empty(), 0);
+            for bucket in 1..self.bucket_number {
+                let (bbox, count) = buckets[bucket - 1][axis];
+                left = (left.0.merge(bbox), left.1 + count);
+                let right = right_boxes[bucket];
+                if left.1 == 0 || right.1 == 0 {
+                    continue;
+                }
+                let cost =
+                    left.0.surface_area() * left.1 as f32 + right.0.surface_area() * right.1 as f32;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = Some(Split {
+                        axis,
+                        bucket,
+                        centroid_min: centroid_bbox.p_min[axis],
+                        bucket_scale: bucket_scale[axis],
+                        left_bbox: left.0,
+                        right_bbox: right.0,
+                    });
                 }
-            } else {
-                stack.push(u.lc.as_ref().unwrap());
-                stack.push(u.rc.as_ref().unwrap());
             }
         }
-        false
+        best
     }
 
-    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
-        if self.bvh_root.is_none() {
-            return false;
-        }
+    /// bucket index of primitive `i` along each axis, clamped so that no primitive is dropped
+    fn buckets_of(
+        &self,
+        i: usize,
+        centroid_min: glam::Vec3A,
+        bucket_scale: glam::Vec3A,
+    ) -> [(usize, usize); 3] {
+        let b = (self.centroids[i] - centroid_min) * bucket_scale;
+        let max = self.bucket_number - 1;
+        [
+            (0, (b.x.max(0.0) as usize).min(max)),
+            (1, (b.y.max(0.0) as usize).min(max)),
+            (2, (b.z.max(0.0) as usize).min(max)),
+        ]
+    }
 
-        let mut stack = vec![self.bvh_root.as_ref().unwrap()];
-        let mut result = false;
-        while let Some(u) = stack.pop() {
-            if !u.bbox.intersect_test(ray, inter.t) {
-                continue;
+    /// moves primitives going left to the front of `order` and returns their count
+    fn partition(&self, order: &mut [usize], split: &Split) -> usize {
+        let max = self.bucket_number - 1;
+        let mut mid = 0;
+        for i in 0..order.len() {
+            let c = self.centroids[order[i]][split.axis];
+            let bucket =
+                (((c - split.centroid_min) * split.bucket_scale).max(0.0) as usize).min(max);
+            if bucket < split.bucket {
+                order.swap(i, mid);
+                mid += 1;
             }
-            if u.is_leaf() {
-                for i in u.start..u.end {
-                    result |= self.primitives[i].intersect(ray, inter);
-                }
+        }
+        mid
+    }
+
+    fn range_bbox(bboxes: &[Bbox], order: &[usize]) -> Bbox {
+        order
+            .iter()
+            .fold(Bbox::empty(), |bbox, i| bbox.merge(bboxes[*i]))
+    }
+}
+
+impl BvhNode {
+    pub(super) fn bbox(&self) -> Bbox {
+        Bbox::new(self.p_min.into(), self.p_max.into())
+    }
+
+    fn intersect_test(
+        &self,
+        origin: glam::Vec3,
+        inv_dir: glam::Vec3,
+        t_min: f32,
+        t_max: f32,
+    ) -> bool {
+        let t0 = (self.p_min - origin) * inv_dir;
+        let t1 = (self.p_max - origin) * inv_dir;
+        let t_enter = t0.min(t1).max_element();
+        let t_exit = t0.max(t1).min_element();
+        t_enter <= t_exit && t_exit > t_min && t_enter < t_max
+    }
+}
+
+impl<P: PrimitiveT> PrimitiveT for BvhAccel<P> {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.bvh.intersect_test(ray, t_max, |i| {
+            self.primitives[i].intersect_test(ray, t_max)
+        })
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        self.bvh.intersect(ray, inter.t, |i| {
+            if self.primitives[i].intersect(ray, inter) {
+                Some(inter.t)
             } else {
-                stack.push(u.lc.as_ref().unwrap());
-                stack.push(u.rc.as_ref().unwrap());
+                None
             }
-        }
-        result
+        })
     }
 
     fn bbox(&self) -> Bbox {
-        if let Some(root) = &self.bvh_root {
-            root.bbox
-        } else {
-            Bbox::empty()
-        }
+        self.bvh.bbox()
     }
 
     fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
@@ -308,3 +484,74 @@ impl<P: PrimitiveT> PrimitiveT for BvhAccel<P> {
             .sum()
     }
 }
+
+impl Cacheable for Bvh {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        writer.write_len(self.nodes.len());
+        for node in &self.nodes {
+            writer.write_vec3a(node.p_min.into());
+            writer.write_vec3a(node.p_max.into());
+            writer.write_u32(node.offset);
+            writer.write_u32(node.count as u32);
+            writer.write_u32(node.axis as u32);
+        }
+    }
+
+    /// primitive ranges are not checked here, see `Bvh::validate`
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        let len = reader.read_len(36)?;
+        let nodes = (0..len)
+            .map(|_| {
+                let p_min = reader.read_vec3a()?.into();
+                let p_max = reader.read_vec3a()?.into();
+                let offset = reader.read_u32()?;
+                let count = reader.read_u32()?;
+                let axis = reader.read_u32()?;
+                if count > u16::MAX as u32 || axis >= 3 {
+                    anyhow::bail!("invalid bvh node");
+                }
+                Ok(BvhNode {
+                    p_min,
+                    p_max,
+                    offset,
+                    count: count as u16,
+                    axis: axis as u8,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { nodes })
+    }
+}
+
+impl Bvh {
+    /// checks that leaves refer to primitives in `0..primitive_count` and children of interior
+    /// nodes are after them, so that loaded nodes can be traversed safely
+    pub fn validate(&self, primitive_count: usize) -> anyhow::Result<()> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            let offset = node.offset as usize;
+            let valid = if node.count > 0 {
+                offset + node.count as usize <= primitive_count
+            } else {
+                i + 1 < self.nodes.len() && offset > i + 1 && offset < self.nodes.len()
+            };
+            if !valid {
+                anyhow::bail!("invalid bvh node {}", i);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<P: PrimitiveT + Cacheable> Cacheable for BvhAccel<P> {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        self.bvh.write_cache(writer);
+        writer.write_slice(&self.primitives);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        let bvh = Bvh::read_cache(reader)?;
+        let primitives: Vec<P> = reader.read_vec()?;
+        bvh.validate(primitives.len())?;
+        Ok(Self { bvh, primitives })
+    }
+}
@@ -1,17 +1,27 @@
-use std::{collections::HashSet, sync::Arc};
+use std::collections::HashSet;
 
 use pep_mesh::{halfedge, io::ply};
 
 use crate::core::{
-    bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
-    scene_resources::SceneResources, transform::Transform,
+    alias_table::AliasTable,
+    bbox::Bbox,
+    cache::{Cache, CacheReader, CacheWriter, Cacheable},
+    intersection::Intersection,
+    loader::InputParams,
+    ray::Ray,
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
 };
 
-use super::{BvhAccel, CubicBezier, PrimitiveT};
+use super::{BasicPrimitiveRef, BvhAccel, CubicBezier, PrimitiveT};
 
 pub struct VData {
     pos: glam::Vec3A,
     new_pos: Option<glam::Vec3A>,
+    /// corner sharpness
+    sharpness: f32,
+    texcoords: Option<glam::Vec2>,
 }
 
 impl Default for VData {
@@ -19,31 +29,34 @@ impl Default for VData {
         Self {
             pos: glam::Vec3A::new(0.0, 0.0, 0.0),
             new_pos: None,
+            sharpness: 0.0,
+            texcoords: None,
         }
     }
 }
 
 impl From<ply::PropertyMap> for VData {
     fn from(props: ply::PropertyMap) -> Self {
-        let x = props.map.get("x").map_or(0.0, |prop| match prop {
-            ply::Property::F32(val) => *val,
-            ply::Property::F64(val) => *val as f32,
-            _ => 0.0,
-        });
-        let y = props.map.get("y").map_or(0.0, |prop| match prop {
-            ply::Property::F32(val) => *val,
-            ply::Property::F64(val) => *val as f32,
-            _ => 0.0,
-        });
-        let z = props.map.get("z").map_or(0.0, |prop| match prop {
-            ply::Property::F32(val) => *val,
-            ply::Property::F64(val) => *val as f32,
-            _ => 0.0,
-        });
+        let x = get_float_property(&props, "x").unwrap_or(0.0);
+        let y = get_float_property(&props, "y").unwrap_or(0.0);
+        let z = get_float_property(&props, "z").unwrap_or(0.0);
         let pos = glam::Vec3A::new(x, y, z);
 
+        let sharpness = get_float_property(&props, "sharpness").unwrap_or(0.0);
+
+        let texcoords = ["s", "u", "texture_u"]
+            .iter()
+            .zip(["t", "v", "texture_v"].iter())
+            .find_map(|(key_u, key_v)| {
+                let u = get_float_property(&props, key_u)?;
+                let v = get_float_property(&props, key_v)?;
+                Some(glam::Vec2::new(u, v))
+            });
+
         Self {
             pos,
+            sharpness,
+            texcoords,
             ..Default::default()
         }
     }
@@ -58,11 +71,7 @@ pub struct EData {
 
 impl From<ply::PropertyMap> for EData {
     fn from(props: ply::PropertyMap) -> Self {
-        let sharpness = props.map.get("sharpness").map_or(0.0, |prop| match prop {
-            ply::Property::F32(val) => *val,
-            ply::Property::F64(val) => *val as f32,
-            _ => 0.0,
-        });
+        let sharpness = get_float_property(&props, "sharpness").unwrap_or(0.0);
         Self {
             sharpness,
             ..Default::default()
@@ -74,36 +83,138 @@ impl From<ply::PropertyMap> for EData {
 pub struct FData {
     new_pos: Option<glam::Vec3A>,
     is_regular: bool,
+    /// face-varying texcoords of each corner
+    texcoords: Vec<(halfedge::VertexRef, glam::Vec2)>,
+    /// `texcoord` list of the face in the file, (u, v) of each corner in the order of vertices
+    corner_texcoords: Vec<glam::Vec2>,
 }
 
 impl From<ply::PropertyMap> for FData {
-    fn from(_: ply::PropertyMap) -> Self {
-        Self::default()
+    fn from(props: ply::PropertyMap) -> Self {
+        let corner_texcoords = get_float_list_property(&props, "texcoord")
+            .map(|values| {
+                values
+                    .chunks_exact(2)
+                    .map(|uv| glam::Vec2::new(uv[0], uv[1]))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            corner_texcoords,
+            ..Default::default()
+        }
     }
 }
 
+fn get_float_property(props: &ply::PropertyMap, key: &str) -> Option<f32> {
+    props.map.get(key).map(|prop| match prop {
+        ply::Property::F32(val) => *val,
+        ply::Property::F64(val) => *val as f32,
+        _ => 0.0,
+    })
+}
+
+fn get_float_list_property(props: &ply::PropertyMap, key: &str) -> Option<Vec<f32>> {
+    props.map.get(key).and_then(|prop| match prop {
+        ply::Property::List(values) => Some(
+            values
+                .iter()
+                .map(|val| match val {
+                    ply::Property::F32(val) => *val,
+                    ply::Property::F64(val) => *val as f32,
+                    _ => 0.0,
+                })
+                .collect(),
+        ),
+        _ => None,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryInterpolation {
+    /// boundary edges are sharp creases
+    EdgesOnly,
+    /// boundary edges are sharp creases and vertices with only one face are sharp corners
+    EdgesAndCorners,
+}
+
 type Mesh = halfedge::HalfEdgeMesh<VData, EData, FData>;
 
 pub struct CatmullClark {
     bbox: Bbox,
     patches: BvhAccel<CubicBezier>,
+    area: f32,
+    /// chooses patch in proportion to its area, indexed in the order of `patches.primitives()`
+    patch_table: AliasTable,
 }
 
 impl CatmullClark {
-    pub fn new(mut mesh: Mesh, fas_times: u32) -> Self {
-        let patches = feature_adaptive_subdivision(&mut mesh, fas_times);
+    pub fn new(mut mesh: Mesh, fas_times: u32, boundary: BoundaryInterpolation) -> Self {
+        init_face_varying_texcoords(&mut mesh);
+
+        let patches = feature_adaptive_subdivision(&mut mesh, fas_times, boundary);
+        Self::from_patches(patches)
+    }
+
+    fn from_patches(patches: BvhAccel<CubicBezier>) -> Self {
         let bbox = patches.bbox();
 
-        Self { bbox, patches }
+        let area = patches.primitives().iter().map(|patch| patch.area()).sum();
+        let props = patches
+            .primitives()
+            .iter()
+            .map(|patch| patch.area() / area)
+            .collect();
+        let patch_table = AliasTable::new(props);
+
+        Self {
+            bbox,
+            patches,
+            area,
+            patch_table,
+        }
     }
 
     pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let ply_file = params.get_file_path("ply_file")?;
-        let mesh = ply::load_to_halfedge(ply_file)?;
 
         let fas_times = params.get_int_or("fas_times", 4) as u32;
 
-        Ok(Self::new(mesh, fas_times))
+        let boundary_str = params.get_str_or("boundary_interpolation", "edges_only");
+        let boundary = match boundary_str.as_str() {
+            "edges_only" => BoundaryInterpolation::EdgesOnly,
+            "edges_and_corners" => BoundaryInterpolation::EdgesAndCorners,
+            ty => anyhow::bail!(format!(
+                "{} - unknown boundary interpolation '{}'",
+                params.name(),
+                ty
+            )),
+        };
+
+        let cache = Cache::open(
+            params,
+            "catmull_clark",
+            &ply_file,
+            &format!("{} {}", fas_times, boundary_str),
+        )?;
+        Cache::load_or_build(cache, || {
+            let mesh = ply::load_to_halfedge(ply_file)?;
+            Ok(Self::new(mesh, fas_times, boundary))
+        })
+    }
+
+    pub fn patches(&self) -> &[CubicBezier] {
+        self.patches.primitives()
+    }
+}
+
+impl Cacheable for CatmullClark {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        self.patches.write_cache(writer);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        Ok(Self::from_patches(BvhAccel::read_cache(reader)?))
     }
 }
 
@@ -120,20 +231,70 @@ impl PrimitiveT for CatmullClark {
         self.bbox
     }
 
-    fn sample<'a>(&'a self, _rng: &mut Rng) -> (Intersection<'a>, f32) {
-        unimplemented!("<CatmullClark as Primitive>::sample() not supported yet")
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let (index, prob) = self.patch_table.sample(rng.uniform_1d());
+        let (inter, pdf) = self.patches.primitives()[index].sample(rng);
+        (inter, pdf * prob)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        if let Some(BasicPrimitiveRef::CubicBezier(patch)) = inter.primitive {
+            patch.pdf(inter) * patch.area() / self.area
+        } else {
+            0.0
+        }
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        self.patches.surface_area(trans)
     }
+}
 
-    fn pdf(&self, _inter: &Intersection<'_>) -> f32 {
-        unimplemented!("<CatmullClark as Primitive>::pdf() not supported yet")
+/// uvs are interpolated linearly at face-varying boundaries as well as inside,
+/// so each face keeps its own copy of corner texcoords,
+/// they come from the `texcoord` list of the face if it has one for every corner,
+/// whose i-th entry is of the i-th vertex of the face starting from the origin of its halfedge,
+/// and from texcoords of vertices otherwise
+fn init_face_varying_texcoords(mesh: &mut Mesh) {
+    let has_vertex_texcoords = mesh.vertices().any(|v| v.data(mesh).texcoords.is_some());
+    let has_corner_texcoords = mesh
+        .faces()
+        .any(|f| !f.data(mesh).corner_texcoords.is_empty());
+    if !has_vertex_texcoords && !has_corner_texcoords {
+        return;
     }
 
-    fn surface_area(&self, _trans: Transform) -> f32 {
-        unimplemented!("<CatmullClark as Primitive>::surface_area() not supported yet")
+    for face in mesh.faces().collect::<Vec<_>>() {
+        if face.is_boundary(mesh) {
+            continue;
+        }
+
+        let corner_texcoords = std::mem::take(&mut face.data_mut(mesh).corner_texcoords);
+        let use_corners = corner_texcoords.len() == face.degree(mesh);
+        let mut texcoords = vec![];
+        let mut he = face.halfedge(mesh);
+        loop {
+            let v = he.vertex(mesh);
+            let uv = if use_corners {
+                corner_texcoords[texcoords.len()]
+            } else {
+                v.data(mesh).texcoords.unwrap_or(glam::Vec2::ZERO)
+            };
+            texcoords.push((v, uv));
+            he = he.next(mesh);
+            if he == face.halfedge(mesh) {
+                break;
+            }
+        }
+        face.data_mut(mesh).texcoords = texcoords;
     }
 }
 
-fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAccel<CubicBezier> {
+fn feature_adaptive_subdivision(
+    mesh: &mut Mesh,
+    max_iter_times: u32,
+    boundary: BoundaryInterpolation,
+) -> BvhAccel<CubicBezier> {
     let mut process_faces = mesh.faces().collect::<Vec<_>>();
 
     let mut patches = vec![];
@@ -243,16 +404,24 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
                 if v.data(mesh).new_pos.is_none() {
                     let mut crease_he_pos1 = None;
                     let mut crease_he_pos2 = None;
+                    let mut sum_sharpness = 0.0;
+                    let mut num_faces = 0;
                     let num_creases = {
                         let mut he = v.halfedge(mesh);
                         let mut num_creases = 0;
                         loop {
                             let twin = he.twin(mesh);
-                            if he.data(mesh).sharpness > 0.0
-                                || he.on_boundary(mesh)
-                                || twin.on_boundary(mesh)
-                            {
+                            if !he.on_boundary(mesh) {
+                                num_faces += 1;
+                            }
+                            let is_boundary = he.on_boundary(mesh) || twin.on_boundary(mesh);
+                            if he.data(mesh).sharpness > 0.0 || is_boundary {
                                 num_creases += 1;
+                                sum_sharpness += if is_boundary {
+                                    1.0
+                                } else {
+                                    he.data(mesh).sharpness.min(1.0)
+                                };
                                 if crease_he_pos1.is_none() {
                                     crease_he_pos1 = Some(twin.vertex(mesh).data(mesh).pos);
                                 } else if crease_he_pos2.is_none() {
@@ -269,40 +438,61 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
 
                     let pos_v = v.data(mesh).pos;
 
-                    if num_creases > 2 {
-                        v.data_mut(mesh).new_pos = Some(pos_v);
-                    } else if num_creases == 2 {
-                        let pos_crease = {
-                            assert!(crease_he_pos1.is_some() && crease_he_pos2.is_some());
-                            let pos1 = crease_he_pos1.unwrap();
-                            let pos2 = crease_he_pos2.unwrap();
-                            0.75 * pos_v + 0.125 * pos1 + 0.125 * pos2
-                        };
-                        v.data_mut(mesh).new_pos = Some(pos_crease);
-                    } else {
-                        let pos_smooth = {
-                            let mut n = 0.0;
-                            let mut sum = glam::Vec3A::new(0.0, 0.0, 0.0);
-                            let mut vhe = he;
-                            loop {
-                                let twin = vhe.twin(mesh);
-                                n += 1.0;
-                                sum += twin.vertex(mesh).data(mesh).pos;
-                                if let Some(pos_f) = vhe.face(mesh).data(mesh).new_pos {
-                                    sum += pos_f;
-                                } else {
-                                    sum += pos_v;
-                                }
-                                vhe = twin.next(mesh);
-                                if vhe == he {
-                                    break;
-                                }
+                    let pos_smooth = {
+                        let mut n = 0.0;
+                        let mut sum = glam::Vec3A::new(0.0, 0.0, 0.0);
+                        let mut vhe = he;
+                        loop {
+                            let twin = vhe.twin(mesh);
+                            n += 1.0;
+                            sum += twin.vertex(mesh).data(mesh).pos;
+                            if let Some(pos_f) = vhe.face(mesh).data(mesh).new_pos {
+                                sum += pos_f;
+                            } else {
+                                sum += pos_v;
+                            }
+                            vhe = twin.next(mesh);
+                            if vhe == he {
+                                break;
                             }
-                            let n_inv = 1.0 / n;
-                            ((n - 2.0) * pos_v + sum * n_inv) * n_inv
+                        }
+                        let n_inv = 1.0 / n;
+                        ((n - 2.0) * pos_v + sum * n_inv) * n_inv
+                    };
+
+                    // semi-sharp features blend with the smooth rule by their sharpness
+                    let avg_sharpness = if num_creases > 0 {
+                        sum_sharpness / num_creases as f32
+                    } else {
+                        0.0
+                    };
+                    let pos_crease = if num_creases == 2 {
+                        assert!(crease_he_pos1.is_some() && crease_he_pos2.is_some());
+                        let pos1 = crease_he_pos1.unwrap();
+                        let pos2 = crease_he_pos2.unwrap();
+                        let pos_crease = 0.75 * pos_v + 0.125 * pos1 + 0.125 * pos2;
+                        pos_smooth.lerp(pos_crease, avg_sharpness)
+                    } else {
+                        pos_smooth
+                    };
+
+                    let mut corner_weight = v.data(mesh).sharpness.min(1.0);
+                    if num_creases > 2 {
+                        let weight = if v.on_boundary(mesh) {
+                            1.0
+                        } else {
+                            avg_sharpness
                         };
-                        v.data_mut(mesh).new_pos = Some(pos_smooth);
+                        corner_weight = corner_weight.max(weight);
+                    }
+                    if boundary == BoundaryInterpolation::EdgesAndCorners
+                        && v.on_boundary(mesh)
+                        && num_faces == 1
+                    {
+                        corner_weight = 1.0;
                     }
+
+                    v.data_mut(mesh).new_pos = Some(pos_crease.lerp(pos_v, corner_weight));
                 }
                 he = he.next(mesh);
                 if he == face.halfedge(mesh) {
@@ -318,7 +508,7 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
                 if he.data(mesh).new_vert.is_none() {
                     he.data_mut(mesh).new_vert = Some(mesh.create_vertex(VData {
                         pos: he.data(mesh).new_pos.unwrap(),
-                        new_pos: None,
+                        ..Default::default()
                     }));
                     halfedges.push(he);
                 }
@@ -328,12 +518,26 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
                 }
             }
         }
-        for he in halfedges {
+        // sharpness of the two child edges decays with chaikin's rule,
+        // all of them are computed ahead as splitting changes the neighbourhood
+        let child_sharpness = halfedges
+            .iter()
+            .map(|he| {
+                let sharpness = he.data(mesh).sharpness;
+                if sharpness > 0.0 {
+                    let s1 = chaikin_sharpness(he, mesh);
+                    let s2 = chaikin_sharpness(&he.twin(mesh), mesh);
+                    (s1, s2)
+                } else {
+                    (0.0, 0.0)
+                }
+            })
+            .collect::<Vec<_>>();
+        for (he, (sharpness1, sharpness2)) in halfedges.into_iter().zip(child_sharpness) {
             let ev = he.data(mesh).new_vert.unwrap();
 
-            let new_sharpness = (he.data(mesh).sharpness - 1.0).max(0.0);
             *he.data_mut(mesh) = EData {
-                sharpness: new_sharpness,
+                sharpness: sharpness2,
                 ..Default::default()
             };
 
@@ -341,7 +545,7 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
                 &he.vertex(mesh),
                 &ev,
                 EData {
-                    sharpness: new_sharpness,
+                    sharpness: sharpness1,
                     ..Default::default()
                 },
             );
@@ -365,12 +569,14 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
         for face in &to_be_subdivided {
             let fv = mesh.create_vertex(VData {
                 pos: face.data(mesh).new_pos.unwrap(),
-                new_pos: None,
+                ..Default::default()
             });
             let is_regular = face.data(mesh).is_regular;
+            let texcoords = std::mem::take(&mut face.data_mut(mesh).texcoords);
             *face.data_mut(mesh) = FData::default();
             let mut new_edges = vec![];
             let mut new_faces = vec![];
+            let mut corners = vec![];
             let mut count = 0;
 
             let mut he = face.halfedge(mesh);
@@ -387,6 +593,7 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
 
                 new_edges.push(new_edge.0);
                 new_edges.push(new_edge.1);
+                corners.push((he_last.vertex(mesh), ev));
 
                 if count == 0 {
                     new_faces.push(*face);
@@ -398,8 +605,10 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
 
                 let vert = he_last.vertex(mesh);
                 if let Some(v_pos) = vert.data(mesh).new_pos {
-                    vert.data_mut(mesh).pos = v_pos;
-                    vert.data_mut(mesh).new_pos = None;
+                    let vert_data = vert.data_mut(mesh);
+                    vert_data.pos = v_pos;
+                    vert_data.new_pos = None;
+                    vert_data.sharpness = (vert_data.sharpness - 1.0).max(0.0);
                 }
 
                 he = he.next(mesh);
@@ -423,6 +632,31 @@ fn feature_adaptive_subdivision(mesh: &mut Mesh, max_iter_times: u32) -> BvhAcce
                 }
             }
 
+            if !texcoords.is_empty() {
+                let texcoords_of = |v: halfedge::VertexRef| {
+                    texcoords
+                        .iter()
+                        .find(|(vert, _)| *vert == v)
+                        .map_or(glam::Vec2::ZERO, |(_, uv)| *uv)
+                };
+                let uv_face = texcoords
+                    .iter()
+                    .fold(glam::Vec2::ZERO, |sum, (_, uv)| sum + *uv)
+                    / texcoords.len() as f32;
+                for i in 0..count {
+                    let (v_last, ev_last) = corners[(i + count - 1) % count];
+                    let (v, ev) = corners[i];
+                    let (v_next, _) = corners[(i + 1) % count];
+                    let uv = texcoords_of(v);
+                    new_faces[i].data_mut(mesh).texcoords = vec![
+                        (v, uv),
+                        (ev, (uv + texcoords_of(v_next)) * 0.5),
+                        (fv, uv_face),
+                        (ev_last, (uv + texcoords_of(v_last)) * 0.5),
+                    ];
+                }
+            }
+
             if !is_regular {
                 process_faces.append(&mut new_faces);
             }
@@ -456,7 +690,7 @@ fn check_if_regular(face: &halfedge::FaceRef, mesh: &Mesh) -> bool {
         if v.on_boundary(mesh) {
             vert_deg += 1;
         }
-        if vert_deg != 4 || he.data(mesh).sharpness > 0.0 {
+        if vert_deg != 4 || he.data(mesh).sharpness > 0.0 || v.data(mesh).sharpness > 0.0 {
             is_regular = false;
         } else {
             he = he.next(mesh);
@@ -469,7 +703,51 @@ fn check_if_regular(face: &halfedge::FaceRef, mesh: &Mesh) -> bool {
     is_regular
 }
 
-fn get_bezier_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> Arc<CubicBezier> {
+/// sharpness of the child edge at the origin of `he` after one subdivision
+fn chaikin_sharpness(he: &halfedge::HalfEdgeRef, mesh: &Mesh) -> f32 {
+    let sharpness = he.data(mesh).sharpness;
+
+    let mut adj_sharpness = None;
+    let mut num_adj = 0;
+    let mut vhe = he.twin(mesh).next(mesh);
+    while vhe != *he {
+        if vhe.data(mesh).sharpness > 0.0 {
+            adj_sharpness = Some(vhe.data(mesh).sharpness);
+            num_adj += 1;
+        }
+        vhe = vhe.twin(mesh).next(mesh);
+    }
+    let adj_sharpness = if num_adj == 1 {
+        adj_sharpness.unwrap()
+    } else {
+        sharpness
+    };
+
+    (0.75 * sharpness + 0.25 * adj_sharpness - 1.0).max(0.0)
+}
+
+/// face-varying texcoords at the 4 corners, starting from the origin of `he`
+fn get_corner_texcoords(
+    face: &halfedge::FaceRef,
+    he: halfedge::HalfEdgeRef,
+    mesh: &Mesh,
+) -> Option<[glam::Vec2; 4]> {
+    let texcoords = &face.data(mesh).texcoords;
+    if texcoords.is_empty() {
+        return None;
+    }
+
+    let mut result = [glam::Vec2::ZERO; 4];
+    let mut he = he;
+    for uv in &mut result {
+        let v = he.vertex(mesh);
+        *uv = texcoords.iter().find(|(vert, _)| *vert == v)?.1;
+        he = he.next(mesh);
+    }
+    Some(result)
+}
+
+fn get_bezier_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> CubicBezier {
     let mut control_points = [[glam::Vec3A::new(0.0, 0.0, 0.0); 4]; 4];
 
     let order = [
@@ -549,10 +827,12 @@ fn get_bezier_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> Arc<CubicBezier> {
         }
     }
 
-    Arc::new(CubicBezier::new(control_points))
+    let texcoords = get_corner_texcoords(face, face.halfedge(mesh).next(mesh), mesh);
+
+    CubicBezier::new(control_points, texcoords)
 }
 
-fn get_gregory_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> Arc<CubicBezier> {
+fn get_gregory_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> CubicBezier {
     let mut control_points = [[glam::Vec3A::new(0.0, 0.0, 0.0); 4]; 4];
 
     let he = face.halfedge(mesh);
@@ -620,7 +900,9 @@ fn get_gregory_patch(face: &halfedge::FaceRef, mesh: &Mesh) -> Arc<CubicBezier>
         calc_face_control_points_neg(pos3, e3_neg, e2_pos, &edge_points3, &face_points3, n3, n2);
     control_points[2][1] = (f3_pos + f3_neg) * 0.5;
 
-    Arc::new(CubicBezier::new(control_points))
+    let texcoords = get_corner_texcoords(face, face.halfedge(mesh), mesh);
+
+    CubicBezier::new(control_points, texcoords)
 }
 
 fn get_edge_points_and_face_points(
@@ -0,0 +1,150 @@
+use crate::core::cache::{CacheReader, CacheWriter, Cacheable};
+
+use super::MeshVertex;
+
+/// vertex data of a mesh in 20 bytes per vertex instead of 80 of `MeshVertex`,
+/// positions are stored unaligned, normals are octahedral-encoded in 2x16 bits,
+/// texcoords are quantized to 2x16 bits in the texcoord bounds of the mesh,
+/// and tangents are not stored but derived from each triangle
+pub struct CompactVertices {
+    positions: Vec<glam::Vec3>,
+    normals: Vec<u32>,
+    texcoords: Vec<u32>,
+    uv_min: glam::Vec2,
+    uv_extent: glam::Vec2,
+}
+
+impl CompactVertices {
+    pub fn new(vertices: &[MeshVertex]) -> Self {
+        let (uv_min, uv_max) = vertices.iter().fold(
+            (glam::Vec2::splat(f32::MAX), glam::Vec2::splat(f32::MIN)),
+            |(min, max), vert| (min.min(vert.texcoords), max.max(vert.texcoords)),
+        );
+        let (uv_min, uv_extent) = if vertices.is_empty() {
+            (glam::Vec2::ZERO, glam::Vec2::ZERO)
+        } else {
+            (uv_min, uv_max - uv_min)
+        };
+
+        let uv_scale = glam::Vec2::select(
+            uv_extent.cmpgt(glam::Vec2::ZERO),
+            u16::MAX as f32 / uv_extent,
+            glam::Vec2::ZERO,
+        );
+        let texcoords = vertices
+            .iter()
+            .map(|vert| {
+                let q = ((vert.texcoords - uv_min) * uv_scale).round();
+                pack_u16x2(q.x as u16, q.y as u16)
+            })
+            .collect();
+
+        Self {
+            positions: vertices.iter().map(|vert| vert.position.into()).collect(),
+            normals: vertices
+                .iter()
+                .map(|vert| encode_octahedral(vert.normal))
+                .collect(),
+            texcoords,
+            uv_min,
+            uv_extent,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn position(&self, index: usize) -> glam::Vec3A {
+        self.positions[index].into()
+    }
+
+    pub fn normal(&self, index: usize) -> glam::Vec3A {
+        decode_octahedral(self.normals[index])
+    }
+
+    pub fn texcoords(&self, index: usize) -> glam::Vec2 {
+        let (x, y) = unpack_u16x2(self.texcoords[index]);
+        let q = glam::Vec2::new(x as f32, y as f32) / u16::MAX as f32;
+        self.uv_min + q * self.uv_extent
+    }
+
+    /// decoded vertices, tangents are left default
+    pub fn to_vertices(&self) -> Vec<MeshVertex> {
+        (0..self.len())
+            .map(|i| MeshVertex {
+                position: self.position(i),
+                normal: self.normal(i),
+                texcoords: self.texcoords(i),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+impl Cacheable for CompactVertices {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        writer.write_len(self.positions.len());
+        for p in &self.positions {
+            writer.write_vec3a((*p).into());
+        }
+        writer.write_u32s(&self.normals);
+        writer.write_u32s(&self.texcoords);
+        writer.write_vec2(self.uv_min);
+        writer.write_vec2(self.uv_extent);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        let len = reader.read_len(12)?;
+        let positions = (0..len)
+            .map(|_| reader.read_vec3a().map(glam::Vec3::from))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let normals = reader.read_u32s()?;
+        let texcoords = reader.read_u32s()?;
+        if normals.len() != len || texcoords.len() != len {
+            anyhow::bail!("invalid compact vertices");
+        }
+        Ok(Self {
+            positions,
+            normals,
+            texcoords,
+            uv_min: reader.read_vec2()?,
+            uv_extent: reader.read_vec2()?,
+        })
+    }
+}
+
+fn pack_u16x2(x: u16, y: u16) -> u32 {
+    x as u32 | (y as u32) << 16
+}
+
+fn unpack_u16x2(packed: u32) -> (u16, u16) {
+    (packed as u16, (packed >> 16) as u16)
+}
+
+/// maps the unit sphere onto an octahedron then unfolds it into [-1, 1]^2,
+/// each coordinate is stored as 16-bit snorm
+fn encode_octahedral(n: glam::Vec3A) -> u32 {
+    let n = n / (n.x.abs() + n.y.abs() + n.z.abs());
+    let (x, y) = if n.z >= 0.0 {
+        (n.x, n.y)
+    } else {
+        (
+            (1.0 - n.y.abs()).copysign(n.x),
+            (1.0 - n.x.abs()).copysign(n.y),
+        )
+    };
+    let snorm = |v: f32| (v.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16 as u16;
+    pack_u16x2(snorm(x), snorm(y))
+}
+
+fn decode_octahedral(packed: u32) -> glam::Vec3A {
+    let (x, y) = unpack_u16x2(packed);
+    let x = (x as i16 as f32 / i16::MAX as f32).max(-1.0);
+    let y = (y as i16 as f32 / i16::MAX as f32).max(-1.0);
+    let z = 1.0 - x.abs() - y.abs();
+    let t = (-z).max(0.0);
+    let x = if x >= 0.0 { x - t } else { x + t };
+    let y = if y >= 0.0 { y - t } else { y + t };
+    glam::Vec3A::new(x, y, z).normalize()
+}
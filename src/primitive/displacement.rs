@@ -0,0 +1,429 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    camera::{Camera, CameraT},
+    core::{bbox::Bbox, loader::InputParams, scene_resources::SceneResources},
+    texture::{Texture, TextureChannel, TextureInput, TextureInputMode, TextureT},
+};
+
+use super::{CubicBezier, MeshVertex, Primitive, PrimitiveT, TriMesh};
+
+pub enum Tessellation {
+    /// every edge is split `level` times
+    Uniform(u32),
+    /// edges are split until their projected length is below `edge_length`,
+    /// which is measured on the film plane where the image height is 1
+    Adaptive {
+        camera: Arc<Camera>,
+        edge_length: f32,
+        max_level: u32,
+    },
+}
+
+pub struct Displacement {
+    map: Arc<Texture>,
+    scale: f32,
+    offset: f32,
+    tessellation: Tessellation,
+}
+
+impl Displacement {
+    pub fn new(map: Arc<Texture>, scale: f32, offset: f32, tessellation: Tessellation) -> Self {
+        Self {
+            map,
+            scale,
+            offset,
+            tessellation,
+        }
+    }
+
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let map = rsc.clone_texture(params.get_str("displacement_map")?)?;
+        let scale = params.get_float_or("displacement_scale", 1.0);
+        let offset = params.get_float_or("displacement_offset", 0.0);
+
+        let ty = if params.contains_key("tessellation") {
+            params.get_str("tessellation")?
+        } else {
+            "uniform".to_owned()
+        };
+        let tessellation = match ty.as_str() {
+            "uniform" => Tessellation::Uniform(params.get_int_or("tessellation_level", 3) as u32),
+            "adaptive" => {
+                let camera = rsc.clone_camera(params.get_str("tessellation_camera")?)?;
+                let edge_length = params.get_float_or("tessellation_edge_length", 0.005);
+                let max_level = params.get_int_or("tessellation_level", 8) as u32;
+                Tessellation::Adaptive {
+                    camera,
+                    edge_length,
+                    max_level,
+                }
+            }
+            _ => anyhow::bail!(format!(
+                "{} - unknown tessellation type '{}'",
+                params.name(),
+                ty
+            )),
+        };
+
+        Ok(Self::new(map, scale, offset, tessellation))
+    }
+
+    /// tessellates and displaces `primitive` into a new mesh,
+    /// returns `None` if the primitive type doesn't support displacement
+    pub fn displace(&self, primitive: &Primitive, trans: glam::Affine3A) -> Option<Primitive> {
+        let (mut vertices, indices, compact) = match primitive {
+            Primitive::TriMesh(mesh) => {
+                let (vertices, indices) = mesh.mesh_data();
+                let (vertices, indices) = self.refine_triangles(vertices, indices, trans);
+                (vertices, indices, mesh.is_compact())
+            }
+            Primitive::LoopSubdivision(surface) => {
+                let (vertices, indices) = surface.mesh().mesh_data();
+                let (vertices, indices) = self.refine_triangles(vertices, indices, trans);
+                (vertices, indices, surface.mesh().is_compact())
+            }
+            Primitive::CatmullClark(surface) => {
+                let (vertices, indices) = self.tessellate_patches(surface.patches(), trans);
+                (vertices, indices, false)
+            }
+            _ => return None,
+        };
+
+        for vertex in &mut vertices {
+            let input = TextureInput {
+                texcoords: vertex.texcoords,
+                position: vertex.position,
+                normal: vertex.normal,
+                tangent: vertex.tangent,
+                bitangent: vertex.bitangent,
+                mode: TextureInputMode::Texcoords,
+                ..Default::default()
+            };
+            let height = self.map.float_at(input, TextureChannel::R) * self.scale + self.offset;
+            vertex.position += vertex.normal * height;
+        }
+
+        TriMesh::calc_normals(&mut vertices, &indices);
+        TriMesh::calc_tangents(&mut vertices, &indices);
+
+        let mesh = TriMesh::new(vertices, indices);
+        Some(if compact { mesh.into_compact() } else { mesh }.into())
+    }
+
+    fn max_level(&self) -> u32 {
+        match self.tessellation {
+            Tessellation::Uniform(level) => level,
+            Tessellation::Adaptive { max_level, .. } => max_level,
+        }
+    }
+
+    /// returns how many segments the edge (p0, p1) should be divided into
+    fn edge_segments(&self, p0: glam::Vec3A, p1: glam::Vec3A, trans: glam::Affine3A) -> u32 {
+        match &self.tessellation {
+            Tessellation::Uniform(level) => 1 << level,
+            Tessellation::Adaptive {
+                camera,
+                edge_length,
+                max_level,
+            } => {
+                let s0 = camera.project(trans.transform_point3a(p0));
+                let s1 = camera.project(trans.transform_point3a(p1));
+                if let (Some(s0), Some(s1)) = (s0, s1) {
+                    let length = ((s0.0 - s1.0).powi(2) + (s0.1 - s1.1).powi(2)).sqrt();
+                    ((length / edge_length).ceil() as u32).clamp(1, 1 << max_level)
+                } else {
+                    1
+                }
+            }
+        }
+    }
+
+    /// splits edges at midpoints, the decision only depends on the two end points of an edge
+    /// so that adjacent triangles always agree and no crack is introduced
+    fn refine_triangles(
+        &self,
+        mut vertices: Vec<MeshVertex>,
+        mut indices: Vec<u32>,
+        trans: glam::Affine3A,
+    ) -> (Vec<MeshVertex>, Vec<u32>) {
+        for _ in 0..self.max_level() {
+            let mut midpoints = HashMap::new();
+            let mut new_indices = Vec::with_capacity(indices.len() * 4);
+
+            for tri in indices.chunks_exact(3) {
+                let mut mids = [None; 3];
+                for (e, mid) in mids.iter_mut().enumerate() {
+                    let i0 = tri[e];
+                    let i1 = tri[(e + 1) % 3];
+                    let p0 = vertices[i0 as usize].position;
+                    let p1 = vertices[i1 as usize].position;
+                    if self.edge_segments(p0, p1, trans) > 1 {
+                        let index =
+                            *midpoints
+                                .entry((i0.min(i1), i0.max(i1)))
+                                .or_insert_with(|| {
+                                    let v = lerp_vertex(
+                                        &vertices[i0 as usize],
+                                        &vertices[i1 as usize],
+                                        0.5,
+                                    );
+                                    vertices.push(v);
+                                    vertices.len() as u32 - 1
+                                });
+                        *mid = Some(index);
+                    }
+                }
+                split_triangle(tri, mids, &mut new_indices);
+            }
+
+            if midpoints.is_empty() {
+                break;
+            }
+            indices = new_indices;
+        }
+
+        (vertices, indices)
+    }
+
+    /// tessellates every patch by the rates of its 4 edges, vertices on an edge are shared by
+    /// the patches around it so that no T-junction is introduced, the interior is a grid whose
+    /// rate is the larger one of the opposite edges, and it is stitched to the edges
+    fn tessellate_patches(
+        &self,
+        patches: &[CubicBezier],
+        trans: glam::Affine3A,
+    ) -> (Vec<MeshVertex>, Vec<u32>) {
+        const CORNERS: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+
+        let bbox = patches
+            .iter()
+            .fold(Bbox::empty(), |bbox, patch| bbox.merge(patch.bbox()));
+        let mut welder = PointWelder::new(bbox.radius_sqr().sqrt() * 1e-5);
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        // vertices of the edge between 2 welded corners, ordered from the smaller one
+        let mut edges = HashMap::<(u32, u32), Vec<u32>>::new();
+
+        for patch in patches {
+            let corners: Vec<_> = CORNERS
+                .iter()
+                .map(|(u, v)| welder.weld(patch.point_at(*u, *v)))
+                .collect();
+
+            // vertices and (u, v) along each edge, in counter-clockwise order around the patch
+            let mut sides = vec![];
+            for e in 0..4 {
+                let (c0, c1) = (corners[e], corners[(e + 1) % 4]);
+                let (uv0, uv1) = (CORNERS[e], CORNERS[(e + 1) % 4]);
+                let key = (c0.min(c1), c0.max(c1));
+                let m = self
+                    .edge_segments(welder.point(key.0), welder.point(key.1), trans)
+                    .max(1);
+                let params: Vec<_> = (0..=m)
+                    .map(|i| {
+                        let t = i as f32 / m as f32;
+                        (uv0.0 + (uv1.0 - uv0.0) * t, uv0.1 + (uv1.1 - uv0.1) * t)
+                    })
+                    .collect();
+
+                let mut side = match edges.get(&key) {
+                    Some(shared) if shared.len() == params.len() => {
+                        let mut shared = shared.clone();
+                        if c0 != key.0 {
+                            shared.reverse();
+                        }
+                        shared
+                    }
+                    _ => {
+                        let side: Vec<_> = params
+                            .iter()
+                            .map(|(u, v)| {
+                                vertices.push(patch_vertex(patch, *u, *v));
+                                vertices.len() as u32 - 1
+                            })
+                            .collect();
+                        let mut shared = side.clone();
+                        if c0 != key.0 {
+                            shared.reverse();
+                        }
+                        edges.insert(key, shared);
+                        side
+                    }
+                };
+                // texcoords may be discontinuous across the edge
+                for (index, (u, v)) in side.iter_mut().zip(&params) {
+                    let texcoords = patch.texcoords_at(*u, *v);
+                    let vertex = &vertices[*index as usize];
+                    if vertex.texcoords.distance_squared(texcoords) > 1e-10 {
+                        let vertex = MeshVertex {
+                            texcoords,
+                            ..*vertex
+                        };
+                        vertices.push(vertex);
+                        *index = vertices.len() as u32 - 1;
+                    }
+                }
+                sides.push(side.into_iter().zip(params).collect::<Vec<_>>());
+            }
+
+            let nu = sides[0].len().max(sides[2].len()).max(3) - 1;
+            let nv = sides[1].len().max(sides[3].len()).max(3) - 1;
+            let base = vertices.len() as u32;
+            for j in 1..nv {
+                for i in 1..nu {
+                    let u = i as f32 / nu as f32;
+                    let v = j as f32 / nv as f32;
+                    vertices.push(patch_vertex(patch, u, v));
+                }
+            }
+            let inner = |i: usize, j: usize| {
+                let index = base + ((j - 1) * (nu - 1) + i - 1) as u32;
+                (index, (i as f32 / nu as f32, j as f32 / nv as f32))
+            };
+            for j in 1..nv - 1 {
+                for i in 1..nu - 1 {
+                    let (i0, _) = inner(i, j);
+                    let (i1, _) = inner(i + 1, j);
+                    let (i2, _) = inner(i, j + 1);
+                    let (i3, _) = inner(i + 1, j + 1);
+                    indices.extend_from_slice(&[i0, i1, i3, i0, i3, i2]);
+                }
+            }
+
+            // sides of the inner grid, in the same order as `sides`
+            let inner_sides = [
+                (1..nu).map(|i| inner(i, 1)).collect::<Vec<_>>(),
+                (1..nv).map(|j| inner(nu - 1, j)).collect(),
+                (1..nu).rev().map(|i| inner(i, nv - 1)).collect(),
+                (1..nv).rev().map(|j| inner(1, j)).collect(),
+            ];
+            for (e, (outer, inner)) in sides.iter().zip(&inner_sides).enumerate() {
+                stitch(outer, inner, e, &mut indices);
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+fn patch_vertex(patch: &CubicBezier, u: f32, v: f32) -> MeshVertex {
+    let tangent = patch.tangent_at(u, v);
+    let bitangent = patch.bitangent_at(u, v);
+    MeshVertex {
+        position: patch.point_at(u, v),
+        normal: tangent.cross(bitangent).normalize_or_zero(),
+        texcoords: patch.texcoords_at(u, v),
+        tangent,
+        bitangent,
+    }
+}
+
+/// triangulates the strip between a side of the patch and the parallel side of the inner grid,
+/// both go in counter-clockwise order and are merged by their parameter along side `e`
+fn stitch(outer: &[(u32, (f32, f32))], inner: &[(u32, (f32, f32))], e: usize, out: &mut Vec<u32>) {
+    let param = |(u, v): (f32, f32)| match e {
+        0 => u,
+        1 => v,
+        2 => 1.0 - u,
+        _ => 1.0 - v,
+    };
+    let (mut i, mut j) = (0, 0);
+    while i + 1 < outer.len() || j + 1 < inner.len() {
+        let advance_outer = j + 1 == inner.len()
+            || (i + 1 < outer.len() && param(outer[i + 1].1) <= param(inner[j + 1].1));
+        if advance_outer {
+            out.extend_from_slice(&[outer[i].0, outer[i + 1].0, inner[j].0]);
+            i += 1;
+        } else {
+            out.extend_from_slice(&[outer[i].0, inner[j + 1].0, inner[j].0]);
+            j += 1;
+        }
+    }
+}
+
+/// merges points closer than `eps`, so that corners of neighbouring patches,
+/// evaluated from different control points, get the same index
+struct PointWelder {
+    eps: f32,
+    points: Vec<glam::Vec3A>,
+    cells: HashMap<(i64, i64, i64), Vec<u32>>,
+}
+
+impl PointWelder {
+    fn new(eps: f32) -> Self {
+        Self {
+            eps: eps.max(f32::MIN_POSITIVE),
+            points: vec![],
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, p: glam::Vec3A) -> (i64, i64, i64) {
+        let p = p / self.eps;
+        (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64)
+    }
+
+    fn weld(&mut self, p: glam::Vec3A) -> u32 {
+        let (x, y, z) = self.cell(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(indices) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+                        for index in indices {
+                            if self.points[*index as usize].distance(p) <= self.eps {
+                                return *index;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.points.push(p);
+        let index = self.points.len() as u32 - 1;
+        self.cells.entry((x, y, z)).or_default().push(index);
+        index
+    }
+
+    fn point(&self, index: u32) -> glam::Vec3A {
+        self.points[index as usize]
+    }
+}
+
+fn lerp_vertex(v0: &MeshVertex, v1: &MeshVertex, t: f32) -> MeshVertex {
+    MeshVertex {
+        position: v0.position.lerp(v1.position, t),
+        normal: v0.normal.lerp(v1.normal, t).normalize_or_zero(),
+        texcoords: v0.texcoords.lerp(v1.texcoords, t),
+        tangent: v0.tangent.lerp(v1.tangent, t),
+        bitangent: v0.bitangent.lerp(v1.bitangent, t),
+    }
+}
+
+/// `mids[e]` is the midpoint of edge (tri[e], tri[e + 1]) if the edge is split
+fn split_triangle(tri: &[u32], mids: [Option<u32>; 3], out: &mut Vec<u32>) {
+    match mids.iter().filter(|mid| mid.is_some()).count() {
+        0 => out.extend_from_slice(tri),
+        1 => {
+            let e = mids.iter().position(|mid| mid.is_some()).unwrap();
+            let (a, b, c) = (tri[e], tri[(e + 1) % 3], tri[(e + 2) % 3]);
+            let m = mids[e].unwrap();
+            out.extend_from_slice(&[a, m, c, m, b, c]);
+        }
+        2 => {
+            // rotate so that the edge (c, a) is the one not split
+            let e = mids.iter().position(|mid| mid.is_none()).unwrap();
+            let (a, b, c) = (tri[(e + 1) % 3], tri[(e + 2) % 3], tri[e]);
+            let m0 = mids[(e + 1) % 3].unwrap();
+            let m1 = mids[(e + 2) % 3].unwrap();
+            out.extend_from_slice(&[a, m0, m1, m0, b, m1, a, m1, c]);
+        }
+        _ => {
+            let (a, b, c) = (tri[0], tri[1], tri[2]);
+            let (m0, m1, m2) = (mids[0].unwrap(), mids[1].unwrap(), mids[2].unwrap());
+            out.extend_from_slice(&[a, m0, m2, m0, b, m1, m2, m1, c, m0, m1, m2]);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::core::{
+    bbox::Bbox,
+    intersection::Intersection,
+    loader::InputParams,
+    ray::{self, Ray},
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
+};
+
+use super::{triangle::intersect_triangle_watertight, BasicPrimitiveRef, PrimitiveT};
+
+/// enough for grids of up to 2^40 samples on each side
+const MAX_TRAVERSAL_STACK: usize = 128;
+
+/// grid of heights on xz plane centered at origin, each cell is split into 2 triangles,
+/// rays traverse a quadtree of min-max heights down to cells instead of a bvh of triangles
+pub struct Heightfield {
+    heights: Vec<f32>,
+    /// number of samples along x and z
+    res_x: usize,
+    res_z: usize,
+    /// xz of the first sample
+    p_min: glam::Vec2,
+    /// extent on xz
+    size: glam::Vec2,
+    cell_size: glam::Vec2,
+    /// min and max heights of cells, of 2x2 nodes of the previous level and so on,
+    /// the last level has a single node
+    levels: Vec<MinMaxLevel>,
+    bbox: Bbox,
+}
+
+/// `t`, grid coordinates of vertices and barycentric coordinates
+type HeightfieldHit = (f32, [(usize, usize); 3], [f32; 3]);
+
+struct MinMaxLevel {
+    width: usize,
+    depth: usize,
+    ranges: Vec<[f32; 2]>,
+}
+
+impl Heightfield {
+    /// `heights` are `res_x * res_z` samples in rows along x
+    pub fn new(
+        heights: Vec<f32>,
+        res_x: usize,
+        res_z: usize,
+        size: glam::Vec2,
+    ) -> anyhow::Result<Self> {
+        if res_x < 2 || res_z < 2 {
+            anyhow::bail!("heightfield should have at least 2x2 samples");
+        }
+        if size.x <= 0.0 || size.y <= 0.0 {
+            anyhow::bail!("'size' should be positive");
+        }
+        if heights.len() != res_x * res_z || heights.iter().any(|h| !h.is_finite()) {
+            anyhow::bail!("invalid heights");
+        }
+
+        let cell_size = size / glam::Vec2::new((res_x - 1) as f32, (res_z - 1) as f32);
+        let p_min = size * -0.5;
+
+        let mut cells = MinMaxLevel {
+            width: res_x - 1,
+            depth: res_z - 1,
+            ranges: Vec::with_capacity((res_x - 1) * (res_z - 1)),
+        };
+        for z in 0..res_z - 1 {
+            for x in 0..res_x - 1 {
+                let h = [
+                    heights[z * res_x + x],
+                    heights[z * res_x + x + 1],
+                    heights[(z + 1) * res_x + x],
+                    heights[(z + 1) * res_x + x + 1],
+                ];
+                cells.ranges.push([
+                    h.iter().copied().fold(f32::MAX, f32::min),
+                    h.iter().copied().fold(f32::MIN, f32::max),
+                ]);
+            }
+        }
+        let mut levels = vec![cells];
+        while levels.last().unwrap().width > 1 || levels.last().unwrap().depth > 1 {
+            let last = levels.last().unwrap();
+            let width = last.width.div_ceil(2);
+            let depth = last.depth.div_ceil(2);
+            let mut ranges = Vec::with_capacity(width * depth);
+            for z in 0..depth {
+                for x in 0..width {
+                    let mut range = [f32::MAX, f32::MIN];
+                    for cz in 2 * z..(2 * z + 2).min(last.depth) {
+                        for cx in 2 * x..(2 * x + 2).min(last.width) {
+                            let child = last.ranges[cz * last.width + cx];
+                            range[0] = range[0].min(child[0]);
+                            range[1] = range[1].max(child[1]);
+                        }
+                    }
+                    ranges.push(range);
+                }
+            }
+            levels.push(MinMaxLevel {
+                width,
+                depth,
+                ranges,
+            });
+        }
+
+        let [y_min, y_max] = levels.last().unwrap().ranges[0];
+        let bbox = Bbox::new(
+            glam::Vec3A::new(p_min.x, y_min, p_min.y),
+            glam::Vec3A::new(-p_min.x, y_max, -p_min.y),
+        );
+
+        Ok(Self {
+            heights,
+            res_x,
+            res_z,
+            p_min,
+            size,
+            cell_size,
+            levels,
+            bbox,
+        })
+    }
+
+    pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let height_scale = params.get_float_or("height_scale", 1.0);
+        let size = params.get_float2_or("size", [1.0, 1.0]).into();
+
+        let (heights, res_x, res_z) = if params.contains_key("exr_file") {
+            let image = params.get_exr_image("exr_file")?;
+            let res_x = image.first().map_or(0, |row| row.len());
+            let res_z = image.len();
+            let heights = image
+                .iter()
+                .flat_map(|row| row.iter().map(|color| color.r * height_scale))
+                .collect();
+            (heights, res_x, res_z)
+        } else {
+            // 8-bit images are widened so that all formats are read the same way
+            let image = params.get_image("image_file")?.to_luma16();
+            let heights = image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * height_scale)
+                .collect();
+            (heights, image.width() as usize, image.height() as usize)
+        };
+
+        Self::new(heights, res_x, res_z, size)
+            .map_err(|err| anyhow::anyhow!(format!("{} - {}", params.name(), err)))
+    }
+
+    fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.res_x + x]
+    }
+
+    fn position(&self, x: usize, z: usize) -> glam::Vec3A {
+        glam::Vec3A::new(
+            self.p_min.x + x as f32 * self.cell_size.x,
+            self.height(x, z),
+            self.p_min.y + z as f32 * self.cell_size.y,
+        )
+    }
+
+    /// smooth normal by central differences, one-sided on borders
+    fn normal(&self, x: usize, z: usize) -> glam::Vec3A {
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.res_x - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.res_z - 1));
+        let dhdx =
+            (self.height(x1, z) - self.height(x0, z)) / ((x1 - x0) as f32 * self.cell_size.x);
+        let dhdz =
+            (self.height(x, z1) - self.height(x, z0)) / ((z1 - z0) as f32 * self.cell_size.y);
+        glam::Vec3A::new(-dhdx, 1.0, -dhdz).normalize()
+    }
+
+    /// grid coordinates of vertices of the 2 triangles of a cell, which face +y
+    fn cell_triangles(x: usize, z: usize) -> [[(usize, usize); 3]; 2] {
+        [
+            [(x, z), (x, z + 1), (x + 1, z)],
+            [(x + 1, z), (x, z + 1), (x + 1, z + 1)],
+        ]
+    }
+
+    /// triangle containing point `g` in grid coordinates and barycentric coordinates of it
+    fn triangle_at(&self, g: glam::Vec2) -> ([(usize, usize); 3], [f32; 3]) {
+        let x = (g.x.max(0.0) as usize).min(self.res_x - 2);
+        let z = (g.y.max(0.0) as usize).min(self.res_z - 2);
+        let fx = (g.x - x as f32).clamp(0.0, 1.0);
+        let fz = (g.y - z as f32).clamp(0.0, 1.0);
+        let [lower, upper] = Self::cell_triangles(x, z);
+        if fx + fz <= 1.0 {
+            (lower, [1.0 - fx - fz, fz, fx])
+        } else {
+            (upper, [1.0 - fz, 1.0 - fx, fx + fz - 1.0])
+        }
+    }
+
+    /// entry `t` of the ray into a node of `level`, `None` if it misses or starts after `t_max`
+    fn node_entry(
+        &self,
+        (level, x, z): (usize, usize, usize),
+        ray: &Ray,
+        dir_inv: glam::Vec3A,
+        t_max: f32,
+    ) -> Option<f32> {
+        let x0 = x << level;
+        let z0 = z << level;
+        let x1 = ((x + 1) << level).min(self.res_x - 1);
+        let z1 = ((z + 1) << level).min(self.res_z - 1);
+        let [y_min, y_max] = self.levels[level].ranges[z * self.levels[level].width + x];
+        let p_min = glam::Vec3A::new(
+            self.p_min.x + x0 as f32 * self.cell_size.x,
+            y_min,
+            self.p_min.y + z0 as f32 * self.cell_size.y,
+        );
+        let p_max = glam::Vec3A::new(
+            self.p_min.x + x1 as f32 * self.cell_size.x,
+            y_max,
+            self.p_min.y + z1 as f32 * self.cell_size.y,
+        );
+
+        let ta = (p_min - ray.origin) * dir_inv;
+        let tb = (p_max - ray.origin) * dir_inv;
+        // rays parallel to an axis and lying on a grid line should enter both sides,
+        // where the products above are NaN
+        let parallel = ray.direction.cmpeq(glam::Vec3A::ZERO);
+        let inside = ray.origin.cmpge(p_min) & ray.origin.cmple(p_max);
+        let unbounded = glam::Vec3A::splat(f32::INFINITY);
+        let near = glam::Vec3A::select(
+            parallel,
+            glam::Vec3A::select(inside, -unbounded, unbounded),
+            ta.min(tb),
+        );
+        let far = glam::Vec3A::select(
+            parallel,
+            glam::Vec3A::select(inside, unbounded, -unbounded),
+            ta.max(tb),
+        );
+        let t0 = near.max_element().max(ray.t_min);
+        let t1 = (far.min_element() * (1.0 + 2.0 * ray::gamma(3))).min(t_max);
+        if t0 <= t1 {
+            Some(t0)
+        } else {
+            None
+        }
+    }
+
+    /// returns `t`, vertices and barycentric coordinates of the nearest hit before `t_max`,
+    /// or of any hit if `any_hit` is set
+    fn intersect_ray(&self, ray: &Ray, mut t_max: f32, any_hit: bool) -> Option<HeightfieldHit> {
+        let dir_inv = ray.direction.recip();
+        let root = (self.levels.len() - 1, 0, 0);
+        let mut hit = None;
+        let mut stack = [(root, 0.0); MAX_TRAVERSAL_STACK];
+        let mut stack_len = 0;
+        if let Some(t) = self.node_entry(root, ray, dir_inv, t_max) {
+            stack[0] = (root, t);
+            stack_len = 1;
+        }
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let ((level, x, z), t_entry) = stack[stack_len];
+            if t_entry > t_max {
+                continue;
+            }
+
+            if level == 0 {
+                for vertices in Self::cell_triangles(x, z) {
+                    let positions = vertices.map(|(vx, vz)| self.position(vx, vz));
+                    if let Some((t, u, v, w)) = intersect_triangle_watertight(positions, ray) {
+                        if t > ray.t_min && t < t_max {
+                            t_max = t;
+                            hit = Some((t, vertices, [u, v, w]));
+                            if any_hit {
+                                return hit;
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let children_level = &self.levels[level - 1];
+            let mut children = [(root, 0.0); 4];
+            let mut children_len = 0;
+            for cz in 2 * z..(2 * z + 2).min(children_level.depth) {
+                for cx in 2 * x..(2 * x + 2).min(children_level.width) {
+                    let child = (level - 1, cx, cz);
+                    if let Some(t) = self.node_entry(child, ray, dir_inv, t_max) {
+                        children[children_len] = (child, t);
+                        children_len += 1;
+                    }
+                }
+            }
+            // farther ones are pushed first so that nearer ones are visited first
+            children[..children_len].sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            stack[stack_len..stack_len + children_len].copy_from_slice(&children[..children_len]);
+            stack_len += children_len;
+        }
+
+        hit
+    }
+
+    fn intersection_at(&self, vertices: [(usize, usize); 3], bary: [f32; 3]) -> Intersection<'_> {
+        let [p0, p1, p2] = vertices.map(|(x, z)| self.position(x, z));
+        let [n0, n1, n2] = vertices.map(|(x, z)| self.normal(x, z));
+        let [g0, g1, g2] = vertices.map(|(x, z)| glam::Vec2::new(x as f32, z as f32));
+        let [u, v, w] = bary;
+
+        let normal = (n0 * u + n1 * v + n2 * w).normalize();
+        let grid_uv = g0 * u + g1 * v + g2 * w;
+        // derivatives of position by texcoords on the smooth surface
+        let tangent = glam::Vec3A::new(1.0, -normal.x / normal.y, 0.0) * self.size.x;
+        let bitangent = glam::Vec3A::new(0.0, -normal.z / normal.y, 1.0) * self.size.y;
+
+        Intersection {
+            position: p0 * u + p1 * v + p2 * w,
+            p_error: ((p0 * u).abs() + (p1 * v).abs() + (p2 * w).abs()) * ray::gamma(7),
+            geo_normal: (p1 - p0).cross(p2 - p0).normalize(),
+            normal,
+            tangent,
+            bitangent,
+            texcoords: grid_uv / glam::Vec2::new((self.res_x - 1) as f32, (self.res_z - 1) as f32),
+            prim_uv: grid_uv,
+            primitive: Some(BasicPrimitiveRef::Heightfield(self)),
+            ..Default::default()
+        }
+    }
+}
+
+impl PrimitiveT for Heightfield {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.intersect_ray(ray, t_max, true).is_some()
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        if let Some((t, vertices, bary)) = self.intersect_ray(ray, inter.t, false) {
+            *inter = Intersection {
+                t,
+                ..self.intersection_at(vertices, bary)
+            };
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.bbox
+    }
+
+    /// uniform on the projection to xz plane, then pdf relative to area is the cosine
+    /// between the triangle and the plane divided by the projected area
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let (rand_x, rand_z) = rng.uniform_2d();
+        let g = glam::Vec2::new(
+            rand_x * (self.res_x - 1) as f32,
+            rand_z * (self.res_z - 1) as f32,
+        );
+        let (vertices, bary) = self.triangle_at(g);
+        let inter = self.intersection_at(vertices, bary);
+        let pdf = inter.geo_normal.y / (self.size.x * self.size.y);
+        (inter, pdf)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        let (vertices, _) = self.triangle_at(inter.prim_uv);
+        let [p0, p1, p2] = vertices.map(|(x, z)| self.position(x, z));
+        let cos = (p1 - p0).cross(p2 - p0).normalize().y;
+        cos / (self.size.x * self.size.y)
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        let mut area = 0.0;
+        for z in 0..self.res_z - 1 {
+            for x in 0..self.res_x - 1 {
+                for vertices in Self::cell_triangles(x, z) {
+                    let [p0, p1, p2] =
+                        vertices.map(|(vx, vz)| trans.transform_point3a(self.position(vx, vz)));
+                    area += (p1 - p0).cross(p2 - p0).length() * 0.5;
+                }
+            }
+        }
+        area
+    }
+}
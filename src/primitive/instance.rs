@@ -5,7 +5,10 @@ use crate::core::{
     scene_resources::SceneResources, surface::Surface, transform::Transform,
 };
 
-use super::{Primitive, PrimitiveT};
+use super::{instancing, Primitive, PrimitiveT};
+
+/// generated instances are named `<name>#<index>`, so user names can't contain it
+const GENERATED_NAME_SEPARATOR: char = '#';
 
 pub struct Instance {
     primitive: Arc<Primitive>,
@@ -32,10 +35,25 @@ impl Instance {
         &self.surface
     }
 
+    pub fn primitive(&self) -> &Arc<Primitive> {
+        &self.primitive
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.trans
+    }
+
     pub fn load(rsc: &mut SceneResources, params: &mut InputParams) -> anyhow::Result<()> {
         params.set_name("instance".into());
         let name = params.get_str("name")?;
         params.set_name(format!("instance-{}", name).into());
+        if name.contains(GENERATED_NAME_SEPARATOR) {
+            anyhow::bail!(format!(
+                "{} - '{}' is reserved for names of generated instances",
+                params.name(),
+                GENERATED_NAME_SEPARATOR
+            ));
+        }
 
         let mut trans = glam::Affine3A::IDENTITY;
         if params.contains_key("matrix") {
@@ -45,11 +63,7 @@ impl Instance {
             trans = glam::Affine3A::from_scale(params.get_float3("scale")?.into()) * trans;
         }
         if params.contains_key("rotate") {
-            let rotate = params.get_float3("rotate")?;
-            trans = glam::Affine3A::from_rotation_z(rotate[2] * std::f32::consts::PI / 180.0)
-                * glam::Affine3A::from_rotation_x(rotate[0] * std::f32::consts::PI / 180.0)
-                * glam::Affine3A::from_rotation_y(rotate[1] * std::f32::consts::PI / 180.0)
-                * trans;
+            trans = euler_rotation(params.get_float3("rotate")?) * trans;
         }
         if params.contains_key("translate") {
             trans =
@@ -67,6 +81,7 @@ impl Instance {
                 material,
                 None,
                 None,
+                None,
                 Color::BLACK,
                 None,
                 false,
@@ -74,10 +89,31 @@ impl Instance {
             ))
         };
 
-        let primitive = rsc.clone_primitive(params.get_str("primitive")?)?;
+        let mut primitive = rsc.clone_primitive(params.get_str("primitive")?)?;
+        // generated instances share the primitive displaced with the transform of the instance
+        if let Some(displacement) = surface.displacement() {
+            if let Some(displaced) = displacement.displace(&primitive, trans) {
+                primitive = Arc::new(displaced);
+            } else {
+                log::warn!(
+                    "{}: displacement is only supported on trimesh and catmull_clark",
+                    params.name()
+                );
+            }
+        }
 
-        let res = Self::new(primitive, trans, surface);
-        rsc.add_instance(name, res)?;
+        if let Some(placements) = instancing::load_placements(rsc, params)? {
+            if placements.is_empty() {
+                log::warn!("{}: generator makes no instance", params.name());
+            }
+            for (i, placement) in placements.into_iter().enumerate() {
+                let res = Self::new(primitive.clone(), placement * trans, surface.clone());
+                rsc.add_instance(format!("{}{}{}", name, GENERATED_NAME_SEPARATOR, i), res)?;
+            }
+        } else {
+            let res = Self::new(primitive, trans, surface);
+            rsc.add_instance(name, res)?;
+        }
 
         params.check_unused_keys();
 
@@ -87,24 +123,40 @@ impl Instance {
 
 impl PrimitiveT for Instance {
     fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        let transformed_ray = ray.transformed_by(self.trans_inv);
-        self.primitive.intersect_test(&transformed_ray, t_max)
+        if self.surface.has_opacity() {
+            let mut inter = Intersection::with_t_max(t_max);
+            self.intersect(ray, &mut inter)
+        } else {
+            let transformed_ray = ray.transformed_by(self.trans_inv);
+            self.primitive.intersect_test(&transformed_ray, t_max)
+        }
     }
 
     fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
-        let transformed_ray = ray.transformed_by(self.trans_inv);
-        if self.primitive.intersect(&transformed_ray, inter) {
-            inter.instance = Some(self);
-
-            inter.surface = Some(self.surface.as_ref());
-            inter.position = ray.point_at(inter.t);
-
-            inter.normal = self.trans.transform_normal3a(inter.normal);
-            inter.tangent = self.trans.transform_vector3a(inter.tangent);
-            inter.bitangent = self.trans.transform_vector3a(inter.bitangent);
-            true
-        } else {
-            false
+        let mut transformed_ray = ray.transformed_by(self.trans_inv);
+        loop {
+            let mut curr = Intersection::with_t_max(inter.t);
+            if !self.primitive.intersect(&transformed_ray, &mut curr) {
+                return false;
+            }
+
+            curr.instance = Some(self);
+
+            curr.surface = Some(self.surface.as_ref());
+            let t_after = curr.t_after(&transformed_ray);
+            curr.transform_position(&self.trans);
+
+            curr.normal = self.trans.transform_normal3a(curr.normal);
+            curr.tangent = self.trans.transform_vector3a(curr.tangent);
+            curr.bitangent = self.trans.transform_vector3a(curr.bitangent);
+
+            if self.surface.is_transparent(ray, &curr) {
+                // skip the cut-out hit and continue from it
+                transformed_ray.t_min = t_after;
+            } else {
+                *inter = curr;
+                return true;
+            }
         }
     }
 
@@ -118,7 +170,7 @@ impl PrimitiveT for Instance {
 
         let original_area = inter.tangent.cross(inter.bitangent).length();
 
-        inter.position = self.trans.transform_point3a(inter.position);
+        inter.transform_position(&self.trans);
         inter.normal = self.trans.transform_normal3a(inter.normal);
         inter.bitangent = self.trans.transform_vector3a(inter.bitangent);
         inter.tangent = self.trans.transform_vector3a(inter.tangent);
@@ -137,7 +189,21 @@ impl PrimitiveT for Instance {
         let original_area = tangent.cross(bitangent).length();
         let transformed_area = inter.tangent.cross(inter.bitangent).length();
 
-        self.primitive.pdf(inter) * original_area / transformed_area
+        // primitives evaluate pdf in their own space
+        let local_inter = Intersection {
+            position: trans_inv.transform_point3a(inter.position),
+            geo_normal: trans_inv.transform_normal3a(inter.geo_normal),
+            normal: trans_inv.transform_normal3a(inter.normal),
+            tangent,
+            bitangent,
+            texcoords: inter.texcoords,
+            prim_uv: inter.prim_uv,
+            instance: inter.instance,
+            primitive: inter.primitive,
+            surface: inter.surface,
+            ..Default::default()
+        };
+        self.primitive.pdf(&local_inter) * original_area / transformed_area
     }
 
     fn surface_area(&self, trans: Transform) -> f32 {
@@ -162,3 +228,10 @@ impl Eq for InstancePtr {}
 
 unsafe impl Send for InstancePtr {}
 unsafe impl Sync for InstancePtr {}
+
+/// rotation by euler angles in degrees, applied in the order of y, x, z
+pub(super) fn euler_rotation(degrees: [f32; 3]) -> glam::Affine3A {
+    glam::Affine3A::from_rotation_z(degrees[2].to_radians())
+        * glam::Affine3A::from_rotation_x(degrees[0].to_radians())
+        * glam::Affine3A::from_rotation_y(degrees[1].to_radians())
+}
@@ -0,0 +1,207 @@
+use anyhow::Context;
+
+use crate::{
+    core::{
+        alias_table::AliasTable, loader::InputParams, rng::Rng, scene_resources::SceneResources,
+    },
+    texture::{TextureChannel, TextureInput, TextureT},
+};
+
+use super::{instance::euler_rotation, Primitive};
+
+/// placements of the instances made by the `"generator"` of an instance,
+/// each one is applied after the transform of the instance itself,
+/// returns `None` if there is no generator
+pub fn load_placements(
+    rsc: &SceneResources,
+    params: &mut InputParams,
+) -> anyhow::Result<Option<Vec<glam::Affine3A>>> {
+    if !params.contains_key("generator") {
+        return Ok(None);
+    }
+
+    let ty = params.get_str("generator")?;
+    let placements = match ty.as_str() {
+        "array" => array_placements(params)?,
+        "scatter" => scatter_placements(rsc, params)?,
+        "points" => point_placements(params)?,
+        _ => anyhow::bail!(format!(
+            "{} - unknown generator type '{}'",
+            params.name(),
+            ty
+        )),
+    };
+    Ok(Some(placements))
+}
+
+/// `array_count` copies along each axis, `array_offset` apart
+fn array_placements(params: &mut InputParams) -> anyhow::Result<Vec<glam::Affine3A>> {
+    let count = params.get_int3("array_count")?;
+    let offset: glam::Vec3 = params.get_float3("array_offset")?.into();
+    if count.iter().any(|c| *c < 1) {
+        anyhow::bail!(format!(
+            "{} - 'array_count' should be positive",
+            params.name()
+        ));
+    }
+
+    let mut placements = Vec::with_capacity((count[0] * count[1] * count[2]) as usize);
+    for k in 0..count[2] {
+        for j in 0..count[1] {
+            for i in 0..count[0] {
+                let index = glam::Vec3::new(i as f32, j as f32, k as f32);
+                placements.push(glam::Affine3A::from_translation(offset * index));
+            }
+        }
+    }
+    Ok(placements)
+}
+
+/// `scatter_count` points sampled uniformly by area on the mesh of instance `scatter_target`,
+/// each one is kept with the probability given by `scatter_density` texture at the point,
+/// and gets a random rotation up to `scatter_rotation` degrees and scale in `scatter_scale`
+fn scatter_placements(
+    rsc: &SceneResources,
+    params: &mut InputParams,
+) -> anyhow::Result<Vec<glam::Affine3A>> {
+    let target = rsc.clone_instance(params.get_str("scatter_target")?)?;
+    let count = params.get_int("scatter_count")?.max(0);
+    let density = if params.contains_key("scatter_density") {
+        Some(rsc.clone_texture(params.get_str("scatter_density")?)?)
+    } else {
+        None
+    };
+    let seed = params.get_int_or("scatter_seed", 0);
+    let max_rotation = params.get_float3_or("scatter_rotation", [0.0, 360.0, 0.0]);
+    let [scale_min, scale_max] = params.get_float2_or("scatter_scale", [1.0, 1.0]);
+    let align_normal = params.get_bool_or("scatter_align_normal", false);
+
+    let (vertices, indices) = match target.primitive().as_ref() {
+        Primitive::TriMesh(mesh) => mesh.mesh_data(),
+        Primitive::LoopSubdivision(surface) => surface.mesh().mesh_data(),
+        _ => anyhow::bail!(format!(
+            "{} - scatter target should be a trimesh or loop subdivision surface",
+            params.name()
+        )),
+    };
+    let trans = target.transform();
+    let positions = vertices
+        .iter()
+        .map(|vert| trans.transform_point3a(vert.position))
+        .collect::<Vec<_>>();
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|tri| [tri[0] as usize, tri[1] as usize, tri[2] as usize])
+        .collect::<Vec<_>>();
+    let areas = triangles
+        .iter()
+        .map(|[i0, i1, i2]| {
+            let p0 = positions[*i0];
+            (positions[*i1] - p0).cross(positions[*i2] - p0).length()
+        })
+        .collect::<Vec<_>>();
+    let total_area: f32 = areas.iter().sum();
+    if total_area <= 0.0 {
+        anyhow::bail!(format!("{} - scatter target has no area", params.name()));
+    }
+    let table = AliasTable::new(areas.iter().map(|area| area / total_area).collect());
+
+    let mut rng = Rng::with_seed(seed as u64);
+    let mut placements = vec![];
+    for _ in 0..count {
+        let [i0, i1, i2] = triangles[table.sample(rng.uniform_1d()).0];
+        let rand = rng.uniform_2d();
+        let r0_sqrt = rand.0.sqrt();
+        let u = 1.0 - r0_sqrt;
+        let v = r0_sqrt * (1.0 - rand.1);
+        let w = 1.0 - u - v;
+        // all random numbers are drawn before rejection,
+        // so kept instances stay where they are when the density changes
+        let keep_rand = rng.uniform_1d();
+        let rotation_rand = [rng.uniform_1d(), rng.uniform_1d(), rng.uniform_1d()];
+        let scale_rand = rng.uniform_1d();
+
+        let position = positions[i0] * u + positions[i1] * v + positions[i2] * w;
+        let normal = trans.transform_normal3a(
+            vertices[i0].normal * u + vertices[i1].normal * v + vertices[i2].normal * w,
+        );
+        if let Some(density) = &density {
+            let input = TextureInput {
+                position,
+                normal,
+                texcoords: vertices[i0].texcoords * u
+                    + vertices[i1].texcoords * v
+                    + vertices[i2].texcoords * w,
+                ..Default::default()
+            };
+            if keep_rand >= density.float_at(input, TextureChannel::R) {
+                continue;
+            }
+        }
+
+        let rotation = euler_rotation([
+            max_rotation[0] * rotation_rand[0],
+            max_rotation[1] * rotation_rand[1],
+            max_rotation[2] * rotation_rand[2],
+        ]);
+        let scale = scale_min + (scale_max - scale_min) * scale_rand;
+        let align = if align_normal {
+            glam::Affine3A::from_quat(glam::Quat::from_rotation_arc(glam::Vec3::Y, normal.into()))
+        } else {
+            glam::Affine3A::IDENTITY
+        };
+        placements.push(
+            glam::Affine3A::from_translation(position.into())
+                * align
+                * rotation
+                * glam::Affine3A::from_scale(glam::Vec3::splat(scale)),
+        );
+    }
+    Ok(placements)
+}
+
+/// one instance per line of `points_file`, which is "x y z", optionally followed by
+/// a uniform scale and then rotation in degrees, i.e. "x y z [scale [rx ry rz]]",
+/// numbers are separated by spaces or commas and lines starting with '#' are skipped
+fn point_placements(params: &mut InputParams) -> anyhow::Result<Vec<glam::Affine3A>> {
+    let path = params.get_file_path("points_file")?;
+    let content = std::fs::read_to_string(&path).context(format!(
+        "{} - can't read '{}'",
+        params.name(),
+        path.display()
+    ))?;
+
+    let mut placements = vec![];
+    for (line_index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error_info = format!(
+            "{} - line {} of '{}' should be 'x y z [scale [rx ry rz]]'",
+            params.name(),
+            line_index + 1,
+            path.display()
+        );
+        let values = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .context(error_info.clone())?;
+        if values.len() != 3 && values.len() != 4 && values.len() != 7 {
+            anyhow::bail!(error_info);
+        }
+
+        let mut placement =
+            glam::Affine3A::from_translation(glam::Vec3::new(values[0], values[1], values[2]));
+        if values.len() == 7 {
+            placement = placement * euler_rotation([values[4], values[5], values[6]]);
+        }
+        if values.len() >= 4 {
+            placement = placement * glam::Affine3A::from_scale(glam::Vec3::splat(values[3]));
+        }
+        placements.push(placement);
+    }
+    Ok(placements)
+}
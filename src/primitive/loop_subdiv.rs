@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+
+use crate::core::{
+    bbox::Bbox,
+    cache::{Cache, CacheReader, CacheWriter, Cacheable},
+    intersection::Intersection,
+    loader::InputParams,
+    ray::Ray,
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
+};
+
+use super::{MeshVertex, PrimitiveT, TriMesh};
+
+/// Loop subdivision surface of a triangle control mesh,
+/// vertices of the final level are pushed to the limit surface and stored as a `TriMesh`
+pub struct LoopSubdivision {
+    mesh: TriMesh,
+}
+
+/// positions are welded so that uv seams don't break the topology,
+/// texcoords are stored per triangle corner and interpolated linearly
+struct ControlMesh {
+    positions: Vec<glam::Vec3A>,
+    triangles: Vec<[u32; 3]>,
+    texcoords: Vec<[glam::Vec2; 3]>,
+}
+
+impl LoopSubdivision {
+    /// subdivides `level` times, or less if all edges are already shorter than `max_edge_length`
+    pub fn new(
+        vertices: Vec<MeshVertex>,
+        indices: Vec<u32>,
+        level: u32,
+        max_edge_length: Option<f32>,
+    ) -> Self {
+        let mut control_mesh = ControlMesh::new(&vertices, &indices);
+        for _ in 0..level {
+            if let Some(max_edge_length) = max_edge_length {
+                if control_mesh.max_edge_length() <= max_edge_length {
+                    break;
+                }
+            }
+            control_mesh = control_mesh.subdivide();
+        }
+
+        let (mut vertices, indices) = control_mesh.limit_mesh_data();
+        TriMesh::calc_tangents(&mut vertices, &indices);
+
+        Self {
+            mesh: TriMesh::new(vertices, indices),
+        }
+    }
+
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let obj_file = params.get_file_path("obj_file")?;
+
+        let level = params.get_int_or("subdivision_level", 3) as u32;
+        let max_edge_length = if params.contains_key("max_edge_length") {
+            Some(params.get_float("max_edge_length")?)
+        } else {
+            None
+        };
+
+        let compact = rsc.compact_meshes();
+        let cache = Cache::open(
+            params,
+            "loop",
+            &obj_file,
+            &format!("{} {:?} {}", level, max_edge_length, compact),
+        )?;
+        Cache::load_or_build(cache, || {
+            let (vertices, indices) = TriMesh::load_obj(obj_file)?;
+            let mut surface = Self::new(vertices, indices, level, max_edge_length);
+            if compact {
+                surface.mesh = surface.mesh.into_compact();
+            }
+            Ok(surface)
+        })
+    }
+
+    pub fn mesh(&self) -> &TriMesh {
+        &self.mesh
+    }
+}
+
+impl Cacheable for LoopSubdivision {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        self.mesh.write_cache(writer);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        Ok(Self {
+            mesh: TriMesh::read_cache(reader)?,
+        })
+    }
+}
+
+impl PrimitiveT for LoopSubdivision {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.mesh.intersect_test(ray, t_max)
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        self.mesh.intersect(ray, inter)
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.mesh.bbox()
+    }
+
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        self.mesh.sample(rng)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        self.mesh.pdf(inter)
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        self.mesh.surface_area(trans)
+    }
+}
+
+impl ControlMesh {
+    fn new(vertices: &[MeshVertex], indices: &[u32]) -> Self {
+        let mut welded = HashMap::new();
+        let mut positions = vec![];
+        let remap = vertices
+            .iter()
+            .map(|vertex| {
+                let key = [
+                    vertex.position.x.to_bits(),
+                    vertex.position.y.to_bits(),
+                    vertex.position.z.to_bits(),
+                ];
+                *welded.entry(key).or_insert_with(|| {
+                    positions.push(vertex.position);
+                    positions.len() as u32 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|tri| {
+                [
+                    remap[tri[0] as usize],
+                    remap[tri[1] as usize],
+                    remap[tri[2] as usize],
+                ]
+            })
+            .collect();
+        let texcoords = indices
+            .chunks_exact(3)
+            .map(|tri| {
+                [
+                    vertices[tri[0] as usize].texcoords,
+                    vertices[tri[1] as usize].texcoords,
+                    vertices[tri[2] as usize].texcoords,
+                ]
+            })
+            .collect();
+
+        Self {
+            positions,
+            triangles,
+            texcoords,
+        }
+    }
+
+    fn max_edge_length(&self) -> f32 {
+        self.triangles
+            .iter()
+            .flat_map(|tri| (0..3).map(move |k| (tri[k], tri[(k + 1) % 3])))
+            .map(|(a, b)| (self.positions[a as usize] - self.positions[b as usize]).length())
+            .fold(0.0, f32::max)
+    }
+
+    fn subdivide(&self) -> Self {
+        let num_vertices = self.positions.len();
+
+        // edges in the order they're first met, with their opposite vertices
+        let mut edge_indices = HashMap::new();
+        let mut edges: Vec<((u32, u32), Vec<u32>)> = vec![];
+        for tri in &self.triangles {
+            for k in 0..3 {
+                let key = edge_key(tri[k], tri[(k + 1) % 3]);
+                let index = *edge_indices.entry(key).or_insert_with(|| {
+                    edges.push((key, vec![]));
+                    edges.len() - 1
+                });
+                edges[index].1.push(tri[(k + 2) % 3]);
+            }
+        }
+
+        let mut neighbors = vec![vec![]; num_vertices];
+        let mut boundary_neighbors = vec![vec![]; num_vertices];
+        for ((a, b), opposite) in &edges {
+            neighbors[*a as usize].push(*b);
+            neighbors[*b as usize].push(*a);
+            if opposite.len() != 2 {
+                boundary_neighbors[*a as usize].push(*b);
+                boundary_neighbors[*b as usize].push(*a);
+            }
+        }
+
+        let mut positions = Vec::with_capacity(num_vertices + edges.len());
+        for (v, pos) in self.positions.iter().enumerate() {
+            let ring = &neighbors[v];
+            let boundary_ring = &boundary_neighbors[v];
+            let new_pos = if ring.is_empty() {
+                *pos
+            } else if boundary_ring.is_empty() {
+                let beta = loop_beta(ring.len());
+                let sum = ring.iter().fold(glam::Vec3A::ZERO, |sum, q| {
+                    sum + self.positions[*q as usize]
+                });
+                (1.0 - ring.len() as f32 * beta) * *pos + beta * sum
+            } else if boundary_ring.len() == 2 {
+                let q0 = self.positions[boundary_ring[0] as usize];
+                let q1 = self.positions[boundary_ring[1] as usize];
+                0.75 * *pos + 0.125 * (q0 + q1)
+            } else {
+                // non-manifold vertex, keep it as a corner
+                *pos
+            };
+            positions.push(new_pos);
+        }
+        for ((a, b), opposite) in &edges {
+            let pa = self.positions[*a as usize];
+            let pb = self.positions[*b as usize];
+            let new_pos = if opposite.len() == 2 {
+                let pc = self.positions[opposite[0] as usize];
+                let pd = self.positions[opposite[1] as usize];
+                0.375 * (pa + pb) + 0.125 * (pc + pd)
+            } else {
+                0.5 * (pa + pb)
+            };
+            positions.push(new_pos);
+        }
+
+        let mut triangles = Vec::with_capacity(self.triangles.len() * 4);
+        let mut texcoords = Vec::with_capacity(self.triangles.len() * 4);
+        for (tri, uv) in self.triangles.iter().zip(self.texcoords.iter()) {
+            let [a, b, c] = *tri;
+            let ab = (num_vertices + edge_indices[&edge_key(a, b)]) as u32;
+            let bc = (num_vertices + edge_indices[&edge_key(b, c)]) as u32;
+            let ca = (num_vertices + edge_indices[&edge_key(c, a)]) as u32;
+            triangles.extend_from_slice(&[[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+
+            let [uv_a, uv_b, uv_c] = *uv;
+            let uv_ab = (uv_a + uv_b) * 0.5;
+            let uv_bc = (uv_b + uv_c) * 0.5;
+            let uv_ca = (uv_c + uv_a) * 0.5;
+            texcoords.extend_from_slice(&[
+                [uv_a, uv_ab, uv_ca],
+                [uv_ab, uv_b, uv_bc],
+                [uv_ca, uv_bc, uv_c],
+                [uv_ab, uv_bc, uv_ca],
+            ]);
+        }
+
+        Self {
+            positions,
+            triangles,
+            texcoords,
+        }
+    }
+
+    /// evaluates limit positions and normals, vertices are split at uv seams
+    fn limit_mesh_data(&self) -> (Vec<MeshVertex>, Vec<u32>) {
+        // (next, prev) vertices of each triangle corner around a vertex
+        let mut fans = vec![vec![]; self.positions.len()];
+        for tri in &self.triangles {
+            for k in 0..3 {
+                fans[tri[k] as usize].push((tri[(k + 1) % 3], tri[(k + 2) % 3]));
+            }
+        }
+
+        let mut vertices = vec![];
+        let mut indices = Vec::with_capacity(self.triangles.len() * 3);
+        let mut split_vertices = HashMap::new();
+        for (tri, uv) in self.triangles.iter().zip(self.texcoords.iter()) {
+            for k in 0..3 {
+                let key = (tri[k], uv[k].x.to_bits(), uv[k].y.to_bits());
+                let index = *split_vertices.entry(key).or_insert_with(|| {
+                    vertices.push(MeshVertex {
+                        position: self.positions[tri[k] as usize],
+                        texcoords: uv[k],
+                        ..Default::default()
+                    });
+                    vertices.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+
+        // smooth normals are used as fallback and to orient limit normals
+        TriMesh::calc_normals(&mut vertices, &indices);
+
+        let limits = self
+            .positions
+            .iter()
+            .zip(fans.iter())
+            .map(|(pos, fan)| self.limit_position_and_normal(*pos, fan))
+            .collect::<Vec<_>>();
+        for ((v, _, _), index) in split_vertices {
+            let vertex = &mut vertices[index as usize];
+            if let Some((position, normal)) = limits[v as usize] {
+                vertex.position = position;
+                if normal.dot(vertex.normal) < 0.0 {
+                    vertex.normal = -normal;
+                } else {
+                    vertex.normal = normal;
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    /// returns `None` if the vertex is not manifold
+    fn limit_position_and_normal(
+        &self,
+        pos: glam::Vec3A,
+        fan: &[(u32, u32)],
+    ) -> Option<(glam::Vec3A, glam::Vec3A)> {
+        let (ring, on_boundary) = ordered_ring(fan)?;
+        let ring = ring
+            .into_iter()
+            .map(|v| self.positions[v as usize])
+            .collect::<Vec<_>>();
+        let n = ring.len();
+
+        let (position, s, t) = if !on_boundary {
+            let beta = loop_beta(n);
+            let gamma = 1.0 / (n as f32 + 3.0 / (8.0 * beta));
+            let sum = ring.iter().fold(glam::Vec3A::ZERO, |sum, q| sum + *q);
+            let position = (1.0 - n as f32 * gamma) * pos + gamma * sum;
+
+            let mut s = glam::Vec3A::ZERO;
+            let mut t = glam::Vec3A::ZERO;
+            for (i, q) in ring.iter().enumerate() {
+                let theta = 2.0 * std::f32::consts::PI * i as f32 / n as f32;
+                s += theta.cos() * *q;
+                t += theta.sin() * *q;
+            }
+            (position, s, t)
+        } else {
+            let position = 0.6 * pos + 0.2 * (ring[0] + ring[n - 1]);
+
+            let s = ring[n - 1] - ring[0];
+            let t = match n {
+                2 => ring[0] + ring[1] - 2.0 * pos,
+                3 => ring[1] - pos,
+                4 => -ring[0] + 2.0 * ring[1] + 2.0 * ring[2] - ring[3],
+                _ => {
+                    let theta = std::f32::consts::PI / (n - 1) as f32;
+                    let mut t = theta.sin() * (ring[0] + ring[n - 1]);
+                    for (i, q) in ring.iter().enumerate().take(n - 1).skip(1) {
+                        t += (2.0 * theta.cos() - 2.0) * (i as f32 * theta).sin() * *q;
+                    }
+                    -t
+                }
+            };
+            (position, s, t)
+        };
+
+        let normal = s.cross(t).normalize_or_zero();
+        if normal == glam::Vec3A::ZERO {
+            None
+        } else {
+            Some((position, normal))
+        }
+    }
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+fn loop_beta(valence: usize) -> f32 {
+    let n = valence as f32;
+    let temp = 0.375 + 0.25 * (2.0 * std::f32::consts::PI / n).cos();
+    (0.625 - temp * temp) / n
+}
+
+/// sorts the one-ring of a vertex, boundary rings start and end with the boundary neighbors,
+/// returns `None` if the fan is not a single disk or half disk
+fn ordered_ring(fan: &[(u32, u32)]) -> Option<(Vec<u32>, bool)> {
+    if fan.is_empty() {
+        return None;
+    }
+
+    let boundary_start = fan
+        .iter()
+        .position(|(next, _)| !fan.iter().any(|(_, prev)| prev == next));
+    let on_boundary = boundary_start.is_some();
+    let start = boundary_start.unwrap_or(0);
+
+    let mut ring = vec![fan[start].0];
+    let mut corner = start;
+    loop {
+        let prev = fan[corner].1;
+        match fan.iter().position(|(next, _)| *next == prev) {
+            Some(i) if i == start => break,
+            Some(i) => {
+                ring.push(prev);
+                corner = i;
+            }
+            None => {
+                ring.push(prev);
+                break;
+            }
+        }
+        if ring.len() > fan.len() + 1 {
+            return None;
+        }
+    }
+
+    if ring.len() == fan.len() + on_boundary as usize {
+        Some((ring, on_boundary))
+    } else {
+        None
+    }
+}
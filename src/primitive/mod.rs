@@ -1,20 +1,36 @@
 mod bezier;
 mod bvh;
 mod catmull;
+mod compact_mesh;
+mod displacement;
 mod group;
+mod heightfield;
 mod instance;
+mod instancing;
+mod loop_subdiv;
+mod sbvh;
+mod sdf;
 mod sphere;
 mod triangle;
+mod wide_bvh;
 
 pub use bvh::*;
 pub use group::*;
 pub use instance::*;
+pub use wide_bvh::*;
 
 pub use bezier::*;
 pub use catmull::*;
+pub use compact_mesh::*;
+pub use displacement::*;
+pub use heightfield::*;
+pub use loop_subdiv::*;
+pub use sdf::*;
 pub use sphere::*;
 pub use triangle::*;
 
+use std::sync::Arc;
+
 use crate::core::{
     bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
     scene_resources::SceneResources, transform::Transform,
@@ -37,17 +53,47 @@ pub trait PrimitiveT: Send + Sync {
     fn surface_area(&self, trans: Transform) -> f32;
 }
 
+impl<P: PrimitiveT> PrimitiveT for Arc<P> {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.as_ref().intersect_test(ray, t_max)
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        self.as_ref().intersect(ray, inter)
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.as_ref().bbox()
+    }
+
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        self.as_ref().sample(rng)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        self.as_ref().pdf(inter)
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        self.as_ref().surface_area(trans)
+    }
+}
+
 #[enum_dispatch::enum_dispatch]
 pub enum Primitive {
-    BvhAccelPrimitive(BvhAccel<Primitive>),
-    BvhAccelInstance(BvhAccel<Instance>),
-    BvhAccelTriangle(BvhAccel<Triangle>),
+    BvhAccelPrimitive(BvhAccel<Arc<Primitive>>),
+    BvhAccelInstance(BvhAccel<Arc<Instance>>),
     BvhAccelCubicBezier(BvhAccel<CubicBezier>),
+    Bvh4AccelInstance(Bvh4Accel<Arc<Instance>>),
+    Bvh8AccelInstance(Bvh8Accel<Arc<Instance>>),
     CatmullClark,
     CubicBezier,
     GroupPrimitive(Group<Primitive>),
     Group(Group<Instance>),
+    Heightfield,
     Instance,
+    LoopSubdivision,
+    Sdf,
     Sphere,
     TriMesh,
 }
@@ -66,6 +112,9 @@ pub fn create_primitive_from_params(
         "trimesh" => TriMesh::load(rsc, params)?.into(),
         "cubic_bezier" => CubicBezier::load(rsc, params)?.into(),
         "catmull_clark" => CatmullClark::load(rsc, params)?.into(),
+        "loop" => LoopSubdivision::load(rsc, params)?.into(),
+        "heightfield" => Heightfield::load(rsc, params)?.into(),
+        "sdf" => Sdf::load(rsc, params)?.into(),
         _ => anyhow::bail!(format!("{}: unknown type '{}'", params.name(), ty)),
     };
 
@@ -79,14 +128,18 @@ pub fn create_primitive_from_params(
 #[derive(Clone, Copy)]
 pub enum BasicPrimitiveRef<'a> {
     CubicBezier(&'a CubicBezier),
+    Heightfield(&'a Heightfield),
+    Sdf(&'a Sdf),
     Sphere(&'a Sphere),
-    Triangle(&'a Triangle),
+    Triangle(Triangle<'a>),
 }
 
 impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.intersect_test(ray, t_max),
+            BasicPrimitiveRef::Heightfield(ele) => ele.intersect_test(ray, t_max),
+            BasicPrimitiveRef::Sdf(ele) => ele.intersect_test(ray, t_max),
             BasicPrimitiveRef::Sphere(ele) => ele.intersect_test(ray, t_max),
             BasicPrimitiveRef::Triangle(ele) => ele.intersect_test(ray, t_max),
         }
@@ -95,6 +148,8 @@ impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn intersect<'b>(&'b self, ray: &Ray, inter: &mut Intersection<'b>) -> bool {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.intersect(ray, inter),
+            BasicPrimitiveRef::Heightfield(ele) => ele.intersect(ray, inter),
+            BasicPrimitiveRef::Sdf(ele) => ele.intersect(ray, inter),
             BasicPrimitiveRef::Sphere(ele) => ele.intersect(ray, inter),
             BasicPrimitiveRef::Triangle(ele) => ele.intersect(ray, inter),
         }
@@ -103,6 +158,8 @@ impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn bbox(&self) -> Bbox {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.bbox(),
+            BasicPrimitiveRef::Heightfield(ele) => ele.bbox(),
+            BasicPrimitiveRef::Sdf(ele) => ele.bbox(),
             BasicPrimitiveRef::Sphere(ele) => ele.bbox(),
             BasicPrimitiveRef::Triangle(ele) => ele.bbox(),
         }
@@ -111,6 +168,8 @@ impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn sample<'b>(&'b self, rng: &mut Rng) -> (Intersection<'b>, f32) {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.sample(rng),
+            BasicPrimitiveRef::Heightfield(ele) => ele.sample(rng),
+            BasicPrimitiveRef::Sdf(ele) => ele.sample(rng),
             BasicPrimitiveRef::Sphere(ele) => ele.sample(rng),
             BasicPrimitiveRef::Triangle(ele) => ele.sample(rng),
         }
@@ -119,6 +178,8 @@ impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn pdf(&self, inter: &Intersection<'_>) -> f32 {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.pdf(inter),
+            BasicPrimitiveRef::Heightfield(ele) => ele.pdf(inter),
+            BasicPrimitiveRef::Sdf(ele) => ele.pdf(inter),
             BasicPrimitiveRef::Sphere(ele) => ele.pdf(inter),
             BasicPrimitiveRef::Triangle(ele) => ele.pdf(inter),
         }
@@ -127,6 +188,8 @@ impl<'a> PrimitiveT for BasicPrimitiveRef<'a> {
     fn surface_area(&self, trans: Transform) -> f32 {
         match self {
             BasicPrimitiveRef::CubicBezier(ele) => ele.surface_area(trans),
+            BasicPrimitiveRef::Heightfield(ele) => ele.surface_area(trans),
+            BasicPrimitiveRef::Sdf(ele) => ele.surface_area(trans),
             BasicPrimitiveRef::Sphere(ele) => ele.surface_area(trans),
             BasicPrimitiveRef::Triangle(ele) => ele.surface_area(trans),
         }
@@ -0,0 +1,396 @@
+use crate::core::bbox::Bbox;
+
+use super::{Bvh, BvhNode};
+
+/// spatial splits are not tried below this depth to bound the number of references
+const MAX_SPATIAL_SPLIT_DEPTH: usize = 48;
+
+/// a triangle, or the part of it inside a node after spatial splits
+#[derive(Clone, Copy)]
+struct Reference {
+    index: usize,
+    bbox: Bbox,
+}
+
+struct Split {
+    cost: f32,
+    axis: usize,
+    kind: SplitKind,
+    left_bbox: Bbox,
+    right_bbox: Bbox,
+    left_count: usize,
+    right_count: usize,
+}
+
+enum SplitKind {
+    /// references with centroid bucket less than `bucket` go left
+    Object {
+        centroid_min: f32,
+        bucket_scale: f32,
+        bucket: usize,
+    },
+    /// references straddling `position` are clipped into both children
+    Spatial { position: f32 },
+}
+
+struct SbvhBuilder<'a> {
+    triangles: &'a [[glam::Vec3A; 3]],
+    max_leaf_size: usize,
+    bucket_number: usize,
+    /// spatial splits are tried if children of the object split overlap more than this
+    min_overlap: f32,
+    nodes: Vec<BvhNode>,
+    order: Vec<usize>,
+}
+
+impl Bvh {
+    /// builds with spatial splits (Stich et al. 2009), which clip triangles straddling a split
+    /// plane into both children, a triangle may then be referenced by several leaves,
+    /// `alpha` is the overlap of children relative to the root above which spatial splits are tried,
+    /// returns the bvh and the triangle index of each reference that leaves refer to
+    pub fn new_spatial(
+        triangles: &[[glam::Vec3A; 3]],
+        max_leaf_size: usize,
+        bucket_number: usize,
+        alpha: f32,
+    ) -> (Self, Vec<usize>) {
+        let refs = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, tri)| Reference {
+                index,
+                bbox: Bbox::from_points(tri),
+            })
+            .collect::<Vec<_>>();
+        let bbox = refs
+            .iter()
+            .fold(Bbox::empty(), |bbox, r| bbox.merge(r.bbox));
+
+        let mut builder = SbvhBuilder {
+            triangles,
+            max_leaf_size: max_leaf_size.max(1),
+            bucket_number: bucket_number.max(2),
+            min_overlap: alpha * bbox.surface_area(),
+            nodes: vec![],
+            order: Vec::with_capacity(triangles.len()),
+        };
+        if !refs.is_empty() {
+            builder.build(refs, bbox, 0);
+        }
+        let bvh = Self::from_nodes(builder.nodes);
+        bvh.log_stats("sbvh", triangles.len());
+
+        (bvh, builder.order)
+    }
+}
+
+impl<'a> SbvhBuilder<'a> {
+    fn build(&mut self, refs: Vec<Reference>, bbox: Bbox, depth: usize) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            p_min: bbox.p_min.into(),
+            p_max: bbox.p_max.into(),
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+        if refs.len() <= self.max_leaf_size {
+            self.make_leaf(index, refs);
+            return index;
+        }
+
+        let object_split = self.find_object_split(&refs);
+        let try_spatial = depth < MAX_SPATIAL_SPLIT_DEPTH
+            && match &object_split {
+                Some(split) => {
+                    intersect_bbox(split.left_bbox, split.right_bbox).surface_area()
+                        > self.min_overlap
+                }
+                None => true,
+            };
+        let spatial_split = if try_spatial {
+            self.find_spatial_split(&refs, bbox)
+        } else {
+            None
+        };
+        let split = match (object_split, spatial_split) {
+            (Some(object), Some(spatial)) if spatial.cost < object.cost => Some(spatial),
+            (Some(object), _) => Some(object),
+            (None, spatial) => spatial,
+        };
+
+        let (axis, left, right) = match split {
+            Some(split) => {
+                let axis = split.axis;
+                let (left, right) = self.perform_split(refs, split);
+                (axis, left, right)
+            }
+            None => (0, refs, vec![]),
+        };
+        let (left, right) = if left.is_empty() || right.is_empty() {
+            let mut refs = left;
+            refs.extend(right);
+            // leaf size is limited by the primitive count of flattened node
+            if refs.len() <= u16::MAX as usize {
+                self.make_leaf(index, refs);
+                return index;
+            }
+            let right = refs.split_off(refs.len() / 2);
+            (refs, right)
+        } else {
+            (left, right)
+        };
+
+        let left_bbox = refs_bbox(&left);
+        let right_bbox = refs_bbox(&right);
+        self.build(left, left_bbox, depth + 1);
+        let rc_index = self.build(right, right_bbox, depth + 1);
+        self.nodes[index].offset = rc_index as u32;
+        self.nodes[index].axis = axis as u8;
+        index
+    }
+
+    fn make_leaf(&mut self, index: usize, refs: Vec<Reference>) {
+        self.nodes[index].offset = self.order.len() as u32;
+        self.nodes[index].count = refs.len() as u16;
+        self.order.extend(refs.into_iter().map(|r| r.index));
+    }
+
+    /// binned sah over reference centroids
+    fn find_object_split(&self, refs: &[Reference]) -> Option<Split> {
+        let centroid_bbox = refs.iter().fold(Bbox::empty(), |bbox, r| {
+            let c = r.bbox.centroid();
+            bbox.merge(Bbox::new(c, c))
+        });
+
+        let mut best: Option<Split> = None;
+        for axis in 0..3 {
+            let centroid_min = centroid_bbox.p_min[axis];
+            let extent = centroid_bbox.p_max[axis] - centroid_min;
+            if extent <= 0.0 {
+                continue;
+            }
+            let bucket_scale = self.bucket_number as f32 / extent;
+
+            let mut boxes = vec![Bbox::empty(); self.bucket_number];
+            let mut counts = vec![0; self.bucket_number];
+            for r in refs {
+                let bucket = bucket_of(
+                    r.bbox.centroid()[axis],
+                    centroid_min,
+                    bucket_scale,
+                    self.bucket_number,
+                );
+                boxes[bucket] = boxes[bucket].merge(r.bbox);
+                counts[bucket] += 1;
+            }
+
+            let split = self.sweep(&boxes, &counts, &counts, axis, |bucket| SplitKind::Object {
+                centroid_min,
+                bucket_scale,
+                bucket,
+            });
+            best = better_split(best, split);
+        }
+        best
+    }
+
+    /// chops the node into equal bins and clips references into each bin they overlap
+    fn find_spatial_split(&self, refs: &[Reference], bbox: Bbox) -> Option<Split> {
+        let mut best: Option<Split> = None;
+        for axis in 0..3 {
+            let bin_min = bbox.p_min[axis];
+            let extent = bbox.p_max[axis] - bin_min;
+            if extent <= 0.0 {
+                continue;
+            }
+            let bin_width = extent / self.bucket_number as f32;
+            let bin_scale = 1.0 / bin_width;
+
+            let mut boxes = vec![Bbox::empty(); self.bucket_number];
+            let mut entries = vec![0; self.bucket_number];
+            let mut exits = vec![0; self.bucket_number];
+            for r in refs {
+                let first = bucket_of(r.bbox.p_min[axis], bin_min, bin_scale, self.bucket_number);
+                let last = bucket_of(r.bbox.p_max[axis], bin_min, bin_scale, self.bucket_number);
+                for (bin, bin_bbox) in boxes.iter_mut().enumerate().take(last + 1).skip(first) {
+                    let lo = bin_min + bin as f32 * bin_width;
+                    let hi = if bin + 1 == self.bucket_number {
+                        bbox.p_max[axis]
+                    } else {
+                        lo + bin_width
+                    };
+                    *bin_bbox = bin_bbox.merge(self.clip(r, axis, lo, hi));
+                }
+                entries[first] += 1;
+                exits[last] += 1;
+            }
+
+            let split = self.sweep(&boxes, &entries, &exits, axis, |bin| SplitKind::Spatial {
+                position: bin_min + bin as f32 * bin_width,
+            });
+            best = better_split(best, split);
+        }
+        best
+    }
+
+    /// evaluates sah of splitting between every two adjacent bins,
+    /// `left_counts` and `right_counts` are the references counted to each side of a bin
+    fn sweep(
+        &self,
+        boxes: &[Bbox],
+        left_counts: &[usize],
+        right_counts: &[usize],
+        axis: usize,
+        kind: impl Fn(usize) -> SplitKind,
+    ) -> Option<Split> {
+        let n = boxes.len();
+        let mut right_boxes = boxes.to_vec();
+        let mut right_sums = right_counts.to_vec();
+        for i in (0..n - 1).rev() {
+            right_boxes[i] = right_boxes[i].merge(right_boxes[i + 1]);
+            right_sums[i] += right_sums[i + 1];
+        }
+
+        let mut best: Option<Split> = None;
+        let mut left_bbox = Bbox::empty();
+        let mut left_count = 0;
+        for i in 1..n {
+            left_bbox = left_bbox.merge(boxes[i - 1]);
+            left_count += left_counts[i - 1];
+            let right_count = right_sums[i];
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = left_bbox.surface_area() * left_count as f32
+                + right_boxes[i].surface_area() * right_count as f32;
+            let better = match &best {
+                Some(best) => cost < best.cost,
+                None => true,
+            };
+            if better {
+                best = Some(Split {
+                    cost,
+                    axis,
+                    kind: kind(i),
+                    left_bbox,
+                    right_bbox: right_boxes[i],
+                    left_count,
+                    right_count,
+                });
+            }
+        }
+        best
+    }
+
+    fn perform_split(
+        &self,
+        refs: Vec<Reference>,
+        split: Split,
+    ) -> (Vec<Reference>, Vec<Reference>) {
+        let axis = split.axis;
+        let mut left = vec![];
+        let mut right = vec![];
+        match split.kind {
+            SplitKind::Object {
+                centroid_min,
+                bucket_scale,
+                bucket,
+            } => {
+                for r in refs {
+                    let c = r.bbox.centroid()[axis];
+                    if bucket_of(c, centroid_min, bucket_scale, self.bucket_number) < bucket {
+                        left.push(r);
+                    } else {
+                        right.push(r);
+                    }
+                }
+            }
+            SplitKind::Spatial { position } => {
+                let left_area = split.left_bbox.surface_area();
+                let right_area = split.right_bbox.surface_area();
+                let left_count = split.left_count as f32;
+                let right_count = split.right_count as f32;
+                let split_cost = left_area * left_count + right_area * right_count;
+                for r in refs {
+                    if r.bbox.p_max[axis] <= position {
+                        left.push(r);
+                    } else if r.bbox.p_min[axis] >= position {
+                        right.push(r);
+                    } else {
+                        // reference unsplitting, keep it whole on one side if that's cheaper
+                        let left_cost = split.left_bbox.merge(r.bbox).surface_area() * left_count
+                            + right_area * (right_count - 1.0);
+                        let right_cost = left_area * (left_count - 1.0)
+                            + split.right_bbox.merge(r.bbox).surface_area() * right_count;
+                        if left_cost < split_cost && left_cost <= right_cost {
+                            left.push(r);
+                        } else if right_cost < split_cost {
+                            right.push(r);
+                        } else {
+                            let left_part = self.clip(&r, axis, f32::NEG_INFINITY, position);
+                            if !left_part.is_empty() {
+                                left.push(Reference {
+                                    index: r.index,
+                                    bbox: left_part,
+                                });
+                            }
+                            let right_part = self.clip(&r, axis, position, f32::INFINITY);
+                            if !right_part.is_empty() {
+                                right.push(Reference {
+                                    index: r.index,
+                                    bbox: right_part,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (left, right)
+    }
+
+    /// returns bbox of the part of the referenced triangle between `lo` and `hi` along `axis`
+    fn clip(&self, r: &Reference, axis: usize, lo: f32, hi: f32) -> Bbox {
+        let tri = &self.triangles[r.index];
+        let mut bbox = Bbox::empty();
+        for i in 0..3 {
+            let p0 = tri[i];
+            let p1 = tri[(i + 1) % 3];
+            let (a0, a1) = (p0[axis], p1[axis]);
+            if a0 >= lo && a0 <= hi {
+                bbox = bbox.merge(Bbox::new(p0, p0));
+            }
+            for plane in [lo, hi] {
+                if (a0 < plane && a1 > plane) || (a0 > plane && a1 < plane) {
+                    let p = p0.lerp(p1, (plane - a0) / (a1 - a0));
+                    bbox = bbox.merge(Bbox::new(p, p));
+                }
+            }
+        }
+        bbox.p_min[axis] = bbox.p_min[axis].max(lo);
+        bbox.p_max[axis] = bbox.p_max[axis].min(hi);
+        intersect_bbox(bbox, r.bbox)
+    }
+}
+
+fn bucket_of(value: f32, min: f32, scale: f32, bucket_number: usize) -> usize {
+    (((value - min) * scale).max(0.0) as usize).min(bucket_number - 1)
+}
+
+fn better_split(best: Option<Split>, split: Option<Split>) -> Option<Split> {
+    match (best, split) {
+        (Some(best), Some(split)) if split.cost < best.cost => Some(split),
+        (Some(best), _) => Some(best),
+        (None, split) => split,
+    }
+}
+
+fn intersect_bbox(a: Bbox, b: Bbox) -> Bbox {
+    Bbox::new(a.p_min.max(b.p_min), a.p_max.min(b.p_max))
+}
+
+fn refs_bbox(refs: &[Reference]) -> Bbox {
+    refs.iter()
+        .fold(Bbox::empty(), |bbox, r| bbox.merge(r.bbox))
+}
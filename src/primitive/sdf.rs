@@ -0,0 +1,577 @@
+use crate::core::{
+    bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
+    scene_resources::SceneResources, transform::Transform,
+};
+
+use super::{instance::euler_rotation, BasicPrimitiveRef, PrimitiveT};
+
+const MAX_TRACE_STEPS: u32 = 512;
+/// the surface is approximated by a shell of this thickness (relative to bbox diagonal)
+/// to estimate area and sample points on it
+const SHELL_THICKNESS: f32 = 1.0 / 128.0;
+const AREA_ESTIMATION_SAMPLES: u32 = 65536;
+/// samples to estimate area under a transform that isn't a uniform scale
+const TRANSFORMED_AREA_SAMPLES: u32 = 4096;
+const SAMPLE_MAX_TRIES: u32 = 1024;
+/// points whose curvature radius is below the shell half thickness over this
+/// (like sharp edges and thin features) are never sampled, as the shell folds there
+const MAX_CURVATURE_THICKNESS: f32 = 0.5;
+
+/// node of a signed distance field, distances of combined or transformed nodes are bounds
+/// rather than exact, which is fine for sphere tracing as long as they don't overestimate
+pub enum SdfNode {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_size: glam::Vec3A,
+        rounding: f32,
+    },
+    /// lies in xz plane
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    Capsule {
+        a: glam::Vec3A,
+        b: glam::Vec3A,
+        radius: f32,
+    },
+    Union(Vec<SdfNode>),
+    Intersection(Vec<SdfNode>),
+    /// the first child minus all others
+    Subtraction(Vec<SdfNode>),
+    SmoothUnion {
+        children: Vec<SdfNode>,
+        smoothness: f32,
+    },
+    Transform {
+        child: Box<SdfNode>,
+        trans: glam::Affine3A,
+        trans_inv: glam::Affine3A,
+        /// distances are shrunk by the smallest scale so that they stay conservative
+        min_scale: f32,
+    },
+    /// `count` copies along each axis, `period` apart,
+    /// the child should fit in its cell or distances may be overestimated
+    Repetition {
+        child: Box<SdfNode>,
+        period: glam::Vec3A,
+        count: glam::Vec3A,
+    },
+}
+
+/// implicit surface of an sdf, intersected by sphere tracing
+pub struct Sdf {
+    root: SdfNode,
+    bbox: Bbox,
+    /// a point closer than this to the surface is considered on it
+    epsilon: f32,
+    /// area of the part of the surface that can be sampled, estimated from the shell volume
+    area: f32,
+}
+
+impl SdfNode {
+    pub fn load(params: &mut InputParams) -> anyhow::Result<Self> {
+        let ty = params.get_str("type")?;
+        let node = match ty.as_str() {
+            "sphere" => Self::Sphere {
+                radius: params.get_float("radius")?,
+            },
+            "box" => {
+                let half_size = glam::Vec3A::from(params.get_float3("size")?) * 0.5;
+                let rounding = params.get_float_or("rounding", 0.0);
+                if rounding < 0.0 || rounding > half_size.min_element() {
+                    anyhow::bail!(format!(
+                        "{} - 'rounding' should be in [0, half of the smallest size]",
+                        params.name()
+                    ));
+                }
+                Self::Box {
+                    half_size,
+                    rounding,
+                }
+            }
+            "torus" => Self::Torus {
+                major_radius: params.get_float("major_radius")?,
+                minor_radius: params.get_float("minor_radius")?,
+            },
+            "capsule" => Self::Capsule {
+                a: params.get_float3("a")?.into(),
+                b: params.get_float3("b")?.into(),
+                radius: params.get_float("radius")?,
+            },
+            "union" | "intersection" | "subtraction" | "smooth_union" => {
+                let children = params
+                    .get_params_array("children")?
+                    .iter_mut()
+                    .map(Self::load)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                if children.is_empty() {
+                    anyhow::bail!(format!("{} - 'children' is empty", params.name()));
+                }
+                match ty.as_str() {
+                    "union" => Self::Union(children),
+                    "intersection" => Self::Intersection(children),
+                    "subtraction" => Self::Subtraction(children),
+                    _ => Self::SmoothUnion {
+                        children,
+                        smoothness: params.get_float("smoothness")?.max(0.0),
+                    },
+                }
+            }
+            "transform" => {
+                let child = Self::load(&mut params.get_params("child")?)?;
+                let mut trans = glam::Affine3A::IDENTITY;
+                if params.contains_key("scale") {
+                    trans = glam::Affine3A::from_scale(params.get_float3("scale")?.into()) * trans;
+                }
+                if params.contains_key("rotate") {
+                    trans = euler_rotation(params.get_float3("rotate")?) * trans;
+                }
+                if params.contains_key("translate") {
+                    trans =
+                        glam::Affine3A::from_translation(params.get_float3("translate")?.into())
+                            * trans;
+                }
+                let m = trans.matrix3;
+                let min_scale = m
+                    .x_axis
+                    .length()
+                    .min(m.y_axis.length())
+                    .min(m.z_axis.length());
+                if min_scale == 0.0 {
+                    anyhow::bail!(format!("{} - transform is singular", params.name()));
+                }
+                Self::Transform {
+                    child: Box::new(child),
+                    trans,
+                    trans_inv: trans.inverse(),
+                    min_scale,
+                }
+            }
+            "repetition" => {
+                let child = Self::load(&mut params.get_params("child")?)?;
+                let period: glam::Vec3A = params.get_float3("period")?.into();
+                let count = params.get_int3_or("count", [1, 1, 1]);
+                if count.iter().any(|c| *c < 1) {
+                    anyhow::bail!(format!("{} - 'count' should be positive", params.name()));
+                }
+                Self::Repetition {
+                    child: Box::new(child),
+                    period,
+                    count: glam::Vec3A::new(count[0] as f32, count[1] as f32, count[2] as f32),
+                }
+            }
+            _ => anyhow::bail!(format!("{} - unknown sdf node '{}'", params.name(), ty)),
+        };
+        params.check_unused_keys();
+        Ok(node)
+    }
+
+    pub fn distance(&self, p: glam::Vec3A) -> f32 {
+        match self {
+            SdfNode::Sphere { radius } => p.length() - radius,
+            SdfNode::Box {
+                half_size,
+                rounding,
+            } => {
+                let q = p.abs() - (*half_size - glam::Vec3A::splat(*rounding));
+                q.max(glam::Vec3A::ZERO).length() + q.max_element().min(0.0) - rounding
+            }
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let q = glam::Vec2::new(glam::Vec2::new(p.x, p.z).length() - major_radius, p.y);
+                q.length() - minor_radius
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = if ba.length_squared() > 0.0 {
+                    (pa.dot(ba) / ba.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (pa - ba * h).length() - radius
+            }
+            SdfNode::Union(children) => children
+                .iter()
+                .map(|child| child.distance(p))
+                .fold(f32::INFINITY, f32::min),
+            SdfNode::Intersection(children) => children
+                .iter()
+                .map(|child| child.distance(p))
+                .fold(f32::NEG_INFINITY, f32::max),
+            SdfNode::Subtraction(children) => children[1..]
+                .iter()
+                .map(|child| -child.distance(p))
+                .fold(children[0].distance(p), f32::max),
+            SdfNode::SmoothUnion {
+                children,
+                smoothness,
+            } => children
+                .iter()
+                .map(|child| child.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *smoothness))
+                .unwrap(),
+            SdfNode::Transform {
+                child,
+                trans_inv,
+                min_scale,
+                ..
+            } => child.distance(trans_inv.transform_point3a(p)) * min_scale,
+            SdfNode::Repetition {
+                child,
+                period,
+                count,
+            } => {
+                let cell = glam::Vec3A::select(
+                    count.cmpgt(glam::Vec3A::ONE),
+                    (p / *period)
+                        .round()
+                        .clamp(glam::Vec3A::ZERO, *count - glam::Vec3A::ONE),
+                    glam::Vec3A::ZERO,
+                );
+                child.distance(p - *period * cell)
+            }
+        }
+    }
+
+    pub fn bbox(&self) -> Bbox {
+        match self {
+            SdfNode::Sphere { radius } => {
+                Bbox::new(glam::Vec3A::splat(-radius), glam::Vec3A::splat(*radius))
+            }
+            SdfNode::Box { half_size, .. } => Bbox::new(-*half_size, *half_size),
+            SdfNode::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let r = major_radius + minor_radius;
+                let half_size = glam::Vec3A::new(r, *minor_radius, r);
+                Bbox::new(-half_size, half_size)
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let r = glam::Vec3A::splat(*radius);
+                Bbox::new(a.min(*b) - r, a.max(*b) + r)
+            }
+            SdfNode::Union(children) => children
+                .iter()
+                .fold(Bbox::empty(), |bbox, child| bbox.merge(child.bbox())),
+            SdfNode::Intersection(children) => {
+                let bbox = children
+                    .iter()
+                    .map(SdfNode::bbox)
+                    .reduce(|a, b| Bbox::new(a.p_min.max(b.p_min), a.p_max.min(b.p_max)));
+                match bbox {
+                    Some(bbox) if !bbox.p_min.cmpgt(bbox.p_max).any() => bbox,
+                    _ => Bbox::empty(),
+                }
+            }
+            SdfNode::Subtraction(children) => children[0].bbox(),
+            SdfNode::SmoothUnion {
+                children,
+                smoothness,
+            } => {
+                let bbox = children
+                    .iter()
+                    .fold(Bbox::empty(), |bbox, child| bbox.merge(child.bbox()));
+                // smooth minimum is below the minimum by at most a quarter of smoothness
+                let bulge = glam::Vec3A::splat(smoothness * 0.25);
+                Bbox::new(bbox.p_min - bulge, bbox.p_max + bulge)
+            }
+            SdfNode::Transform { child, trans, .. } => child.bbox().transformed_by(*trans),
+            SdfNode::Repetition {
+                child,
+                period,
+                count,
+            } => {
+                let bbox = child.bbox();
+                let span = *period * (*count - glam::Vec3A::ONE);
+                bbox.merge(Bbox::new(bbox.p_min + span, bbox.p_max + span))
+            }
+        }
+    }
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode, epsilon: Option<f32>) -> anyhow::Result<Self> {
+        let bbox = root.bbox();
+        if bbox.is_empty() || !bbox.p_min.is_finite() || !bbox.p_max.is_finite() {
+            anyhow::bail!("sdf has no finite bounding box");
+        }
+        let diagonal = (bbox.p_max - bbox.p_min).length();
+        let epsilon = epsilon.unwrap_or(diagonal * 1.0e-5);
+        // hit points are up to `epsilon` away from the surface
+        let margin = glam::Vec3A::splat(epsilon * 2.0);
+        let bbox = Bbox::new(bbox.p_min - margin, bbox.p_max + margin);
+
+        let mut sdf = Self {
+            root,
+            bbox,
+            epsilon,
+            area: 0.0,
+        };
+        sdf.area = sdf.estimate_area();
+        Ok(sdf)
+    }
+
+    pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+        let root = SdfNode::load(&mut params.get_params("node")?)?;
+        let epsilon = if params.contains_key("epsilon") {
+            Some(params.get_float("epsilon")?)
+        } else {
+            None
+        };
+        Self::new(root, epsilon)
+            .map_err(|err| anyhow::anyhow!(format!("{} - {}", params.name(), err)))
+    }
+
+    /// sphere tracing in bbox, returns `t` of the first point within `epsilon` to the surface
+    fn trace(&self, ray: &Ray, t_max: f32) -> Option<f32> {
+        let (t0, t1) = self.bbox.intersect_ray(ray)?;
+        let dir_len_inv = 1.0 / ray.direction.length();
+        let t_end = t1.min(t_max);
+        let mut t = t0.max(ray.t_min);
+        for _ in 0..MAX_TRACE_STEPS {
+            if t >= t_end {
+                return None;
+            }
+            // absolute distance so that rays starting inside the solid find the surface too
+            let dist = self.root.distance(ray.point_at(t)).abs();
+            if dist < self.epsilon {
+                return Some(t);
+            }
+            t += dist * dir_len_inv;
+        }
+        None
+    }
+
+    /// normalized gradient by central differences on a tetrahedron
+    fn normal_at(&self, p: glam::Vec3A) -> glam::Vec3A {
+        let h = self.epsilon;
+        let k0 = glam::Vec3A::new(1.0, -1.0, -1.0);
+        let k1 = glam::Vec3A::new(-1.0, -1.0, 1.0);
+        let k2 = glam::Vec3A::new(-1.0, 1.0, -1.0);
+        let k3 = glam::Vec3A::new(1.0, 1.0, 1.0);
+        let grad = k0 * self.root.distance(p + k0 * h)
+            + k1 * self.root.distance(p + k1 * h)
+            + k2 * self.root.distance(p + k2 * h)
+            + k3 * self.root.distance(p + k3 * h);
+        let n = grad.normalize_or_zero();
+        if n == glam::Vec3A::ZERO {
+            glam::Vec3A::Z
+        } else {
+            n
+        }
+    }
+
+    /// moves `p` onto the surface along the gradient
+    fn project(&self, mut p: glam::Vec3A) -> (glam::Vec3A, glam::Vec3A) {
+        let mut n = self.normal_at(p);
+        for _ in 0..2 {
+            p -= n * self.root.distance(p);
+            n = self.normal_at(p);
+        }
+        (p, n)
+    }
+
+    fn intersection_at(&self, p: glam::Vec3A, n: glam::Vec3A) -> Intersection<'_> {
+        let tangent = if n.x.abs() > 0.9 {
+            glam::Vec3A::Y.cross(n)
+        } else {
+            glam::Vec3A::X.cross(n)
+        }
+        .normalize();
+        Intersection {
+            position: p,
+            // spawned rays start farther than `epsilon` from the surface so they don't hit it again
+            p_error: glam::Vec3A::splat(self.epsilon * 4.0),
+            geo_normal: n,
+            normal: n,
+            tangent,
+            bitangent: n.cross(tangent),
+            primitive: Some(BasicPrimitiveRef::Sdf(self)),
+            ..Default::default()
+        }
+    }
+
+    fn shell_half_thickness(&self) -> f32 {
+        (self.bbox.p_max - self.bbox.p_min).length() * SHELL_THICKNESS * 0.5
+    }
+
+    /// bbox grown by the shell half thickness, so that it contains the whole shell
+    fn shell_bbox(&self) -> Bbox {
+        let h = glam::Vec3A::splat(self.shell_half_thickness());
+        Bbox::new(self.bbox.p_min - h, self.bbox.p_max + h)
+    }
+
+    fn uniform_in_shell_bbox(&self, rng: &mut Rng) -> glam::Vec3A {
+        let bbox = self.shell_bbox();
+        let rand = glam::Vec3A::new(rng.uniform_1d(), rng.uniform_1d(), rng.uniform_1d());
+        bbox.p_min + (bbox.p_max - bbox.p_min) * rand
+    }
+
+    /// a point uniform in the bbox is accepted if it's in the shell and projected onto a point
+    /// where the shell doesn't fold, returns the projected point and its normal
+    fn sample_shell(&self, p: glam::Vec3A) -> Option<(glam::Vec3A, glam::Vec3A)> {
+        if self.root.distance(p).abs() >= self.shell_half_thickness() {
+            return None;
+        }
+        let (p, n) = self.project(p);
+        self.projection_jacobian(p).map(|_| (p, n))
+    }
+
+    /// area is the volume of the accepted part of the shell divided by its thickness
+    fn estimate_area(&self) -> f32 {
+        let h = self.shell_half_thickness();
+        let mut rng = Rng::with_seed(0);
+        let accepted = (0..AREA_ESTIMATION_SAMPLES)
+            .filter(|_| {
+                self.sample_shell(self.uniform_in_shell_bbox(&mut rng))
+                    .is_some()
+            })
+            .count();
+        let bbox = self.shell_bbox();
+        let size = bbox.p_max - bbox.p_min;
+        let volume = size.x * size.y * size.z;
+        accepted as f32 / AREA_ESTIMATION_SAMPLES as f32 * volume / (2.0 * h)
+    }
+
+    /// mean and gaussian curvature at surface point `p`, from the hessian of the distance
+    /// by central differences with step of the shell half thickness
+    fn curvatures(&self, p: glam::Vec3A) -> (f32, f32) {
+        let e = self.shell_half_thickness();
+        let axes = [glam::Vec3A::X * e, glam::Vec3A::Y * e, glam::Vec3A::Z * e];
+        let d = |p: glam::Vec3A| self.root.distance(p);
+        let d0 = d(p);
+        let mut hessian = [[0.0; 3]; 3];
+        for i in 0..3 {
+            hessian[i][i] = (d(p + axes[i]) - 2.0 * d0 + d(p - axes[i])) / (e * e);
+            for j in 0..i {
+                let value = (d(p + axes[i] + axes[j])
+                    - d(p + axes[i] - axes[j])
+                    - d(p - axes[i] + axes[j])
+                    + d(p - axes[i] - axes[j]))
+                    / (4.0 * e * e);
+                hessian[i][j] = value;
+                hessian[j][i] = value;
+            }
+        }
+        let hessian = glam::Mat3A::from_cols_array_2d(&hessian);
+
+        // shape operator is the hessian restricted to the tangent plane
+        let n = self.normal_at(p);
+        let proj = glam::Mat3A::IDENTITY - glam::Mat3A::from_cols(n * n.x, n * n.y, n * n.z);
+        let shape = proj * hessian * proj;
+        let trace = shape.x_axis.x + shape.y_axis.y + shape.z_axis.z;
+        let shape_sqr = shape * shape;
+        let trace_sqr = shape_sqr.x_axis.x + shape_sqr.y_axis.y + shape_sqr.z_axis.z;
+        (0.5 * trace, 0.5 * (trace * trace - trace_sqr))
+    }
+
+    /// ratio of the density of projected shell points at surface point `p` to the uniform one,
+    /// points at offset t along the normal take volume (1 - t k1)(1 - t k2) dt dA,
+    /// whose integral over (-h, h) is 2h (1 + h^2 K / 3) as the mean curvature term cancels,
+    /// returns `None` where the shell folds, which holds for exact distances only
+    fn projection_jacobian(&self, p: glam::Vec3A) -> Option<f32> {
+        let h = self.shell_half_thickness();
+        let (mean, gaussian) = self.curvatures(p);
+        let max_curvature = mean.abs() + (mean * mean - gaussian).max(0.0).sqrt();
+        if max_curvature * h > MAX_CURVATURE_THICKNESS {
+            None
+        } else {
+            Some(1.0 + h * h * gaussian / 3.0)
+        }
+    }
+}
+
+impl PrimitiveT for Sdf {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.trace(ray, t_max).is_some()
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        if let Some(t) = self.trace(ray, inter.t) {
+            let p = ray.point_at(t);
+            let n = self.normal_at(p);
+            *inter = Intersection {
+                t,
+                ..self.intersection_at(p - n * self.root.distance(p), n)
+            };
+            true
+        } else {
+            false
+        }
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.bbox
+    }
+
+    /// points uniformly sampled in the shell around the surface are projected onto it,
+    /// the density is given by `projection_jacobian`
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        for _ in 0..SAMPLE_MAX_TRIES {
+            if let Some((p, n)) = self.sample_shell(self.uniform_in_shell_bbox(rng)) {
+                let inter = self.intersection_at(p, n);
+                let pdf = self.pdf(&inter);
+                return (inter, pdf);
+            }
+        }
+        (Intersection::default(), 0.0)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        match self.projection_jacobian(inter.position) {
+            Some(jacobian) => jacobian / self.area.max(0.000001),
+            None => 0.0,
+        }
+    }
+
+    /// area under a transform other than a uniform scale is estimated by sampling the surface
+    fn surface_area(&self, trans: Transform) -> f32 {
+        let x = trans.transform_vector3a(glam::Vec3A::X);
+        let y = trans.transform_vector3a(glam::Vec3A::Y);
+        let z = trans.transform_vector3a(glam::Vec3A::Z);
+        let scale_sqr = x.length_squared();
+        let tolerance = scale_sqr * 1e-4;
+        if (y.length_squared() - scale_sqr).abs() < tolerance
+            && (z.length_squared() - scale_sqr).abs() < tolerance
+            && x.dot(y).abs() < tolerance
+            && y.dot(z).abs() < tolerance
+            && z.dot(x).abs() < tolerance
+        {
+            return self.area * scale_sqr;
+        }
+
+        let mut rng = Rng::with_seed(0);
+        let sum: f32 = (0..TRANSFORMED_AREA_SAMPLES)
+            .map(|_| {
+                let (inter, pdf) = self.sample(&mut rng);
+                if pdf > 0.0 {
+                    let original_area = inter.tangent.cross(inter.bitangent).length();
+                    let transformed_area = trans
+                        .transform_vector3a(inter.tangent)
+                        .cross(trans.transform_vector3a(inter.bitangent))
+                        .length();
+                    transformed_area / original_area / pdf
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+        sum / TRANSFORMED_AREA_SAMPLES as f32
+    }
+}
+
+/// polynomial smooth minimum, which is below `min(a, b)` by at most `k / 4`
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
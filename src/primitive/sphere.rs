@@ -1,6 +1,11 @@
 use crate::core::{
-    bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
-    scene_resources::SceneResources, transform::Transform,
+    bbox::Bbox,
+    intersection::Intersection,
+    loader::InputParams,
+    ray::{self, Ray},
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
 };
 
 use super::{BasicPrimitiveRef, PrimitiveT};
@@ -22,22 +27,47 @@ impl Sphere {
         }
     }
 
-    fn intersect_ray(&self, ray: &Ray) -> Option<(f32, f32)> {
+    /// returns the nearest `t` in (`ray.t_min`, `t_max`), roots are computed as in
+    /// "Precision Improvements for Ray/Sphere Intersection" (Ray Tracing Gems) and
+    /// ones within the error bound of zero are rejected, so a ray leaving the sphere won't hit it
+    fn intersect_ray(&self, ray: &Ray, t_max: f32) -> Option<f32> {
         let oc = ray.origin - self.center;
         let a = ray.direction.length_squared();
         let b = ray.direction.dot(oc);
         let c = oc.length_squared() - self.radius * self.radius;
-        let delta = b * b - a * c;
-        if delta >= 0.0 {
-            let delta = delta.sqrt();
-            let min = (-b - delta) / a;
-            let max = (-b + delta) / a;
-            Some((min, max))
+        // distance from center to the ray line, avoid the cancellation in b * b - a * c
+        let l = oc - ray.direction * (b / a);
+        let delta = a * (self.radius * self.radius - l.length_squared());
+        if delta < 0.0 {
+            return None;
+        }
+        let q = -(b + delta.sqrt().copysign(b));
+        let (min, max) = if q == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let (t0, t1) = (c / q, q / a);
+            (t0.min(t1), t0.max(t1))
+        };
+
+        let t_error = ray::gamma(7) * (oc.length() + self.radius) / a.sqrt();
+        let t_min = ray.t_min.max(t_error);
+        if min > t_min && min < t_max {
+            Some(min)
+        } else if max > t_min && max < t_max {
+            Some(max)
         } else {
             None
         }
     }
 
+    /// position reprojected onto the sphere with its error bound
+    fn position_at(&self, norm: glam::Vec3A) -> (glam::Vec3A, glam::Vec3A) {
+        let offset = norm * self.radius;
+        let p = self.center + offset;
+        let p_error = offset.abs() * ray::gamma(5) + p.abs() * ray::gamma(1);
+        (p, p_error)
+    }
+
     pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let center = params.get_float3_or("center", [0.0, 0.0, 0.0]);
 
@@ -49,36 +79,33 @@ impl Sphere {
 
 impl PrimitiveT for Sphere {
     fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        if let Some((min, max)) = self.intersect_ray(ray) {
-            min < t_max && max > ray.t_min
-        } else {
-            false
-        }
+        self.intersect_ray(ray, t_max).is_some()
     }
 
     fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
-        if let Some((min, max)) = self.intersect_ray(ray) {
-            let t = if min < ray.t_min { max } else { min };
-            if ray.t_min < t && t < inter.t {
-                inter.t = t;
-                let norm = (ray.point_at(t) - self.center) / self.radius;
-                let sin_theta = (1.0 - norm.y * norm.y).sqrt();
-                inter.normal = norm;
-                if sin_theta != 0.0 {
-                    inter.bitangent = norm * (-norm.y / sin_theta);
-                    inter.bitangent.y = sin_theta;
-                    inter.tangent = inter.bitangent.cross(inter.normal);
-                } else if norm.y > 0.0 {
-                    inter.bitangent = glam::Vec3A::X;
-                    inter.tangent = glam::Vec3A::Z;
-                } else {
-                    inter.bitangent = -glam::Vec3A::X;
-                    inter.tangent = -glam::Vec3A::Z;
-                }
-                inter.texcoords = sphere_normal_to_texcoords(norm);
-                inter.primitive = Some(BasicPrimitiveRef::Sphere(self));
-                return true;
+        if let Some(t) = self.intersect_ray(ray, inter.t) {
+            inter.t = t;
+            let norm = (ray.point_at(t) - self.center).normalize();
+            let (position, p_error) = self.position_at(norm);
+            inter.position = position;
+            inter.p_error = p_error;
+            inter.geo_normal = norm;
+            let sin_theta = (1.0 - norm.y * norm.y).sqrt();
+            inter.normal = norm;
+            if sin_theta != 0.0 {
+                inter.bitangent = norm * (-norm.y / sin_theta);
+                inter.bitangent.y = sin_theta;
+                inter.tangent = inter.bitangent.cross(inter.normal);
+            } else if norm.y > 0.0 {
+                inter.bitangent = glam::Vec3A::X;
+                inter.tangent = glam::Vec3A::Z;
+            } else {
+                inter.bitangent = -glam::Vec3A::X;
+                inter.tangent = -glam::Vec3A::Z;
             }
+            inter.texcoords = sphere_normal_to_texcoords(norm);
+            inter.primitive = Some(BasicPrimitiveRef::Sphere(self));
+            return true;
         }
         false
     }
@@ -89,10 +116,12 @@ impl PrimitiveT for Sphere {
 
     fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
         let norm = rng.uniform_on_sphere();
-        let pos = self.center + norm * self.radius;
+        let (pos, p_error) = self.position_at(norm);
 
         let mut inter = Intersection {
             position: pos,
+            p_error,
+            geo_normal: norm,
             normal: norm,
             texcoords: sphere_normal_to_texcoords(norm),
             primitive: Some(BasicPrimitiveRef::Sphere(self)),
@@ -1,11 +1,17 @@
-use std::sync::Arc;
+use std::path::PathBuf;
 
 use crate::core::{
-    bbox::Bbox, intersection::Intersection, loader::InputParams, ray::Ray, rng::Rng,
-    scene_resources::SceneResources, transform::Transform,
+    bbox::Bbox,
+    cache::{Cache, CacheReader, CacheWriter, Cacheable},
+    intersection::Intersection,
+    loader::InputParams,
+    ray::{self, Ray},
+    rng::Rng,
+    scene_resources::SceneResources,
+    transform::Transform,
 };
 
-use super::{BasicPrimitiveRef, BvhAccel, PrimitiveT};
+use super::{BasicPrimitiveRef, Bvh, CompactVertices, PrimitiveT};
 
 #[derive(Copy, Clone)]
 pub struct MeshVertex {
@@ -17,13 +23,28 @@ pub struct MeshVertex {
 }
 
 pub struct TriMesh {
-    triangles: BvhAccel<Triangle>,
+    vertices: MeshVertices,
+    /// vertex indices of each triangle, sorted in bvh leaf order,
+    /// a triangle appears once per leaf referring to it if spatial splits are used
+    triangles: Vec<[u32; 3]>,
+    /// position in `triangles` of each distinct triangle, empty if no triangle appears twice
+    distinct: Vec<u32>,
+    bvh: Bvh,
+    /// use the watertight ray-triangle test, which is slightly slower
+    watertight: bool,
 }
 
-pub struct Triangle {
-    vertices: Arc<Vec<MeshVertex>>,
+enum MeshVertices {
+    Full(Vec<MeshVertex>),
+    Compact(CompactVertices),
+}
+
+/// a triangle in a `TriMesh`
+#[derive(Clone, Copy)]
+pub struct Triangle<'a> {
+    vertices: &'a MeshVertices,
     indices: [usize; 3],
-    bbox: Bbox,
+    watertight: bool,
 }
 
 impl Default for MeshVertex {
@@ -40,23 +61,115 @@ impl Default for MeshVertex {
 
 impl TriMesh {
     pub fn new(vertices: Vec<MeshVertex>, indices: Vec<u32>) -> Self {
-        let vertices = Arc::new(vertices);
-        let triangle_count = indices.len() / 3;
-        let mut triangles = Vec::with_capacity(triangle_count);
-        for i in 0..triangle_count {
-            let i0 = indices[3 * i] as usize;
-            let i1 = indices[3 * i + 1] as usize;
-            let i2 = indices[3 * i + 2] as usize;
-            triangles.push(Arc::new(Triangle::new(vertices.clone(), [i0, i1, i2])));
+        let triangles = Self::triangles_of(&indices);
+        let bboxes = triangles
+            .iter()
+            .map(|tri| {
+                let p0 = vertices[tri[0] as usize].position;
+                let p1 = vertices[tri[1] as usize].position;
+                let p2 = vertices[tri[2] as usize].position;
+                Bbox::from_points(&[p0, p1, p2])
+            })
+            .collect::<Vec<_>>();
+        let (bvh, order) = Bvh::new(&bboxes, 4, 16);
+        Self::with_bvh(vertices, triangles, bvh, order)
+    }
+
+    /// builds the bvh with spatial splits, see `Bvh::new_spatial`
+    pub fn new_spatial(vertices: Vec<MeshVertex>, indices: Vec<u32>, alpha: f32) -> Self {
+        let triangles = Self::triangles_of(&indices);
+        let points = triangles
+            .iter()
+            .map(|tri| tri.map(|i| vertices[i as usize].position))
+            .collect::<Vec<_>>();
+        let (bvh, order) = Bvh::new_spatial(&points, 4, 16, alpha);
+        Self::with_bvh(vertices, triangles, bvh, order)
+    }
+
+    fn triangles_of(indices: &[u32]) -> Vec<[u32; 3]> {
+        indices
+            .chunks_exact(3)
+            .map(|tri| [tri[0], tri[1], tri[2]])
+            .collect()
+    }
+
+    fn with_bvh(
+        vertices: Vec<MeshVertex>,
+        triangles: Vec<[u32; 3]>,
+        bvh: Bvh,
+        order: Vec<usize>,
+    ) -> Self {
+        let mut distinct = vec![];
+        if order.len() != triangles.len() {
+            let mut seen = vec![false; triangles.len()];
+            for (pos, &i) in order.iter().enumerate() {
+                if !seen[i] {
+                    seen[i] = true;
+                    distinct.push(pos as u32);
+                }
+            }
         }
-        let triangles = BvhAccel::new(triangles, 4, 16);
+        let triangles = order.into_iter().map(|i| triangles[i]).collect();
 
-        Self { triangles }
+        Self {
+            vertices: MeshVertices::Full(vertices),
+            triangles,
+            distinct,
+            bvh,
+            watertight: true,
+        }
     }
 
-    pub fn load(_rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
+    /// stores vertices in `CompactVertices`, tangents are derived from triangles after that
+    pub fn into_compact(self) -> Self {
+        let vertices = match self.vertices {
+            MeshVertices::Full(vertices) => MeshVertices::Compact(CompactVertices::new(&vertices)),
+            compact => compact,
+        };
+        Self { vertices, ..self }
+    }
+
+    pub fn is_compact(&self) -> bool {
+        matches!(self.vertices, MeshVertices::Compact(_))
+    }
+
+    pub fn load(rsc: &SceneResources, params: &mut InputParams) -> anyhow::Result<Self> {
         let obj_file = params.get_file_path("obj_file")?;
+        let watertight = params.get_bool_or("watertight", true);
+        let compact = rsc.compact_meshes();
+        let spatial_split_alpha = if params.get_bool_or("spatial_split", false) {
+            Some(params.get_float_or("spatial_split_alpha", 1.0e-5))
+        } else {
+            None
+        };
+
+        let cache = Cache::open(
+            params,
+            "trimesh",
+            &obj_file,
+            &format!("{:?} {}", spatial_split_alpha, compact),
+        )?;
+        let mut mesh = Cache::load_or_build(cache, || {
+            let (mut vertices, indices) = Self::load_obj(obj_file)?;
+            Self::calc_tangents(&mut vertices, &indices);
+
+            let mesh = if let Some(alpha) = spatial_split_alpha {
+                Self::new_spatial(vertices, indices, alpha)
+            } else {
+                Self::new(vertices, indices)
+            };
+            Ok(if compact { mesh.into_compact() } else { mesh })
+        })?;
+        mesh.set_watertight(watertight);
+        Ok(mesh)
+    }
 
+    pub fn set_watertight(&mut self, watertight: bool) {
+        self.watertight = watertight;
+    }
+
+    /// loads and triangulates all models in an obj file into one vertex/index buffer
+    pub fn load_obj(obj_file: PathBuf) -> anyhow::Result<(Vec<MeshVertex>, Vec<u32>)> {
         let mut load_options = tobj::LoadOptions::default();
         load_options.triangulate = true;
         load_options.single_index = true;
@@ -92,39 +205,141 @@ impl TriMesh {
                     );
                 }
             }
-            vertices.append(&mut model_vertices);
-            let mut model_indices = model
+            let model_indices = model
                 .mesh
                 .indices
                 .into_iter()
-                .map(|ind| ind + indices.len() as u32)
+                .map(|ind| ind + vertices.len() as u32)
                 .collect::<Vec<_>>();
-            indices.append(&mut model_indices);
+            vertices.append(&mut model_vertices);
+            indices.extend(model_indices);
+        }
+
+        Ok((vertices, indices))
+    }
+
+    /// returns vertices and indices of the mesh, triangles may be in a different order from input
+    pub fn mesh_data(&self) -> (Vec<MeshVertex>, Vec<u32>) {
+        let indices: Vec<u32> = (0..self.triangle_count())
+            .flat_map(|i| self.triangles[self.distinct_position(i)])
+            .collect();
+        let vertices = match &self.vertices {
+            MeshVertices::Full(vertices) => vertices.clone(),
+            MeshVertices::Compact(vertices) => {
+                let mut vertices = vertices.to_vertices();
+                Self::calc_tangents(&mut vertices, &indices);
+                vertices
+            }
+        };
+        (vertices, indices)
+    }
+
+    fn triangle(&self, index: usize) -> Triangle<'_> {
+        let [i0, i1, i2] = self.triangles[index];
+        Triangle {
+            vertices: &self.vertices,
+            indices: [i0 as usize, i1 as usize, i2 as usize],
+            watertight: self.watertight,
         }
+    }
 
-        Self::calc_tangents(&mut vertices, &indices);
+    fn triangle_count(&self) -> usize {
+        if self.distinct.is_empty() {
+            self.triangles.len()
+        } else {
+            self.distinct.len()
+        }
+    }
 
-        Ok(Self::new(vertices, indices))
+    /// position in `triangles` of the `index`-th distinct triangle
+    fn distinct_position(&self, index: usize) -> usize {
+        if self.distinct.is_empty() {
+            index
+        } else {
+            self.distinct[index] as usize
+        }
     }
 }
 
-impl Triangle {
-    fn new(vertices: Arc<Vec<MeshVertex>>, indices: [usize; 3]) -> Self {
-        let p0 = vertices[indices[0]].position;
-        let p1 = vertices[indices[1]].position;
-        let p2 = vertices[indices[2]].position;
-        let bbox = Bbox::from_points(&[p0, p1, p2]);
-        Self {
-            vertices,
-            indices,
-            bbox,
+impl MeshVertices {
+    fn len(&self) -> usize {
+        match self {
+            MeshVertices::Full(vertices) => vertices.len(),
+            MeshVertices::Compact(vertices) => vertices.len(),
+        }
+    }
+
+    fn position(&self, index: usize) -> glam::Vec3A {
+        match self {
+            MeshVertices::Full(vertices) => vertices[index].position,
+            MeshVertices::Compact(vertices) => vertices.position(index),
+        }
+    }
+
+    fn normal(&self, index: usize) -> glam::Vec3A {
+        match self {
+            MeshVertices::Full(vertices) => vertices[index].normal,
+            MeshVertices::Compact(vertices) => vertices.normal(index),
+        }
+    }
+
+    fn texcoords(&self, index: usize) -> glam::Vec2 {
+        match self {
+            MeshVertices::Full(vertices) => vertices[index].texcoords,
+            MeshVertices::Compact(vertices) => vertices.texcoords(index),
+        }
+    }
+}
+
+impl<'a> Triangle<'a> {
+    fn position(&self, k: usize) -> glam::Vec3A {
+        self.vertices.position(self.indices[k])
+    }
+
+    fn normal(&self, k: usize) -> glam::Vec3A {
+        self.vertices.normal(self.indices[k])
+    }
+
+    fn texcoords(&self, k: usize) -> glam::Vec2 {
+        self.vertices.texcoords(self.indices[k])
+    }
+
+    /// interpolated tangent and bitangent at barycentric coordinates (u, v, w),
+    /// compact vertices don't store them so the ones of the triangle itself are used
+    fn tangents(&self, u: f32, v: f32, w: f32) -> (glam::Vec3A, glam::Vec3A) {
+        match self.vertices {
+            MeshVertices::Full(vertices) => {
+                let [v0, v1, v2] = self.indices.map(|i| &vertices[i]);
+                (
+                    v0.tangent * u + v1.tangent * v + v2.tangent * w,
+                    v0.bitangent * u + v1.bitangent * v + v2.bitangent * w,
+                )
+            }
+            MeshVertices::Compact(_) => face_tangents(
+                [self.position(0), self.position(1), self.position(2)],
+                [self.texcoords(0), self.texcoords(1), self.texcoords(2)],
+            )
+            .unwrap_or((glam::Vec3A::X, glam::Vec3A::Y)),
         }
     }
 
+    /// returns (t, u, v, w), where u, v, w are barycentric coordinates of the 3 vertices
     fn intersect_ray(&self, ray: &Ray) -> Option<(f32, f32, f32, f32)> {
-        let p0 = self.vertices[self.indices[0]].position;
-        let p1 = self.vertices[self.indices[1]].position;
-        let p2 = self.vertices[self.indices[2]].position;
+        if self.watertight {
+            intersect_triangle_watertight(
+                [self.position(0), self.position(1), self.position(2)],
+                ray,
+            )
+        } else {
+            self.intersect_ray_moller(ray)
+        }
+    }
+
+    /// Moller-Trumbore test, rays may slip through shared edges due to rounding
+    fn intersect_ray_moller(&self, ray: &Ray) -> Option<(f32, f32, f32, f32)> {
+        let p0 = self.position(0);
+        let p1 = self.position(1);
+        let p2 = self.position(2);
         let e1 = p1 - p0;
         let e2 = p2 - p0;
         let q = ray.direction.cross(e2);
@@ -145,71 +360,32 @@ impl Triangle {
         }
         None
     }
-}
-
-impl PrimitiveT for TriMesh {
-    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        self.triangles.intersect_test(ray, t_max)
-    }
-
-    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
-        self.triangles.intersect(ray, inter)
-    }
-
-    fn bbox(&self) -> Bbox {
-        self.triangles.bbox()
-    }
-
-    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
-        self.triangles.sample(rng)
-    }
-
-    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
-        self.triangles.pdf(inter)
-    }
-
-    fn surface_area(&self, trans: Transform) -> f32 {
-        self.triangles.surface_area(trans)
-    }
-}
-
-impl PrimitiveT for Triangle {
-    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
-        if let Some((t, _, _, _)) = self.intersect_ray(ray) {
-            t > ray.t_min && t < t_max
-        } else {
-            false
-        }
-    }
 
-    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+    fn intersect_with(self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
         if let Some((t, u, v, w)) = self.intersect_ray(ray) {
             if t > ray.t_min && t < inter.t {
+                let p0 = self.position(0);
+                let p1 = self.position(1);
+                let p2 = self.position(2);
                 inter.t = t;
+                inter.position = p0 * u + p1 * v + p2 * w;
+                inter.p_error = ((p0 * u).abs() + (p1 * v).abs() + (p2 * w).abs()) * ray::gamma(7);
+                inter.geo_normal = (p1 - p0).cross(p2 - p0).normalize();
                 inter.normal = {
-                    let n0 = self.vertices[self.indices[0]].normal;
-                    let n1 = self.vertices[self.indices[1]].normal;
-                    let n2 = self.vertices[self.indices[2]].normal;
+                    let n0 = self.normal(0);
+                    let n1 = self.normal(1);
+                    let n2 = self.normal(2);
                     (n0 * u + n1 * v + n2 * w).normalize()
                 };
                 inter.texcoords = {
-                    let uv0 = self.vertices[self.indices[0]].texcoords;
-                    let uv1 = self.vertices[self.indices[1]].texcoords;
-                    let uv2 = self.vertices[self.indices[2]].texcoords;
+                    let uv0 = self.texcoords(0);
+                    let uv1 = self.texcoords(1);
+                    let uv2 = self.texcoords(2);
                     lerp_point2(uv0, uv1, uv2, u, v, w)
                 };
-                inter.tangent = {
-                    let t0 = self.vertices[self.indices[0]].tangent;
-                    let t1 = self.vertices[self.indices[1]].tangent;
-                    let t2 = self.vertices[self.indices[2]].tangent;
-                    t0 * u + t1 * v + t2 * w
-                };
-                inter.bitangent = {
-                    let b0 = self.vertices[self.indices[0]].bitangent;
-                    let b1 = self.vertices[self.indices[1]].bitangent;
-                    let b2 = self.vertices[self.indices[2]].bitangent;
-                    b0 * u + b1 * v + b2 * w
-                };
+                let (tangent, bitangent) = self.tangents(u, v, w);
+                inter.tangent = tangent;
+                inter.bitangent = bitangent;
                 inter.primitive = Some(BasicPrimitiveRef::Triangle(self));
                 return true;
             }
@@ -217,47 +393,36 @@ impl PrimitiveT for Triangle {
         false
     }
 
-    fn bbox(&self) -> Bbox {
-        self.bbox
-    }
-
-    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+    fn sample_with(self, rng: &mut Rng) -> (Intersection<'a>, f32) {
         let rand = rng.uniform_2d();
         let r0_sqrt = rand.0.sqrt();
         let u = 1.0 - r0_sqrt;
         let v = r0_sqrt * (1.0 - rand.1);
         let w = 1.0 - u - v;
 
-        let p0 = self.vertices[self.indices[0]].position;
-        let p1 = self.vertices[self.indices[1]].position;
-        let p2 = self.vertices[self.indices[2]].position;
-
-        let n0 = self.vertices[self.indices[0]].normal;
-        let n1 = self.vertices[self.indices[1]].normal;
-        let n2 = self.vertices[self.indices[2]].normal;
+        let p0 = self.position(0);
+        let p1 = self.position(1);
+        let p2 = self.position(2);
 
-        let t0 = self.vertices[self.indices[0]].tangent;
-        let t1 = self.vertices[self.indices[1]].tangent;
-        let t2 = self.vertices[self.indices[2]].tangent;
+        let n0 = self.normal(0);
+        let n1 = self.normal(1);
+        let n2 = self.normal(2);
 
-        let b0 = self.vertices[self.indices[0]].bitangent;
-        let b1 = self.vertices[self.indices[1]].bitangent;
-        let b2 = self.vertices[self.indices[2]].bitangent;
-
-        let uv0 = self.vertices[self.indices[0]].texcoords;
-        let uv1 = self.vertices[self.indices[1]].texcoords;
-        let uv2 = self.vertices[self.indices[2]].texcoords;
+        let uv0 = self.texcoords(0);
+        let uv1 = self.texcoords(1);
+        let uv2 = self.texcoords(2);
 
         let p = p0 * u + p1 * v + p2 * w;
         let area = (p1 - p0).cross(p2 - p0).length() * 0.5;
 
         let norm = n0 * u + n1 * v + n2 * w;
-        let tan = t0 * u + t1 * v + t2 * w;
-        let bitan = b0 * u + b1 * v + b2 * w;
+        let (tan, bitan) = self.tangents(u, v, w);
         let uv = uv0 * u + uv1 * v + uv2 * w;
 
         let inter = Intersection {
             position: p,
+            p_error: ((p0 * u).abs() + (p1 * v).abs() + (p2 * w).abs()) * ray::gamma(6),
+            geo_normal: (p1 - p0).cross(p2 - p0).normalize(),
             normal: norm,
             tangent: tan,
             bitangent: bitan,
@@ -268,11 +433,142 @@ impl PrimitiveT for Triangle {
 
         (inter, 1.0 / area.max(0.001))
     }
+}
 
+impl PrimitiveT for TriMesh {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.bvh
+            .intersect_test(ray, t_max, |i| self.triangle(i).intersect_test(ray, t_max))
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        self.bvh.intersect(ray, inter.t, |i| {
+            if self.triangle(i).intersect_with(ray, inter) {
+                Some(inter.t)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.bvh.bbox()
+    }
+
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let count = self.triangle_count();
+        let index = rng.uniform_1d() * count as f32;
+        let index = (index as usize).min(count - 1);
+        let (inter, pdf) = self
+            .triangle(self.distinct_position(index))
+            .sample_with(rng);
+        (inter, pdf / count as f32)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        inter.primitive.unwrap().pdf(inter) / self.triangle_count() as f32
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        (0..self.triangle_count())
+            .map(|i| self.triangle(self.distinct_position(i)).surface_area(trans))
+            .sum()
+    }
+}
+
+impl Cacheable for MeshVertex {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        writer.write_vec3a(self.position);
+        writer.write_vec3a(self.normal);
+        writer.write_vec2(self.texcoords);
+        writer.write_vec3a(self.tangent);
+        writer.write_vec3a(self.bitangent);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        Ok(Self {
+            position: reader.read_vec3a()?,
+            normal: reader.read_vec3a()?,
+            texcoords: reader.read_vec2()?,
+            tangent: reader.read_vec3a()?,
+            bitangent: reader.read_vec3a()?,
+        })
+    }
+}
+
+impl Cacheable for TriMesh {
+    fn write_cache(&self, writer: &mut CacheWriter) {
+        match &self.vertices {
+            MeshVertices::Full(vertices) => {
+                writer.write_u32(0);
+                writer.write_slice(vertices);
+            }
+            MeshVertices::Compact(vertices) => {
+                writer.write_u32(1);
+                vertices.write_cache(writer);
+            }
+        }
+        writer.write_u32s(&self.triangles.concat());
+        writer.write_u32s(&self.distinct);
+        self.bvh.write_cache(writer);
+    }
+
+    fn read_cache(reader: &mut CacheReader<'_>) -> anyhow::Result<Self> {
+        let vertices = match reader.read_u32()? {
+            0 => MeshVertices::Full(reader.read_vec::<MeshVertex>()?),
+            1 => MeshVertices::Compact(CompactVertices::read_cache(reader)?),
+            _ => anyhow::bail!("invalid vertex format"),
+        };
+        let indices = reader.read_u32s()?;
+        if indices.len() % 3 != 0 || indices.iter().any(|i| *i as usize >= vertices.len()) {
+            anyhow::bail!("invalid triangle indices");
+        }
+        let triangles = Self::triangles_of(&indices);
+        let distinct = reader.read_u32s()?;
+        if distinct.iter().any(|pos| *pos as usize >= triangles.len()) {
+            anyhow::bail!("invalid distinct triangles");
+        }
+        let bvh = Bvh::read_cache(reader)?;
+        bvh.validate(triangles.len())?;
+        Ok(Self {
+            vertices,
+            triangles,
+            distinct,
+            bvh,
+            watertight: true,
+        })
+    }
+}
+
+impl<'t> PrimitiveT for Triangle<'t> {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        if let Some((t, _, _, _)) = self.intersect_ray(ray) {
+            t > ray.t_min && t < t_max
+        } else {
+            false
+        }
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        let triangle: Triangle<'a> = *self;
+        triangle.intersect_with(ray, inter)
+    }
+
+    fn bbox(&self) -> Bbox {
+        let p0 = self.position(0);
+        let p1 = self.position(1);
+        let p2 = self.position(2);
+        Bbox::from_points(&[p0, p1, p2])
+    }
+
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let triangle: Triangle<'a> = *self;
+        triangle.sample_with(rng)
+    }
     fn pdf(&self, _inter: &Intersection<'_>) -> f32 {
-        let p0 = self.vertices[self.indices[0]].position;
-        let p1 = self.vertices[self.indices[1]].position;
-        let p2 = self.vertices[self.indices[2]].position;
+        let p0 = self.position(0);
+        let p1 = self.position(1);
+        let p2 = self.position(2);
 
         let area = (p1 - p0).cross(p2 - p0).length() * 0.5;
 
@@ -280,14 +576,93 @@ impl PrimitiveT for Triangle {
     }
 
     fn surface_area(&self, trans: Transform) -> f32 {
-        let p0 = trans.transform_point3a(self.vertices[self.indices[0]].position);
-        let p1 = trans.transform_point3a(self.vertices[self.indices[1]].position);
-        let p2 = trans.transform_point3a(self.vertices[self.indices[2]].position);
+        let p0 = trans.transform_point3a(self.position(0));
+        let p1 = trans.transform_point3a(self.position(1));
+        let p2 = trans.transform_point3a(self.position(2));
 
         (p1 - p0).cross(p2 - p0).length() * 0.5
     }
 }
 
+/// watertight test by Woop et al. 2013, the ray is transformed so that it goes along +z
+/// and edge functions are evaluated in 2d, which are consistent for edges shared by triangles,
+/// `t` is rejected if it's not surely positive, with the error bound from pbrt,
+/// returns (t, u, v, w) like `Triangle::intersect_ray`
+pub(super) fn intersect_triangle_watertight(
+    positions: [glam::Vec3A; 3],
+    ray: &Ray,
+) -> Option<(f32, f32, f32, f32)> {
+    let dir = ray.direction;
+    let abs_dir = dir.abs();
+    let kz = if abs_dir.x > abs_dir.y && abs_dir.x > abs_dir.z {
+        0
+    } else if abs_dir.y > abs_dir.z {
+        1
+    } else {
+        2
+    };
+    let (kx, ky) = if dir[kz] < 0.0 {
+        ((kz + 2) % 3, (kz + 1) % 3)
+    } else {
+        ((kz + 1) % 3, (kz + 2) % 3)
+    };
+    let sx = -dir[kx] / dir[kz];
+    let sy = -dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    // vertices relative to ray origin, sheared in xy
+    let shear = |p: glam::Vec3A| {
+        let p = p - ray.origin;
+        glam::Vec3A::new(p[kx] + sx * p[kz], p[ky] + sy * p[kz], p[kz])
+    };
+    let a = shear(positions[0]);
+    let b = shear(positions[1]);
+    let c = shear(positions[2]);
+
+    let mut u = b.x * c.y - b.y * c.x;
+    let mut v = c.x * a.y - c.y * a.x;
+    let mut w = a.x * b.y - a.y * b.x;
+    // fall back to double precision if the ray goes exactly through an edge
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        u = (b.x as f64 * c.y as f64 - b.y as f64 * c.x as f64) as f32;
+        v = (c.x as f64 * a.y as f64 - c.y as f64 * a.x as f64) as f32;
+        w = (a.x as f64 * b.y as f64 - a.y as f64 * b.x as f64) as f32;
+    }
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let az = sz * a.z;
+    let bz = sz * b.z;
+    let cz = sz * c.z;
+    let t_scaled = u * az + v * bz + w * cz;
+    if (det < 0.0 && t_scaled >= 0.0) || (det > 0.0 && t_scaled <= 0.0) {
+        return None;
+    }
+    let det_inv = 1.0 / det;
+    let t = t_scaled * det_inv;
+
+    let max_z = az.abs().max(bz.abs()).max(cz.abs());
+    let max_x = a.x.abs().max(b.x.abs()).max(c.x.abs());
+    let max_y = a.y.abs().max(b.y.abs()).max(c.y.abs());
+    let delta_z = ray::gamma(3) * max_z;
+    let delta_x = ray::gamma(5) * (max_x + max_z);
+    let delta_y = ray::gamma(5) * (max_y + max_z);
+    let delta_e = 2.0 * (ray::gamma(2) * max_x * max_y + delta_y * max_x + delta_x * max_y);
+    let max_e = u.abs().max(v.abs()).max(w.abs());
+    let delta_t =
+        3.0 * (ray::gamma(3) * max_e * max_z + delta_e * max_z + delta_z * max_e) * det_inv.abs();
+    if t <= delta_t {
+        return None;
+    }
+
+    Some((t, u * det_inv, v * det_inv, w * det_inv))
+}
+
 fn lerp_point2(
     p0: glam::Vec2,
     p1: glam::Vec2,
@@ -351,23 +726,15 @@ impl TriMesh {
             let p0 = vertices[i0].position;
             let p1 = vertices[i1].position;
             let p2 = vertices[i2].position;
-            let e1 = p1 - p0;
-            let e2 = p2 - p0;
 
             let uv0 = vertices[i0].texcoords;
             let uv1 = vertices[i1].texcoords;
             let uv2 = vertices[i2].texcoords;
-            let u1 = uv1 - uv0;
-            let u2 = uv2 - uv0;
 
-            let det = u1.x * u2.y - u1.y * u2.x;
-            if det != 0.0 {
-                let det = 1.0 / det;
-                let t = ((e1 * u2.y - e2 * u1.y) * det).normalize();
+            if let Some((t, b)) = face_tangents([p0, p1, p2], [uv0, uv1, uv2]) {
                 tangents_sum[i0] += t;
                 tangents_sum[i1] += t;
                 tangents_sum[i2] += t;
-                let b = ((e2 * u1.x - e1 * u2.x) * det).normalize();
                 bitangents_sum[i0] += b;
                 bitangents_sum[i1] += b;
                 bitangents_sum[i2] += b;
@@ -387,3 +754,25 @@ impl TriMesh {
         }
     }
 }
+
+/// tangent and bitangent of a triangle following the direction of its texcoords,
+/// returns `None` if texcoords are degenerate
+fn face_tangents(
+    positions: [glam::Vec3A; 3],
+    texcoords: [glam::Vec2; 3],
+) -> Option<(glam::Vec3A, glam::Vec3A)> {
+    let e1 = positions[1] - positions[0];
+    let e2 = positions[2] - positions[0];
+    let u1 = texcoords[1] - texcoords[0];
+    let u2 = texcoords[2] - texcoords[0];
+
+    let det = u1.x * u2.y - u1.y * u2.x;
+    if det != 0.0 {
+        let det = 1.0 / det;
+        let t = ((e1 * u2.y - e2 * u1.y) * det).normalize();
+        let b = ((e2 * u1.x - e1 * u2.x) * det).normalize();
+        Some((t, b))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,331 @@
+use crate::core::{
+    bbox::Bbox, intersection::Intersection, ray::Ray, rng::Rng, transform::Transform,
+};
+
+use super::{Bvh, BvhAccel, BvhNode, PrimitiveT};
+
+/// bvh with 4 or 8 children per node, collapsed from the binary sah tree,
+/// children of a node are tested against the ray at once
+pub struct WideBvhAccel<P: PrimitiveT, N: WideBvhNode> {
+    nodes: Vec<N>,
+    primitives: Vec<P>,
+    bbox: Bbox,
+}
+
+pub type Bvh4Accel<P> = WideBvhAccel<P, Bvh4Node>;
+pub type Bvh8Accel<P> = WideBvhAccel<P, Bvh8Node>;
+
+pub trait WideBvhNode: Default + Send + Sync {
+    const WIDTH: usize;
+
+    fn set_child(&mut self, slot: usize, bbox: Bbox, offset: u32, count: u32);
+
+    fn len(&self) -> usize;
+
+    /// returns (offset, count) of a child, `count` is 0 for interior child
+    fn child(&self, slot: usize) -> (u32, u32);
+
+    /// writes the entering distance of each child to `t_enter`, `f32::INFINITY` if missed
+    fn intersect(&self, ray: &WideRay, t_max: f32, t_enter: &mut [f32]);
+}
+
+/// ray with each component of origin and inverse direction splatted
+pub struct WideRay {
+    origin: [glam::Vec4; 3],
+    inv_dir: [glam::Vec4; 3],
+    t_min: f32,
+}
+
+/// children bounds are stored per axis so that 4 slab tests are done with sse
+/// (glam falls back to scalar code on other targets)
+#[derive(Default)]
+#[repr(C, align(16))]
+pub struct Bvh4Node {
+    p_min: [glam::Vec4; 3],
+    p_max: [glam::Vec4; 3],
+    offset: [u32; 4],
+    count: [u32; 4],
+    len: u32,
+}
+
+/// 8 slab tests are done with avx if it's enabled at compile time (e.g. `-C target-cpu=native`),
+/// or as two halves with sse otherwise
+#[derive(Default)]
+#[repr(C, align(32))]
+pub struct Bvh8Node {
+    p_min: [[f32; 8]; 3],
+    p_max: [[f32; 8]; 3],
+    offset: [u32; 8],
+    count: [u32; 8],
+    len: u32,
+}
+
+impl<P: PrimitiveT, N: WideBvhNode> WideBvhAccel<P, N> {
+    pub fn new(primitives: Vec<P>, max_leaf_size: usize, bucket_number: usize) -> Self {
+        let (bvh, primitives) =
+            BvhAccel::new(primitives, max_leaf_size, bucket_number).into_parts();
+        let nodes = Self::collapse(&bvh);
+        Self {
+            nodes,
+            primitives,
+            bbox: bvh.bbox(),
+        }
+    }
+
+    fn collapse(bvh: &Bvh) -> Vec<N> {
+        let binary = bvh.nodes();
+        let mut nodes = vec![];
+        if !binary.is_empty() {
+            Self::collapse_node(binary, 0, &mut nodes);
+        }
+        nodes
+    }
+
+    /// pulls up grandchildren with the largest surface area until the node is full
+    fn collapse_node(binary: &[BvhNode], index: usize, nodes: &mut Vec<N>) -> u32 {
+        let mut children = if binary[index].count > 0 {
+            vec![index]
+        } else {
+            vec![index + 1, binary[index].offset as usize]
+        };
+        while children.len() < N::WIDTH {
+            let largest = children
+                .iter()
+                .enumerate()
+                .filter(|(_, &child)| binary[child].count == 0)
+                .map(|(slot, &child)| (slot, binary[child].bbox().surface_area()))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .map(|(slot, _)| slot);
+            if let Some(slot) = largest {
+                let child = children.swap_remove(slot);
+                children.push(child + 1);
+                children.push(binary[child].offset as usize);
+            } else {
+                break;
+            }
+        }
+
+        let node_index = nodes.len();
+        nodes.push(N::default());
+        for (slot, child) in children.into_iter().enumerate() {
+            let node = &binary[child];
+            let (offset, count) = if node.count > 0 {
+                (node.offset, node.count as u32)
+            } else {
+                (Self::collapse_node(binary, child, nodes), 0)
+            };
+            nodes[node_index].set_child(slot, node.bbox(), offset, count);
+        }
+        node_index as u32
+    }
+
+    fn traverse(
+        &self,
+        ray: &Ray,
+        mut t_max: f32,
+        any_hit: bool,
+        mut hit: impl FnMut(usize) -> Option<f32>,
+    ) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let wide_ray = WideRay::new(ray);
+        let mut t_enter = [0.0; 8];
+        let mut result = false;
+        // (entering distance, offset, count)
+        let mut stack = Vec::with_capacity(64);
+        stack.push((f32::NEG_INFINITY, 0, 0));
+        while let Some((t, offset, count)) = stack.pop() {
+            if t > t_max {
+                continue;
+            }
+            if count > 0 {
+                for i in offset as usize..(offset + count) as usize {
+                    if let Some(t) = hit(i) {
+                        if any_hit {
+                            return true;
+                        }
+                        t_max = t;
+                        result = true;
+                    }
+                }
+                continue;
+            }
+
+            let node = &self.nodes[offset as usize];
+            node.intersect(&wide_ray, t_max, &mut t_enter);
+            let start = stack.len();
+            for (slot, &t) in t_enter.iter().enumerate().take(node.len()) {
+                if t < f32::INFINITY {
+                    let (offset, count) = node.child(slot);
+                    stack.push((t, offset, count));
+                }
+            }
+            // nearest child is popped first
+            stack[start..].sort_unstable_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        }
+        result
+    }
+}
+
+impl WideRay {
+    fn new(ray: &Ray) -> Self {
+        let inv_dir = ray.direction.recip();
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let inv_dir = [inv_dir.x, inv_dir.y, inv_dir.z];
+        Self {
+            origin: origin.map(glam::Vec4::splat),
+            inv_dir: inv_dir.map(glam::Vec4::splat),
+            t_min: ray.t_min,
+        }
+    }
+
+    fn slab_test(&self, p_min: [glam::Vec4; 3], p_max: [glam::Vec4; 3], t_max: f32) -> glam::Vec4 {
+        let mut t_enter = glam::Vec4::splat(self.t_min);
+        let mut t_exit = glam::Vec4::splat(t_max);
+        for axis in 0..3 {
+            let t0 = (p_min[axis] - self.origin[axis]) * self.inv_dir[axis];
+            let t1 = (p_max[axis] - self.origin[axis]) * self.inv_dir[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        glam::Vec4::select(
+            t_enter.cmpgt(t_exit),
+            glam::Vec4::splat(f32::INFINITY),
+            t_enter,
+        )
+    }
+}
+
+impl WideBvhNode for Bvh4Node {
+    const WIDTH: usize = 4;
+
+    fn set_child(&mut self, slot: usize, bbox: Bbox, offset: u32, count: u32) {
+        for axis in 0..3 {
+            self.p_min[axis][slot] = bbox.p_min[axis];
+            self.p_max[axis][slot] = bbox.p_max[axis];
+        }
+        self.offset[slot] = offset;
+        self.count[slot] = count;
+        self.len = self.len.max(slot as u32 + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn child(&self, slot: usize) -> (u32, u32) {
+        (self.offset[slot], self.count[slot])
+    }
+
+    fn intersect(&self, ray: &WideRay, t_max: f32, t_enter: &mut [f32]) {
+        let t = ray.slab_test(self.p_min, self.p_max, t_max);
+        t_enter[..4].copy_from_slice(&t.to_array());
+    }
+}
+
+impl WideBvhNode for Bvh8Node {
+    const WIDTH: usize = 8;
+
+    fn set_child(&mut self, slot: usize, bbox: Bbox, offset: u32, count: u32) {
+        for axis in 0..3 {
+            self.p_min[axis][slot] = bbox.p_min[axis];
+            self.p_max[axis][slot] = bbox.p_max[axis];
+        }
+        self.offset[slot] = offset;
+        self.count[slot] = count;
+        self.len = self.len.max(slot as u32 + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn child(&self, slot: usize) -> (u32, u32) {
+        (self.offset[slot], self.count[slot])
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+    fn intersect(&self, ray: &WideRay, t_max: f32, t_enter: &mut [f32]) {
+        use std::arch::x86_64::*;
+
+        // safety: avx is enabled at compile time and the node is 32-byte aligned
+        unsafe {
+            let mut enter = _mm256_set1_ps(ray.t_min);
+            let mut exit = _mm256_set1_ps(t_max);
+            for axis in 0..3 {
+                let origin = _mm256_set1_ps(ray.origin[axis].x);
+                let inv_dir = _mm256_set1_ps(ray.inv_dir[axis].x);
+                let p_min = _mm256_load_ps(self.p_min[axis].as_ptr());
+                let p_max = _mm256_load_ps(self.p_max[axis].as_ptr());
+                let t0 = _mm256_mul_ps(_mm256_sub_ps(p_min, origin), inv_dir);
+                let t1 = _mm256_mul_ps(_mm256_sub_ps(p_max, origin), inv_dir);
+                enter = _mm256_max_ps(enter, _mm256_min_ps(t0, t1));
+                exit = _mm256_min_ps(exit, _mm256_max_ps(t0, t1));
+            }
+            let miss = _mm256_cmp_ps::<_CMP_GT_OQ>(enter, exit);
+            let t = _mm256_blendv_ps(enter, _mm256_set1_ps(f32::INFINITY), miss);
+            _mm256_storeu_ps(t_enter.as_mut_ptr(), t);
+        }
+    }
+
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "avx")))]
+    fn intersect(&self, ray: &WideRay, t_max: f32, t_enter: &mut [f32]) {
+        for half in 0..2 {
+            let range = half * 4..half * 4 + 4;
+            let p_min = self
+                .p_min
+                .map(|p| glam::Vec4::from_slice(&p[range.clone()]));
+            let p_max = self
+                .p_max
+                .map(|p| glam::Vec4::from_slice(&p[range.clone()]));
+            let t = ray.slab_test(p_min, p_max, t_max);
+            t_enter[range].copy_from_slice(&t.to_array());
+        }
+    }
+}
+
+impl<P: PrimitiveT, N: WideBvhNode> PrimitiveT for WideBvhAccel<P, N> {
+    fn intersect_test(&self, ray: &Ray, t_max: f32) -> bool {
+        self.traverse(ray, t_max, true, |i| {
+            if self.primitives[i].intersect_test(ray, t_max) {
+                Some(t_max)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn intersect<'a>(&'a self, ray: &Ray, inter: &mut Intersection<'a>) -> bool {
+        self.traverse(ray, inter.t, false, |i| {
+            if self.primitives[i].intersect(ray, inter) {
+                Some(inter.t)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn bbox(&self) -> Bbox {
+        self.bbox
+    }
+
+    fn sample<'a>(&'a self, rng: &mut Rng) -> (Intersection<'a>, f32) {
+        let index = rng.uniform_1d() * self.primitives.len() as f32;
+        let index = (index as usize).min(self.primitives.len() - 1);
+        let (inter, pdf) = self.primitives[index].sample(rng);
+        (inter, pdf / self.primitives.len() as f32)
+    }
+
+    fn pdf(&self, inter: &Intersection<'_>) -> f32 {
+        inter.primitive.unwrap().pdf(inter) / self.primitives.len() as f32
+    }
+
+    fn surface_area(&self, trans: Transform) -> f32 {
+        self.primitives
+            .iter()
+            .map(|prim| prim.surface_area(trans))
+            .sum()
+    }
+}
@@ -78,7 +78,9 @@ impl PathTracer {
                     let atten = medium.transport_attenuation(transported_dist);
                     if pdf != 0.0
                         && pdf.is_finite()
-                        && !scene.aggregate().intersect_test(&shadow_ray, dist - 0.001)
+                        && !scene
+                            .aggregate()
+                            .intersect_test(&shadow_ray, dist * (1.0 - Ray::SHADOW_EPS))
                     {
                         if light_is_delta {
                             li = atten * phase * light_strength / pdf;
@@ -117,7 +119,9 @@ impl PathTracer {
                     break;
                 }
 
-                let mut po = ray.point_at(inter.t);
+                let mut po = inter.position;
+                let mut po_error = inter.p_error;
+                let mut po_geo_normal = inter.geo_normal;
                 let surf = inter.surface.unwrap();
                 let (bxdf_context, mut coord_po) = surf.scatter_and_coord(&ray, &inter);
 
@@ -139,13 +143,18 @@ impl PathTracer {
                 let wo = coord_po.to_local(-ray.direction);
                 let bxdf_inputs = BxdfInputs {
                     po,
+                    po_error,
+                    geo_normal_po: po_geo_normal,
                     coord_po,
                     wo,
-                    scene: scene.aggregate(),
+                    scene: Some(scene.aggregate()),
+                    instance: inter.instance,
                 };
                 let samp = bxdf_context.sample(&bxdf_inputs, rng);
                 if let Some(subsurface) = samp.subsurface {
                     po = subsurface.pi;
+                    po_error = subsurface.pi_error;
+                    po_geo_normal = subsurface.geo_normal_pi;
                     coord_po = subsurface.coord_pi;
                     throuput *= subsurface.sp / subsurface.pdf_pi;
                 }
@@ -162,11 +171,12 @@ impl PathTracer {
                     let wi = coord_po.to_local(light_dir);
                     let bxdf = bxdf_context.bxdf(wo, wi);
                     let mat_pdf = bxdf_context.pdf(wo, wi);
-                    let mut shadow_ray = Ray::new(po, light_dir);
-                    shadow_ray.t_min = Ray::T_MIN_EPS / wi.z.abs().max(0.00001);
+                    let shadow_ray = Ray::spawn(po, po_error, po_geo_normal, light_dir);
                     if pdf != 0.0
                         && pdf.is_finite()
-                        && !scene.aggregate().intersect_test(&shadow_ray, dist - 0.001)
+                        && !scene
+                            .aggregate()
+                            .intersect_test(&shadow_ray, dist * (1.0 - Ray::SHADOW_EPS))
                     {
                         if light_is_delta {
                             li = light_strength * bxdf * wi.z.abs() / pdf.max(0.00001);
@@ -180,8 +190,7 @@ impl PathTracer {
 
                 last_sample_pdf = samp.pdf;
                 let wi_world = coord_po.to_world(samp.wi);
-                ray = Ray::new(po, wi_world);
-                ray.t_min = Ray::T_MIN_EPS / samp.wi.z.abs().max(0.00001);
+                ray = Ray::spawn(po, po_error, po_geo_normal, wi_world);
                 throuput *= samp.bxdf * samp.wi.z.abs() / samp.pdf.max(0.00001);
                 if !coord_po.in_expected_hemisphere(wi_world, samp.ty.dir) {
                     break;
@@ -218,15 +227,16 @@ impl PathTracer {
     ) -> (Ray, f32) {
         let mut shadow_ray = Ray::new(p, light_dir);
 
-        let mut temp_inter = Intersection::with_t_max(light_dist - 0.001);
+        let shadow_t_max = light_dist * (1.0 - Ray::SHADOW_EPS);
+        let mut temp_inter = Intersection::with_t_max(shadow_t_max);
 
         let transported_dist;
         if medium_primitive.intersect(&shadow_ray, &mut temp_inter) {
             transported_dist = temp_inter.t;
-            shadow_ray.t_min += temp_inter.t;
+            shadow_ray.t_min = temp_inter.t_after(&shadow_ray);
         } else {
             transported_dist = light_dist;
-            shadow_ray.t_min += light_dist - 0.001;
+            shadow_ray.t_min = shadow_t_max;
         }
 
         (shadow_ray, transported_dist)